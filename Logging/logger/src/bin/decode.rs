@@ -0,0 +1,60 @@
+// Turns binary log files back into text, one line per record:
+//
+//   decode FILE...
+//   decode --dir DIRECTORY --prefix PREFIX
+//
+// With --dir every file written under the prefix is decoded, oldest first.
+use std::{
+    env,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+};
+use logger::record::{read_header, read_record};
+use logger::writer::log_files;
+
+// nanoseconds since the epoch as seconds.nanoseconds, UTC
+fn format_timestamp(timestamp: u64) -> String {
+    format!("{}.{:09}", timestamp / 1_000_000_000, timestamp % 1_000_000_000)
+}
+
+fn decode(path: &Path, out: &mut impl Write) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let schema = read_header(&mut reader)?;
+    while let Some(record) = read_record(&mut reader)? {
+        let text = match schema.iter().find(|event| event.id == record.event) {
+            Some(event) => event.format(&record),
+            None => format!("event{} {:?}", record.event, record.get_args()),
+        };
+        writeln!(out, "{} {}", format_timestamp(record.timestamp), text)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let files: Vec<PathBuf> = match args.as_slice() {
+        [flag, directory, prefix_flag, prefix] if flag == "--dir" && prefix_flag == "--prefix" => {
+            log_files(Path::new(directory), prefix).unwrap_or_else(|error| {
+                eprintln!("cannot list {}: {}", directory, error);
+                process::exit(1);
+            })
+        }
+        [] => {
+            eprintln!("usage: decode FILE... | decode --dir DIRECTORY --prefix PREFIX");
+            process::exit(2);
+        }
+        files => files.iter().map(PathBuf::from).collect(),
+    };
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for path in files {
+        if let Err(error) = decode(&path, &mut out) {
+            eprintln!("{}: {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+    out.flush().ok();
+}
@@ -0,0 +1,192 @@
+// Asynchronous binary logger. Threads on the hot path copy a fixed-size record
+// into a lock-free ring buffer and carry on; a background thread drains the
+// buffer into rotating binary files. The `decode` binary turns them back into text.
+pub mod record;
+pub mod ring;
+pub mod writer;
+
+use std::{
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+pub use record::{ArgKind, EventSpec, Record};
+use ring::RingBuffer;
+use writer::RotatingWriter;
+
+#[derive(Clone, Debug)]
+pub struct LoggerConfig {
+    pub directory: PathBuf,
+    pub file_prefix: String,
+    pub max_file_bytes: u64,
+    pub max_files: usize,
+    // records the ring buffer holds before new ones are dropped
+    pub capacity: usize,
+    // how long the writer sleeps when it finds the buffer empty
+    pub idle_wait: Duration,
+}
+
+impl LoggerConfig {
+    pub fn new(directory: impl Into<PathBuf>, file_prefix: &str) -> Self {
+        Self {
+            directory: directory.into(),
+            file_prefix: file_prefix.to_string(),
+            max_file_bytes: 64 * 1024 * 1024,
+            max_files: 8,
+            capacity: 1 << 16,
+            idle_wait: Duration::from_millis(1),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    ring: RingBuffer,
+    dropped: AtomicU64,
+    shutdown: AtomicBool,
+}
+
+// Cheap to share between threads behind an Arc; `log` never blocks or allocates.
+#[derive(Debug)]
+pub struct Logger {
+    shared: Arc<Shared>,
+    writer: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Logger {
+    pub fn start(config: LoggerConfig, schema: Vec<EventSpec>) -> io::Result<Self> {
+        let mut writer = RotatingWriter::new(&config.directory, &config.file_prefix, config.max_file_bytes, config.max_files, schema)?;
+        let shared = Arc::new(Shared {
+            ring: RingBuffer::new(config.capacity),
+            dropped: AtomicU64::new(0),
+            shutdown: AtomicBool::new(false),
+        });
+
+        let background = Arc::clone(&shared);
+        let handle = thread::Builder::new()
+            .name("logger".to_string())
+            .spawn(move || -> io::Result<()> {
+                loop {
+                    // read the flag first so nothing pushed before shutdown is missed
+                    let shutdown = background.shutdown.load(Ordering::Acquire);
+                    let mut wrote = false;
+                    while let Some(record) = background.ring.pop() {
+                        writer.write(&record)?;
+                        wrote = true;
+                    }
+                    if shutdown {
+                        return writer.flush();
+                    }
+                    if !wrote {
+                        writer.flush()?;
+                        thread::park_timeout(config.idle_wait);
+                    }
+                }
+            })?;
+
+        Ok(Self { shared, writer: Some(handle) })
+    }
+
+    // Stamps and queues a record. If the writer has fallen behind and the buffer
+    // is full the record is dropped and counted rather than waiting.
+    #[inline]
+    pub fn log(&self, event: u16, args: &[u64]) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        if self.shared.ring.push(Record::new(timestamp, event, args)).is_err() {
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    // Writes out everything queued so far and stops the writer thread.
+    pub fn shutdown(mut self) -> io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> io::Result<()> {
+        let Some(handle) = self.writer.take() else { return Ok(()) };
+        self.shared.shutdown.store(true, Ordering::Release);
+        handle.thread().unpark();
+        handle.join().map_err(|_| io::Error::other("logger thread panicked"))?
+    }
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        self.stop().ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+    use crate::record::{read_header, read_record};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("logger-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&directory).ok();
+        directory
+    }
+
+    #[test]
+    fn test_logs_rotate_and_decode(){
+        let directory = temp_dir("rotate");
+        let schema = vec![EventSpec::new(1, "trade", &[("order_id", ArgKind::Unsigned), ("price", ArgKind::Signed)])];
+        let mut config = LoggerConfig::new(&directory, "book");
+        // room for the header and ten records per file
+        config.max_file_bytes = 64 * 11;
+        config.max_files = 3;
+
+        let logger = Logger::start(config, schema.clone()).unwrap();
+        for order_id in 0..45 {
+            logger.log(1, &[order_id, (-(order_id as i64)) as u64]);
+        }
+        assert_eq!(logger.dropped(), 0);
+        logger.shutdown().unwrap();
+
+        // 45 records at ten a file is five files, of which the newest three are kept
+        let files = writer::log_files(&directory, "book").unwrap();
+        assert_eq!(files.len(), 3);
+        let mut lines = vec![];
+        for path in &files {
+            let mut reader = BufReader::new(File::open(path).unwrap());
+            let decoded = read_header(&mut reader).unwrap();
+            assert_eq!(decoded, schema);
+            while let Some(record) = read_record(&mut reader).unwrap() {
+                lines.push(decoded[0].format(&record));
+            }
+        }
+        assert_eq!(lines.len(), 25);
+        assert_eq!(lines[0], "trade order_id=20 price=-20");
+        assert_eq!(lines[24], "trade order_id=44 price=-44");
+        std::fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn test_full_buffer_drops_instead_of_blocking(){
+        let directory = temp_dir("drop");
+        let mut config = LoggerConfig::new(&directory, "book");
+        config.capacity = 4;
+        // the writer sleeps long enough for the buffer to fill
+        config.idle_wait = Duration::from_secs(5);
+
+        let logger = Logger::start(config, vec![]).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        for event in 0..100 {
+            logger.log(event, &[]);
+        }
+        assert!(logger.dropped() >= 96);
+        logger.shutdown().unwrap();
+        std::fs::remove_dir_all(&directory).ok();
+    }
+}
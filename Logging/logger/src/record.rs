@@ -0,0 +1,231 @@
+use std::io::{self, Read, Write};
+
+pub const MAX_ARGS: usize = 6;
+pub const RECORD_SIZE: usize = 64;
+
+// Identifies a log file and the layout of the records in it.
+pub const MAGIC: &[u8; 8] = b"HFTLOG01";
+
+// One fixed-size log entry. The hot path only fills this in; turning it into
+// text is left to the decoder.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Record {
+    // nanoseconds since the Unix epoch
+    pub timestamp: u64,
+    pub event: u16,
+    pub arg_count: u16,
+    pub args: [u64; MAX_ARGS],
+}
+
+impl Record {
+    // args past MAX_ARGS are dropped
+    pub fn new(timestamp: u64, event: u16, args: &[u64]) -> Self {
+        let arg_count = args.len().min(MAX_ARGS);
+        let mut record = Self { timestamp, event, arg_count: arg_count as u16, args: [0; MAX_ARGS] };
+        record.args[..arg_count].copy_from_slice(&args[..arg_count]);
+        record
+    }
+
+    pub fn get_args(&self) -> &[u64] {
+        &self.args[..self.arg_count as usize]
+    }
+
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[0..8].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.event.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.arg_count.to_le_bytes());
+        // 12..16 is padding so the args stay 8 byte aligned
+        for (index, arg) in self.args.iter().enumerate() {
+            let start = 16 + index * 8;
+            bytes[start..start + 8].copy_from_slice(&arg.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Self {
+        let word = |start: usize| u64::from_le_bytes(bytes[start..start + 8].try_into().unwrap());
+        let mut args = [0; MAX_ARGS];
+        for (index, arg) in args.iter_mut().enumerate() {
+            *arg = word(16 + index * 8);
+        }
+        Self {
+            timestamp: word(0),
+            event: u16::from_le_bytes([bytes[8], bytes[9]]),
+            arg_count: u16::from_le_bytes([bytes[10], bytes[11]]).min(MAX_ARGS as u16),
+            args,
+        }
+    }
+}
+
+// How the decoder should print an argument. Everything travels as a u64.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ArgKind {
+    Unsigned,
+    // an i64 cast with `as u64`
+    Signed,
+    // f64::to_bits
+    Float,
+}
+
+impl ArgKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            ArgKind::Unsigned => 0,
+            ArgKind::Signed => 1,
+            ArgKind::Float => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(ArgKind::Unsigned),
+            1 => Ok(ArgKind::Signed),
+            2 => Ok(ArgKind::Float),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown argument kind {}", byte))),
+        }
+    }
+
+    pub fn format(self, value: u64) -> String {
+        match self {
+            ArgKind::Unsigned => value.to_string(),
+            ArgKind::Signed => (value as i64).to_string(),
+            ArgKind::Float => f64::from_bits(value).to_string(),
+        }
+    }
+}
+
+// Names an event id and its arguments. The schema is written at the top of
+// every log file so the decoder needs nothing but the file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EventSpec {
+    pub id: u16,
+    pub name: String,
+    pub args: Vec<(String, ArgKind)>,
+}
+
+impl EventSpec {
+    pub fn new(id: u16, name: &str, args: &[(&str, ArgKind)]) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            args: args.iter().map(|(name, kind)| (name.to_string(), *kind)).collect(),
+        }
+    }
+
+    // "trade bid_id=1 ask_id=2 price=100 quantity=10"
+    pub fn format(&self, record: &Record) -> String {
+        let mut line = self.name.clone();
+        for (index, value) in record.get_args().iter().enumerate() {
+            match self.args.get(index) {
+                Some((name, kind)) => line.push_str(&format!(" {}={}", name, kind.format(*value))),
+                None => line.push_str(&format!(" arg{}={}", index, value)),
+            }
+        }
+        line
+    }
+}
+
+fn write_name(writer: &mut impl Write, name: &str) -> io::Result<()> {
+    let bytes = name.as_bytes();
+    let length = u8::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("name too long: {}", name)))?;
+    writer.write_all(&[length])?;
+    writer.write_all(bytes)
+}
+
+fn read_name(reader: &mut impl Read) -> io::Result<String> {
+    let mut length = [0; 1];
+    reader.read_exact(&mut length)?;
+    let mut bytes = vec![0; length[0] as usize];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+// Header layout: MAGIC, u16 event count, then per event its id, name and
+// (kind, name) per argument. Names are length prefixed, lengths are one byte.
+pub fn write_header(writer: &mut impl Write, schema: &[EventSpec]) -> io::Result<u64> {
+    let mut header = vec![];
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&(schema.len() as u16).to_le_bytes());
+    for event in schema {
+        header.extend_from_slice(&event.id.to_le_bytes());
+        write_name(&mut header, &event.name)?;
+        header.push(event.args.len() as u8);
+        for (name, kind) in &event.args {
+            header.push(kind.to_byte());
+            write_name(&mut header, name)?;
+        }
+    }
+    writer.write_all(&header)?;
+    Ok(header.len() as u64)
+}
+
+pub fn read_header(reader: &mut impl Read) -> io::Result<Vec<EventSpec>> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a binary log file"));
+    }
+    let mut count = [0; 2];
+    reader.read_exact(&mut count)?;
+
+    let mut schema = vec![];
+    for _ in 0..u16::from_le_bytes(count) {
+        let mut id = [0; 2];
+        reader.read_exact(&mut id)?;
+        let name = read_name(reader)?;
+        let mut arg_count = [0; 1];
+        reader.read_exact(&mut arg_count)?;
+        let mut args = vec![];
+        for _ in 0..arg_count[0] {
+            let mut kind = [0; 1];
+            reader.read_exact(&mut kind)?;
+            args.push((read_name(reader)?, ArgKind::from_byte(kind[0])?));
+        }
+        schema.push(EventSpec { id: u16::from_le_bytes(id), name, args });
+    }
+    Ok(schema)
+}
+
+// Reads the next record, or None at a clean end of file. A record cut short
+// (the writer died mid-write) is treated as the end.
+pub fn read_record(reader: &mut impl Read) -> io::Result<Option<Record>> {
+    let mut bytes = [0; RECORD_SIZE];
+    let mut filled = 0;
+    while filled < RECORD_SIZE {
+        match reader.read(&mut bytes[filled..])? {
+            0 => return Ok(None),
+            read => filled += read,
+        }
+    }
+    Ok(Some(Record::from_bytes(&bytes)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_and_header_round_trip(){
+        let record = Record::new(1_700_000_000_123_456_789, 3, &[7, (-5i64) as u64, 2.5f64.to_bits()]);
+        assert_eq!(Record::from_bytes(&record.to_bytes()), record);
+
+        let schema = vec![
+            EventSpec::new(3, "trade", &[("order_id", ArgKind::Unsigned), ("price", ArgKind::Signed), ("fee", ArgKind::Float)]),
+            EventSpec::new(4, "cancel", &[("order_id", ArgKind::Unsigned)]),
+        ];
+        let mut file = vec![];
+        write_header(&mut file, &schema).unwrap();
+        file.extend_from_slice(&record.to_bytes());
+        file.extend_from_slice(&record.to_bytes()[..10]);
+
+        let mut reader = &file[..];
+        let decoded = read_header(&mut reader).unwrap();
+        assert_eq!(decoded, schema);
+        let read = read_record(&mut reader).unwrap().unwrap();
+        assert_eq!(decoded[0].format(&read), "trade order_id=7 price=-5 fee=2.5");
+        // the torn record at the end is ignored
+        assert_eq!(read_record(&mut reader).unwrap(), None);
+    }
+}
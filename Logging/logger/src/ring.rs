@@ -0,0 +1,152 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use crate::record::Record;
+
+// keeps the producer and consumer positions on separate cache lines
+#[repr(align(64))]
+struct Position(AtomicUsize);
+
+struct Slot {
+    // whose turn the slot is: `position` when free for the producer claiming that
+    // position, `position + 1` once written and ready for the consumer
+    sequence: AtomicUsize,
+    record: UnsafeCell<Record>,
+}
+
+// Bounded lock-free queue of records (Vyukov's array queue). Any number of
+// threads can push; the logger's writer thread is the only one that pops.
+// A push never blocks: when the buffer is full the record is handed back.
+pub struct RingBuffer {
+    slots: Box<[Slot]>,
+    mask: usize,
+    enqueue: Position,
+    dequeue: Position,
+}
+
+// Slots are only touched by the thread that won them through `sequence`.
+unsafe impl Sync for RingBuffer {}
+unsafe impl Send for RingBuffer {}
+
+impl RingBuffer {
+    // capacity is rounded up to a power of two
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|position| Slot { sequence: AtomicUsize::new(position), record: UnsafeCell::new(Record::default()) })
+            .collect();
+        Self {
+            slots,
+            mask: capacity - 1,
+            enqueue: Position(AtomicUsize::new(0)),
+            dequeue: Position(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn push(&self, record: Record) -> Result<(), Record> {
+        let mut position = self.enqueue.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match sequence.wrapping_sub(position) as isize {
+                0 => match self.enqueue.0.compare_exchange_weak(position, position + 1, Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        unsafe { *slot.record.get() = record };
+                        slot.sequence.store(position + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => position = current,
+                },
+                // the consumer hasn't freed this slot from the last lap yet
+                lag if lag < 0 => return Err(record),
+                _ => position = self.enqueue.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<Record> {
+        let mut position = self.dequeue.0.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match sequence.wrapping_sub(position + 1) as isize {
+                0 => match self.dequeue.0.compare_exchange_weak(position, position + 1, Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        let record = unsafe { *slot.record.get() };
+                        slot.sequence.store(position + self.mask + 1, Ordering::Release);
+                        return Some(record);
+                    }
+                    Err(current) => position = current,
+                },
+                // nothing written here yet
+                lag if lag < 0 => return None,
+                _ => position = self.dequeue.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for RingBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RingBuffer {{ capacity: {} }}", self.capacity())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn test_full_buffer_hands_record_back(){
+        let ring = RingBuffer::new(3);
+        assert_eq!(ring.capacity(), 4);
+        for event in 0..4 {
+            ring.push(Record::new(0, event, &[])).unwrap();
+        }
+        assert_eq!(ring.push(Record::new(0, 9, &[])).unwrap_err().event, 9);
+        assert_eq!(ring.pop().unwrap().event, 0);
+        ring.push(Record::new(0, 4, &[])).unwrap();
+        let events: Vec<u16> = std::iter::from_fn(|| ring.pop()).map(|record| record.event).collect();
+        assert_eq!(events, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_concurrent_producers(){
+        let ring = Arc::new(RingBuffer::new(1024));
+        let producers: Vec<_> = (0..4u64)
+            .map(|producer| {
+                let ring = Arc::clone(&ring);
+                thread::spawn(move || {
+                    for sequence in 0..10_000u64 {
+                        let record = Record::new(sequence, producer as u16, &[sequence]);
+                        while ring.push(record).is_err() {
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        // each producer's records come out complete and in the order it pushed them
+        let mut next = [0u64; 4];
+        while next.iter().any(|sequence| *sequence < 10_000) {
+            if let Some(record) = ring.pop() {
+                let producer = record.event as usize;
+                assert_eq!(record.timestamp, next[producer]);
+                assert_eq!(record.get_args(), &[next[producer]]);
+                next[producer] += 1;
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert!(ring.pop().is_none());
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use crate::record::{write_header, EventSpec, Record, RECORD_SIZE};
+
+// Writes records to `{prefix}.{index}.bin` in `directory`, starting a new file
+// once the current one passes `max_file_bytes` and keeping the newest `max_files`.
+pub struct RotatingWriter {
+    directory: PathBuf,
+    prefix: String,
+    max_file_bytes: u64,
+    max_files: usize,
+    schema: Vec<EventSpec>,
+    index: u64,
+    written: u64,
+    file: BufWriter<File>,
+}
+
+impl RotatingWriter {
+    pub fn new(directory: &Path, prefix: &str, max_file_bytes: u64, max_files: usize, schema: Vec<EventSpec>) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        // carry on after any files left by an earlier run
+        let index = existing_indexes(directory, prefix)?.into_iter().max().map_or(0, |index| index + 1);
+        let (file, written) = open(directory, prefix, index, &schema)?;
+        let writer = Self {
+            directory: directory.to_path_buf(),
+            prefix: prefix.to_string(),
+            max_file_bytes,
+            max_files: max_files.max(1),
+            schema,
+            index,
+            written,
+            file,
+        };
+        writer.remove_old_files()?;
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        if self.written + RECORD_SIZE as u64 > self.max_file_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(&record.to_bytes())?;
+        self.written += RECORD_SIZE as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    pub fn current_path(&self) -> PathBuf {
+        file_path(&self.directory, &self.prefix, self.index)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.index += 1;
+        let (file, written) = open(&self.directory, &self.prefix, self.index, &self.schema)?;
+        self.file = file;
+        self.written = written;
+        self.remove_old_files()
+    }
+
+    fn remove_old_files(&self) -> io::Result<()> {
+        for index in existing_indexes(&self.directory, &self.prefix)? {
+            if index + (self.max_files as u64) <= self.index {
+                fs::remove_file(file_path(&self.directory, &self.prefix, index))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn file_path(directory: &Path, prefix: &str, index: u64) -> PathBuf {
+    directory.join(format!("{}.{:06}.bin", prefix, index))
+}
+
+fn open(directory: &Path, prefix: &str, index: u64, schema: &[EventSpec]) -> io::Result<(BufWriter<File>, u64)> {
+    let mut file = BufWriter::new(File::create(file_path(directory, prefix, index))?);
+    let written = write_header(&mut file, schema)?;
+    Ok((file, written))
+}
+
+fn existing_indexes(directory: &Path, prefix: &str) -> io::Result<Vec<u64>> {
+    let mut indexes = vec![];
+    for entry in fs::read_dir(directory)? {
+        let name = entry?.file_name();
+        let index = name.to_str()
+            .and_then(|name| name.strip_prefix(prefix))
+            .and_then(|rest| rest.strip_prefix('.'))
+            .and_then(|rest| rest.strip_suffix(".bin"))
+            .and_then(|index| index.parse::<u64>().ok());
+        indexes.extend(index);
+    }
+    Ok(indexes)
+}
+
+// log files in `directory` written with `prefix`, oldest first
+pub fn log_files(directory: &Path, prefix: &str) -> io::Result<Vec<PathBuf>> {
+    let mut indexes = existing_indexes(directory, prefix)?;
+    indexes.sort();
+    Ok(indexes.into_iter().map(|index| file_path(directory, prefix, index)).collect())
+}
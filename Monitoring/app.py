@@ -1,9 +1,21 @@
+import asyncio
+import json
 import logging
+import os
 from datetime import datetime
-from nicegui import ui
+from pathlib import Path
+from nicegui import background_tasks, ui
 import random
 from scripts import mock_data, leos_func
 
+# price_update bars come from the engine: the strategy crate's `bars` binary replays
+# BARS_CAPTURE (a .csv or .bin capture) through a book and prints every closed bar
+BARS_CAPTURE = os.environ.get('BARS_CAPTURE')
+BARS_SYMBOL = os.environ.get('BARS_SYMBOL', 'SPY')
+BARS_INTERVAL = os.environ.get('BARS_INTERVAL', '1s')
+BARS_SPEED = os.environ.get('BARS_SPEED', '1')
+STRATEGY_MANIFEST = Path(__file__).resolve().parent.parent / 'Strategy' / 'strategy' / 'Cargo.toml'
+
 logger = logging.getLogger()
 
 class LogElementHandler(logging.Handler):
@@ -20,6 +32,27 @@ class LogElementHandler(logging.Handler):
         except Exception:
             self.handleError(record)
 
+async def stream_bars(client, update_log: ui.log) -> None:
+    """Pushes each bar the engine closes onto the price_update panel."""
+    if not BARS_CAPTURE:
+        update_log.push('set BARS_CAPTURE to a market data capture to stream bars')
+        return
+    process = await asyncio.create_subprocess_exec(
+        'cargo', 'run', '--release', '--quiet', '--manifest-path', str(STRATEGY_MANIFEST), '--bin', 'bars', '--',
+        BARS_CAPTURE, BARS_SYMBOL, BARS_INTERVAL, BARS_SPEED,
+        stdout=asyncio.subprocess.PIPE,
+    )
+
+    def stop() -> None:
+        if process.returncode is None:
+            process.kill()
+
+    client.on_disconnect(stop)
+    async for line in process.stdout:
+        update_log.push(json.loads(line))
+    if await process.wait() != 0:
+        update_log.push(f'bars exited with status {process.returncode}')
+
 @ui.page('/')
 def page():
     update_log = ui.log().classes('w-full h-80')
@@ -54,6 +87,8 @@ def page():
     # ui.context.client.on_disconnect(lambda: logger.removeHandler(match_hdl))
     # ui.context.client.on_disconnect(lambda: logger.removeHandler(sys_hdl))
 
-    ui.timer(random.randint(1, 2), lambda: leos_func(random.randint(1, 5), update_log=update_log, matches_log=match_log, systems_log=sys_log))
+    background_tasks.create(stream_bars(ui.context.client, update_log))
+    # matches and system logs are still mock data
+    ui.timer(random.randint(1, 2), lambda: leos_func(random.randint(1, 5), update_log=None, matches_log=match_log, systems_log=sys_log))
 
 ui.run()
@@ -26,7 +26,7 @@ async def leos_func(num_of_logs, update_log, matches_log, systems_log):
             systems_log.push(random_log_event)
         elif random_log_event["type"] == "match":
             matches_log.push(random_log_event)
-        else:
+        elif update_log is not None:
             update_log.push(random_log_event)
 
     
@@ -1,29 +1,47 @@
-use tokio::{
-    net::TcpStream, 
-    io::{AsyncReadExt, AsyncWriteExt}, 
-    time::sleep, time::Duration
+use tokio::time::Duration;
+use fix_ptc::{
+    fix::{fix_client, MessageBuilder, Version},
+    session::SessionConfig,
 };
 
+// News (B): Headline, and LinesOfText with one line
+fn news(count: u32) -> MessageBuilder {
+    MessageBuilder::new(Version::Fix44, "B")
+        .field(148, format!("update {}", count))
+        .group(33, 1)
+        .field(58, "Client is alive")
+}
+
 #[tokio::main]
 async fn main() {
-    let mut stream = TcpStream::connect("127.0.0.1:7000").await.unwrap();
-    let duration: u32 = 7;
-    let send_at = duration - 1; //need to send before not at timeout
-    stream.write_all(&duration.to_be_bytes()).await;
-    
-    loop {
-        stream.write_all(b"HB").await.unwrap();
+    let config = SessionConfig {
+        version: Version::Fix44,
+        sender_comp_id: "CLIENT".to_string(),
+        target_comp_id: "SERVER".to_string(),
+        heartbeat_interval: 7,
+    };
+    let mut client = fix_client::new("127.0.0.1:7000", config);
+    if let Err(error) = client.connect().await {
+        println!("Logon failed: {}", error);
+        return;
+    }
+    println!("Logged on");
 
-        let mut response = [0u8; 2];
-        match stream.read_exact(&mut response).await {
-            Ok(_) if &response == b"OK" => println!("Server is alive"),
-            _ => {
-                println!("No response. Server might be down.");
-                break;
+    for count in 1..=3 {
+        if let Err(error) = client.send(news(count)).await {
+            println!("Send failed: {}", error);
+            return;
+        }
+        // long enough for a heartbeat to go each way
+        match client.recv(Duration::from_secs(10)).await {
+            Ok(_) => println!("Server is alive"),
+            Err(error) => {
+                println!("Session ended: {}", error);
+                return;
             }
         }
-
-        sleep(Duration::from_secs(send_at.into())).await; //11
     }
 
+    client.disconnect().await.unwrap();
+    println!("Logged out");
 }
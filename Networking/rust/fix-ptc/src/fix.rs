@@ -1,52 +1,840 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Write},
+    str::{self, FromStr},
+    time::{Duration, Instant},
+};
 use tokio::{
-    net::TcpStream, 
-    io::{AsyncReadExt, AsyncWriteExt}, 
-    time::sleep, time::Duration
+    net::{TcpListener, TcpStream},
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::timeout,
 };
+use crate::session::{self, Action, Role, Session, SessionConfig};
+
+// Field delimiter
+pub const SOH: u8 = 0x01;
+
+// Bodies longer than this are refused rather than waited for
+pub const MAX_BODY_LENGTH: usize = 1 << 20;
+
+// "8=" and "9=" must be terminated within this many bytes
+const MAX_HEADER_FIELD: usize = 32;
+
+pub type Tag = u32;
+
+pub mod tag {
+    use super::Tag;
+
+    pub const BEGIN_STRING: Tag = 8;
+    pub const BODY_LENGTH: Tag = 9;
+    pub const CHECK_SUM: Tag = 10;
+    pub const MSG_SEQ_NUM: Tag = 34;
+    pub const MSG_TYPE: Tag = 35;
+    pub const SENDER_COMP_ID: Tag = 49;
+    pub const SENDING_TIME: Tag = 52;
+    pub const TARGET_COMP_ID: Tag = 56;
+    pub const TEXT: Tag = 58;
+
+    // session level
+    pub const BEGIN_SEQ_NO: Tag = 7;
+    pub const END_SEQ_NO: Tag = 16;
+    pub const NEW_SEQ_NO: Tag = 36;
+    pub const POSS_DUP_FLAG: Tag = 43;
+    pub const REF_SEQ_NUM: Tag = 45;
+    pub const ENCRYPT_METHOD: Tag = 98;
+    pub const HEART_BT_INT: Tag = 108;
+    pub const TEST_REQ_ID: Tag = 112;
+    pub const ORIG_SENDING_TIME: Tag = 122;
+    pub const GAP_FILL_FLAG: Tag = 123;
+    pub const RESET_SEQ_NUM_FLAG: Tag = 141;
+    pub const REF_TAG_ID: Tag = 371;
+    pub const REF_MSG_TYPE: Tag = 372;
+    pub const SESSION_REJECT_REASON: Tag = 373;
+
+    // length fields and the data fields they size, which may hold SOH
+    pub const DATA_FIELDS: [(Tag, Tag); 4] = [(90, 91), (93, 89), (95, 96), (212, 213)];
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Version {
+    Fix42,
+    Fix44,
+}
+
+impl Version {
+    pub const fn begin_string(&self) -> &'static str {
+        match self {
+            Version::Fix42 => "FIX.4.2",
+            Version::Fix44 => "FIX.4.4",
+        }
+    }
+
+    pub fn from_begin_string(value: &[u8]) -> Option<Self> {
+        match value {
+            b"FIX.4.2" => Some(Version::Fix42),
+            b"FIX.4.4" => Some(Version::Fix44),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FixError {
+    // not a whole message yet; read more and try again
+    Incomplete,
+    // no '=', a tag that isn't a number, or an empty value
+    MalformedField { offset: usize },
+    // BeginString isn't FIX.4.2 or FIX.4.4
+    UnsupportedVersion,
+    // a header or trailer field missing from its place (8, 9 and 35 first, 10 last) or found somewhere else
+    FieldOrder { tag: Tag, offset: usize },
+    // the body doesn't end where BodyLength says it does
+    BodyLength { declared: usize },
+    CheckSum { declared: u8, computed: u8 },
+    MissingField(Tag),
+    InvalidValue(Tag),
+    GroupCount { tag: Tag, declared: usize, found: usize },
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixError::Incomplete => write!(f, "incomplete message"),
+            FixError::MalformedField { offset } => write!(f, "malformed field at byte {}", offset),
+            FixError::UnsupportedVersion => write!(f, "unsupported BeginString"),
+            FixError::FieldOrder { tag, offset } => write!(f, "tag {} out of place at byte {}", tag, offset),
+            FixError::BodyLength { declared } => write!(f, "body doesn't match BodyLength {}", declared),
+            FixError::CheckSum { declared, computed } => write!(f, "CheckSum {:03} but computed {:03}", declared, computed),
+            FixError::MissingField(tag) => write!(f, "missing tag {}", tag),
+            FixError::InvalidValue(tag) => write!(f, "invalid value for tag {}", tag),
+            FixError::GroupCount { tag, declared, found } => write!(f, "group {} declares {} entries but has {}", tag, declared, found),
+        }
+    }
+}
+
+impl std::error::Error for FixError {}
+
+// One tag=value pair. The value borrows from the buffer the message was parsed from.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Field<'a> {
+    pub tag: Tag,
+    pub value: &'a [u8],
+}
+
+impl<'a> Field<'a> {
+    pub fn as_str(&self) -> Result<&'a str, FixError> {
+        str::from_utf8(self.value).map_err(|_| FixError::InvalidValue(self.tag))
+    }
+
+    pub fn parse<T: FromStr>(&self) -> Result<T, FixError> {
+        self.as_str()?.parse().map_err(|_| FixError::InvalidValue(self.tag))
+    }
+
+    // FIX Boolean: Y or N
+    pub fn as_bool(&self) -> Result<bool, FixError> {
+        match self.value {
+            b"Y" => Ok(true),
+            b"N" => Ok(false),
+            _ => Err(FixError::InvalidValue(self.tag)),
+        }
+    }
+}
+
+// Digits only, no sign or leading zero, and never zero.
+fn parse_tag(bytes: &[u8]) -> Option<Tag> {
+    if bytes.is_empty() || bytes[0] == b'0' || !bytes.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+// The field starting at `pos`, and where the next one starts. None when its SOH
+// hasn't arrived yet. `data_length` is how long the value is when it's a data
+// field, which can hold SOH itself.
+fn next_field(buf: &[u8], pos: usize, data_length: Option<usize>) -> Result<Option<(Field<'_>, usize)>, FixError> {
+    let rest = &buf[pos..];
+    let Some(equals) = rest.iter().position(|&byte| byte == b'=' || byte == SOH) else { return Ok(None) };
+    if rest[equals] != b'=' {
+        return Err(FixError::MalformedField { offset: pos });
+    }
+    let tag = parse_tag(&rest[..equals]).ok_or(FixError::MalformedField { offset: pos })?;
+    let value_start = equals + 1;
+    let value_end = match data_length {
+        Some(length) => {
+            let end = value_start.checked_add(length).ok_or(FixError::InvalidValue(tag))?;
+            if rest.len() <= end {
+                return Ok(None);
+            }
+            if rest[end] != SOH {
+                return Err(FixError::InvalidValue(tag));
+            }
+            end
+        }
+        None => match rest[value_start..].iter().position(|&byte| byte == SOH) {
+            Some(length) => value_start + length,
+            None => return Ok(None),
+        },
+    };
+    if value_end == value_start {
+        return Err(FixError::MalformedField { offset: pos });
+    }
+    Ok(Some((Field { tag, value: &rest[value_start..value_end] }, pos + value_end + 1)))
+}
+
+fn header_field(buf: &[u8], pos: usize, tag: Tag) -> Result<(Field<'_>, usize), FixError> {
+    match next_field(buf, pos, None) {
+        Ok(Some((field, next))) if field.tag == tag => Ok((field, next)),
+        Ok(Some(_)) => Err(FixError::FieldOrder { tag, offset: pos }),
+        // an unterminated field is only worth waiting for while it could still be this one
+        Ok(None) | Err(FixError::MalformedField { .. }) => {
+            let expected = format!("{}=", tag);
+            let seen = &buf[pos..];
+            let prefix = seen.len().min(expected.len());
+            if seen[..prefix] != expected.as_bytes()[..prefix] {
+                Err(FixError::FieldOrder { tag, offset: pos })
+            } else if seen.len() > MAX_HEADER_FIELD || seen.contains(&SOH) {
+                Err(FixError::MalformedField { offset: pos })
+            } else {
+                Err(FixError::Incomplete)
+            }
+        }
+        Err(error) => Err(error),
+    }
+}
+
+pub fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+// A run of fields: a message body, or one entry of a repeating group.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Group<'m, 'a> {
+    fields: &'m [Field<'a>],
+}
+
+impl<'m, 'a> Group<'m, 'a> {
+    pub const fn fields(&self) -> &'m [Field<'a>] {
+        self.fields
+    }
+
+    // the first occurrence
+    pub fn get(&self, tag: Tag) -> Option<&'m Field<'a>> {
+        self.fields.iter().find(|field| field.tag == tag)
+    }
+
+    pub fn get_str(&self, tag: Tag) -> Result<&'a str, FixError> {
+        self.get(tag).ok_or(FixError::MissingField(tag))?.as_str()
+    }
+
+    pub fn get_parsed<T: FromStr>(&self, tag: Tag) -> Result<T, FixError> {
+        self.get(tag).ok_or(FixError::MissingField(tag))?.parse()
+    }
+
+    // Entries of the group counted by `count_tag`. `members` are the tags an
+    // entry can hold, delimiter first, including those of any nested groups.
+    // A missing count tag is an empty group.
+    pub fn group(&self, count_tag: Tag, members: &[Tag]) -> Result<Vec<Group<'m, 'a>>, FixError> {
+        let Some(index) = self.fields.iter().position(|field| field.tag == count_tag) else { return Ok(vec![]) };
+        let declared: usize = self.fields[index].parse()?;
+        let mut entries = vec![];
+        let mut pos = index + 1;
+        if let Some(&delimiter) = members.first() {
+            while pos < self.fields.len() && self.fields[pos].tag == delimiter {
+                let start = pos;
+                pos += 1;
+                while pos < self.fields.len() && self.fields[pos].tag != delimiter && members.contains(&self.fields[pos].tag) {
+                    pos += 1;
+                }
+                entries.push(Group { fields: &self.fields[start..pos] });
+            }
+        }
+        if entries.len() != declared {
+            return Err(FixError::GroupCount { tag: count_tag, declared, found: entries.len() });
+        }
+        Ok(entries)
+    }
+}
+
+// A parsed message. Nothing is copied out of the buffer; fields point into it.
+#[derive(Clone, Debug)]
+pub struct Message<'a> {
+    bytes: &'a [u8],
+    version: Version,
+    // all of them, BeginString to CheckSum
+    fields: Vec<Field<'a>>,
+}
+
+impl<'a> Message<'a> {
+    // Parses the message at the front of `buf`, returning it and how many bytes
+    // it took. Incomplete means the rest of it hasn't arrived yet.
+    pub fn parse(buf: &'a [u8]) -> Result<(Self, usize), FixError> {
+        let (begin_string, pos) = header_field(buf, 0, tag::BEGIN_STRING)?;
+        let version = Version::from_begin_string(begin_string.value).ok_or(FixError::UnsupportedVersion)?;
+        let (body_length, body_start) = header_field(buf, pos, tag::BODY_LENGTH)?;
+        let declared: usize = body_length.parse()?;
+        if declared > MAX_BODY_LENGTH || !body_length.value.iter().all(u8::is_ascii_digit) {
+            return Err(FixError::InvalidValue(tag::BODY_LENGTH));
+        }
+
+        // 10=NNN<SOH> straight after the body
+        let body_end = body_start + declared;
+        let end = body_end + 7;
+        if buf.len() < end {
+            return Err(FixError::Incomplete);
+        }
+        if declared == 0 || buf[body_end - 1] != SOH || &buf[body_end..body_end + 3] != b"10=" || buf[end - 1] != SOH {
+            return Err(FixError::BodyLength { declared });
+        }
+        let checksum_field = Field { tag: tag::CHECK_SUM, value: &buf[body_end + 3..end - 1] };
+        if !checksum_field.value.iter().all(u8::is_ascii_digit) {
+            return Err(FixError::InvalidValue(tag::CHECK_SUM));
+        }
+        let declared_checksum: u8 = checksum_field.parse()?;
+        let computed = checksum(&buf[..body_end]);
+        if declared_checksum != computed {
+            return Err(FixError::CheckSum { declared: declared_checksum, computed });
+        }
+
+        let mut fields = vec![begin_string, body_length];
+        let body = &buf[..body_end];
+        let mut pos = body_start;
+        let mut data_length = None;
+        while pos < body_end {
+            let (field, next) = next_field(body, pos, data_length.map(|(_, length)| length))?
+                .ok_or(FixError::MalformedField { offset: pos })?;
+            if let Some((data_tag, _)) = data_length.take() {
+                if field.tag != data_tag {
+                    return Err(FixError::MissingField(data_tag));
+                }
+            }
+            if pos == body_start && field.tag != tag::MSG_TYPE {
+                return Err(FixError::FieldOrder { tag: tag::MSG_TYPE, offset: pos });
+            }
+            if matches!(field.tag, tag::BEGIN_STRING | tag::BODY_LENGTH | tag::CHECK_SUM) {
+                return Err(FixError::FieldOrder { tag: field.tag, offset: pos });
+            }
+            if let Some(&(_, data_tag)) = tag::DATA_FIELDS.iter().find(|(length_tag, _)| *length_tag == field.tag) {
+                data_length = Some((data_tag, field.parse()?));
+            }
+            fields.push(field);
+            pos = next;
+        }
+        if let Some((data_tag, _)) = data_length {
+            return Err(FixError::MissingField(data_tag));
+        }
+        fields.push(checksum_field);
+        Ok((Self { bytes: &buf[..end], version, fields }, end))
+    }
+
+    pub const fn get_version(&self) -> Version {
+        self.version
+    }
+
+    pub const fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn fields(&self) -> &[Field<'a>] {
+        &self.fields
+    }
+
+    // everything from MsgType up to the trailer
+    pub fn body(&self) -> Group<'_, 'a> {
+        Group { fields: &self.fields[2..self.fields.len() - 1] }
+    }
+
+    pub fn msg_type(&self) -> &'a str {
+        // parse made sure it's there and first in the body
+        self.fields[2].as_str().unwrap_or("")
+    }
+
+    pub fn get(&self, tag: Tag) -> Option<&Field<'a>> {
+        self.fields.iter().find(|field| field.tag == tag)
+    }
+
+    pub fn get_str(&self, tag: Tag) -> Result<&'a str, FixError> {
+        self.get(tag).ok_or(FixError::MissingField(tag))?.as_str()
+    }
+
+    pub fn get_parsed<T: FromStr>(&self, tag: Tag) -> Result<T, FixError> {
+        self.get(tag).ok_or(FixError::MissingField(tag))?.parse()
+    }
+
+    pub fn group(&self, count_tag: Tag, members: &[Tag]) -> Result<Vec<Group<'_, 'a>>, FixError> {
+        self.body().group(count_tag, members)
+    }
+}
+
+impl fmt::Display for Message<'_> {
+    // SOH shown as '|' for logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text: String = String::from_utf8_lossy(self.bytes).chars().map(|c| if c == '\u{1}' { '|' } else { c }).collect();
+        write!(f, "{}", text)
+    }
+}
+
+// Builds an outbound message. Fields go out in the order they're added, after
+// MsgType and any header fields; BeginString, BodyLength and CheckSum are filled
+// in by build.
+#[derive(Clone, Debug)]
+pub struct MessageBuilder {
+    version: Version,
+    // MsgType and the rest of the standard header
+    header: Vec<u8>,
+    body: Vec<u8>,
+    // the first field that couldn't be encoded
+    invalid: Option<Tag>,
+}
+
+// false, leaving `buffer` as it was, when the value is empty or holds SOH
+fn encode(buffer: &mut Vec<u8>, tag: Tag, value: impl fmt::Display) -> bool {
+    let start = buffer.len();
+    write!(buffer, "{}=", tag).ok();
+    let value_start = buffer.len();
+    write!(buffer, "{}", value).ok();
+    let value = &buffer[value_start..];
+    if value.is_empty() || value.contains(&SOH) {
+        buffer.truncate(start);
+        return false;
+    }
+    buffer.push(SOH);
+    true
+}
+
+impl MessageBuilder {
+    pub fn new(version: Version, msg_type: &str) -> Self {
+        let mut builder = Self { version, header: Vec::with_capacity(64), body: Vec::with_capacity(128), invalid: None };
+        builder.push_header(tag::MSG_TYPE, msg_type);
+        builder
+    }
+
+    pub fn push(&mut self, tag: Tag, value: impl fmt::Display) -> &mut Self {
+        if !encode(&mut self.body, tag, value) {
+            self.invalid.get_or_insert(tag);
+        }
+        self
+    }
+
+    // goes out ahead of every body field, whenever it's added
+    pub fn push_header(&mut self, tag: Tag, value: impl fmt::Display) -> &mut Self {
+        if !encode(&mut self.header, tag, value) {
+            self.invalid.get_or_insert(tag);
+        }
+        self
+    }
+
+    pub fn field(mut self, tag: Tag, value: impl fmt::Display) -> Self {
+        self.push(tag, value);
+        self
+    }
+
+    pub fn flag(self, tag: Tag, value: bool) -> Self {
+        self.field(tag, if value { 'Y' } else { 'N' })
+    }
+
+    // a length field and the data field it sizes; the data may hold anything
+    pub fn data(mut self, length_tag: Tag, data_tag: Tag, value: &[u8]) -> Self {
+        if value.is_empty() {
+            self.invalid.get_or_insert(data_tag);
+            return self;
+        }
+        self.push(length_tag, value.len());
+        write!(self.body, "{}=", data_tag).ok();
+        self.body.extend_from_slice(value);
+        self.body.push(SOH);
+        self
+    }
+
+    // The count of a repeating group. Its entries are the fields added next,
+    // each starting with the group's delimiter tag.
+    pub fn group(self, count_tag: Tag, count: usize) -> Self {
+        self.field(count_tag, count)
+    }
+
+    pub fn build(&self) -> Result<Vec<u8>, FixError> {
+        if let Some(tag) = self.invalid {
+            return Err(FixError::InvalidValue(tag));
+        }
+        let body_length = self.header.len() + self.body.len();
+        let mut message = Vec::with_capacity(body_length + 32);
+        write!(message, "8={}\x019={}\x01", self.version.begin_string(), body_length).ok();
+        message.extend_from_slice(&self.header);
+        message.extend_from_slice(&self.body);
+        let sum = checksum(&message);
+        write!(message, "10={:03}\x01", sum).ok();
+        Ok(message)
+    }
+}
+
+// How long a read waits before the session's timers get a look in
+const TICK: Duration = Duration::from_millis(200);
 
+// How long to wait for the other side's Logon, or its reply to our Logout
+pub const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn aborted(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::ConnectionAborted, reason.to_string())
+}
+
+// A session over a socket. Heartbeats, test requests and resends are dealt
+// with while recv waits; only application messages come out of it.
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+    session: Session,
+    buffer: Vec<u8>,
+    inbox: VecDeque<Vec<u8>>,
+    logged_on: bool,
+    // why the session ended
+    closed: Option<String>,
+}
+
+impl Connection {
+    // Sends `logon` if we're initiating, then waits until the session is up.
+    async fn open(stream: TcpStream, session: Session, logon: Option<Vec<u8>>) -> io::Result<Self> {
+        let mut connection = Self { stream, session, buffer: vec![], inbox: VecDeque::new(), logged_on: false, closed: None };
+        if let Some(logon) = logon {
+            connection.stream.write_all(&logon).await?;
+        }
+        let deadline = Instant::now() + LOGON_TIMEOUT;
+        while !connection.logged_on {
+            if let Some(reason) = &connection.closed {
+                return Err(aborted(reason));
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "no Logon"));
+            }
+            connection.poll(deadline).await?;
+        }
+        Ok(connection)
+    }
+
+    pub const fn get_session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.closed.is_none()
+    }
+
+    pub async fn send(&mut self, builder: MessageBuilder) -> io::Result<()> {
+        if let Some(reason) = &self.closed {
+            return Err(aborted(reason));
+        }
+        let bytes = self.session.send(Instant::now(), builder).map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+        self.stream.write_all(&bytes).await
+    }
+
+    // The next application message, or None if nothing came within `wait`.
+    // Errors once the session is over.
+    pub async fn recv(&mut self, wait: Duration) -> io::Result<Option<Vec<u8>>> {
+        let deadline = Instant::now() + wait;
+        loop {
+            if let Some(message) = self.inbox.pop_front() {
+                return Ok(Some(message));
+            }
+            if let Some(reason) = &self.closed {
+                return Err(aborted(reason));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            self.poll(deadline).await?;
+        }
+    }
+
+    // Logs out and waits for the reply before closing.
+    pub async fn logout(&mut self, text: &str) -> io::Result<()> {
+        if self.closed.is_some() {
+            return Ok(());
+        }
+        let logout = self.session.logout(Instant::now(), text);
+        self.stream.write_all(&logout).await?;
+        let deadline = Instant::now() + LOGON_TIMEOUT;
+        while self.closed.is_none() && Instant::now() < deadline {
+            self.poll(deadline).await?;
+        }
+        if self.closed.is_none() {
+            self.close("no reply to Logout").await;
+        }
+        Ok(())
+    }
+
+    // one read, for no longer than a tick, then the timers
+    async fn poll(&mut self, deadline: Instant) -> io::Result<()> {
+        let mut chunk = [0u8; 4096];
+        let wait = deadline.saturating_duration_since(Instant::now()).min(TICK);
+        match timeout(wait, self.stream.read(&mut chunk)).await {
+            Ok(Ok(0)) => {
+                self.closed = Some("connection closed".to_string());
+                return Ok(());
+            }
+            Ok(Ok(read)) => {
+                self.buffer.extend_from_slice(&chunk[..read]);
+                self.drain().await?;
+            }
+            Ok(Err(error)) => return Err(error),
+            Err(_) => {}
+        }
+        let actions = self.session.timer(Instant::now());
+        self.apply(actions).await
+    }
+
+    // hands every whole message in the buffer to the session
+    async fn drain(&mut self) -> io::Result<()> {
+        while self.closed.is_none() {
+            let (actions, used) = match Message::parse(&self.buffer) {
+                Ok((message, used)) => (self.session.on_message(Instant::now(), &message), used),
+                Err(FixError::Incomplete) => return Ok(()),
+                // garbled: dropped without taking a sequence number, as if it never came
+                Err(_) => (vec![], session::skip_garbled(&self.buffer)),
+            };
+            self.buffer.drain(..used);
+            self.apply(actions).await?;
+        }
+        Ok(())
+    }
+
+    async fn apply(&mut self, actions: Vec<Action>) -> io::Result<()> {
+        for action in actions {
+            match action {
+                Action::Send(bytes) => self.stream.write_all(&bytes).await?,
+                Action::Deliver(bytes) => self.inbox.push_back(bytes),
+                Action::LoggedOn => self.logged_on = true,
+                Action::Disconnect(reason) => self.close(&reason).await,
+            }
+        }
+        Ok(())
+    }
+
+    async fn close(&mut self, reason: &str) {
+        self.stream.shutdown().await.ok();
+        self.closed = Some(reason.to_string());
+    }
+}
+
+#[allow(non_camel_case_types)]
 pub struct fix_client {
     target : String,
+    config: SessionConfig,
+    connection: Option<Connection>,
     is_connected : bool
 }
 
+#[allow(non_camel_case_types)]
 pub struct fix_server {
     addr: String,
+    config: SessionConfig,
+    listener: Option<TcpListener>,
 }
 
-pub struct fix_message
-
 impl fix_client{
 
-    pub fn new(addr: &str) -> Self {
+    pub fn new(addr: &str, config: SessionConfig) -> Self {
         Self{
-            target : addr,
+            target : addr.to_string(),
+            config,
+            connection: None,
             is_connected: false,
         }
     }
 
-    pub fn connect() -> Result<(), Box<dyn std::error::Error>> {
-        // stream = TcpStream::connect(&addr)?;
-        todo!();
+    pub fn is_connected(&self) -> bool {
+        self.is_connected && self.connection.as_ref().is_some_and(Connection::is_open)
     }
 
-    pub fn disconnect() -> Result<(), Box<dyn std::error::Error>> {
-        todo!();
+    pub fn get_connection(&self) -> Option<&Connection> {
+        self.connection.as_ref()
+    }
+
+    // connects and logs on
+    pub async fn connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let stream = TcpStream::connect(&self.target).await?;
+        // a new session numbers from 1 both ways, and its Logon says so
+        let mut session = Session::new(self.config.clone(), Role::Initiator, Instant::now())?;
+        let logon = session.logon(Instant::now());
+        self.connection = Some(Connection::open(stream, session, Some(logon)).await?);
+        self.is_connected = true;
+        Ok(())
+    }
+
+    fn connection(&mut self) -> Result<&mut Connection, Box<dyn std::error::Error>> {
+        Ok(self.connection.as_mut().ok_or("not connected")?)
+    }
+
+    pub async fn send(&mut self, builder: MessageBuilder) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(self.connection()?.send(builder).await?)
+    }
+
+    pub async fn recv(&mut self, wait: Duration) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+        Ok(self.connection()?.recv(wait).await?)
+    }
+
+    // logs out
+    pub async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connection()?.logout("client disconnecting").await?;
+        self.is_connected = false;
+        Ok(())
     }
 }
 
 impl fix_server{
 
-    pub fn new(addr: &str) -> Self {
+    pub fn new(addr: &str, config: SessionConfig) -> Self {
         Self{
-            addr : String,
+            addr : addr.to_string(),
+            config,
+            listener: None,
+        }
+    }
+
+    pub async fn listen(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.listener = Some(TcpListener::bind(&self.addr).await?);
+        Ok(())
+    }
+
+    // the next client, once it's logged on
+    pub async fn accept(&self) -> Result<Connection, Box<dyn std::error::Error>> {
+        let listener = self.listener.as_ref().ok_or("not listening")?;
+        let (stream, _) = listener.accept().await?;
+        let session = Session::new(self.config.clone(), Role::Acceptor, Instant::now())?;
+        Ok(Connection::open(stream, session, None).await?)
+    }
+
+    // stops taking connections; the ones already accepted carry on
+    pub fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.listener.take().ok_or("not listening")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    // NoPartyIDs and its entry
+    const NO_PARTY_IDS: Tag = 453;
+    const PARTY: [Tag; 3] = [448, 447, 452];
+
+    fn order() -> MessageBuilder {
+        MessageBuilder::new(Version::Fix44, "D")
+            .field(tag::MSG_SEQ_NUM, 2)
+            .field(tag::SENDER_COMP_ID, "CLIENT")
+            .field(tag::TARGET_COMP_ID, "VENUE")
+            .field(11, "ord-1")
+            .group(NO_PARTY_IDS, 2)
+            .field(448, "TRADER1").field(447, 'D').field(452, 11)
+            .field(448, "DESK").field(447, 'D')
+            .field(55, "ABC")
+            .field(54, 1)
+            .field(38, 100)
+            .field(44, 101.25)
+    }
+
+    #[test]
+    fn test_build_and_parse(){
+        let bytes = order().build().unwrap();
+        let text = String::from_utf8(bytes.clone()).unwrap().replace('\u{1}', "|");
+        assert!(text.starts_with("8=FIX.4.4|9=113|35=D|34=2|"));
+        assert!(text.ends_with(&format!("|10={:03}|", checksum(&bytes[..bytes.len() - 7]))));
+
+        // two back to back, the second cut short
+        let mut stream = bytes.clone();
+        stream.extend_from_slice(&bytes[..20]);
+        let (message, used) = Message::parse(&stream).unwrap();
+        assert_eq!(used, bytes.len());
+        assert_eq!(Message::parse(&stream[used..]).unwrap_err(), FixError::Incomplete);
+
+        assert_eq!(message.get_version(), Version::Fix44);
+        assert_eq!(message.msg_type(), "D");
+        assert_eq!(message.get_parsed::<u64>(tag::MSG_SEQ_NUM), Ok(2));
+        assert_eq!(message.get_parsed::<f64>(44), Ok(101.25));
+        assert_eq!(message.get_str(1), Err(FixError::MissingField(1)));
+        // values are slices of the input
+        let symbol = message.get(55).unwrap().value;
+        assert!(stream.as_ptr_range().contains(&symbol.as_ptr()));
+
+        let parties = message.group(NO_PARTY_IDS, &PARTY).unwrap();
+        assert_eq!(parties.len(), 2);
+        assert_eq!((parties[0].get_str(448), parties[0].get_parsed::<u32>(452)), (Ok("TRADER1"), Ok(11)));
+        assert_eq!((parties[1].get_str(448), parties[1].get(452)), (Ok("DESK"), None));
+        // the entries stop where the group's tags do
+        assert_eq!(parties[1].fields().len(), 2);
+        assert_eq!(message.group(NO_PARTY_IDS, &[448]), Err(FixError::GroupCount { tag: NO_PARTY_IDS, declared: 2, found: 1 }));
+    }
+
+    #[test]
+    fn test_known_message(){
+        let bytes = b"8=FIX.4.2\x019=50\x0135=0\x0134=7\x0149=VENUE\x0152=20240102-09:30:00\x0156=CLIENT\x0110=064\x01";
+        let (message, used) = Message::parse(bytes).unwrap();
+        assert_eq!((message.get_version(), message.msg_type(), used), (Version::Fix42, "0", bytes.len()));
+        assert_eq!(message.to_string(), "8=FIX.4.2|9=50|35=0|34=7|49=VENUE|52=20240102-09:30:00|56=CLIENT|10=064|");
+        let rebuilt = MessageBuilder::new(Version::Fix42, "0")
+            .field(34, 7).field(49, "VENUE").field(52, "20240102-09:30:00").field(56, "CLIENT")
+            .build().unwrap();
+        assert_eq!(rebuilt, bytes);
+
+        // every prefix is just waiting for the rest
+        for end in 0..bytes.len() {
+            assert_eq!(Message::parse(&bytes[..end]).unwrap_err(), FixError::Incomplete, "prefix of {}", end);
         }
     }
 
-    pub fn listen() -> Result<(), Box<dyn std::error::Error>> {
-        todo!();
+    #[test]
+    fn test_malformed_input(){
+        let parse = |text: &str| Message::parse(text.replace('|', "\u{1}").as_bytes()).map(|(message, _)| message.fields().len());
+        assert_eq!(parse("8=FIX.4.2|9=5|35=0|10=161|"), Ok(4));
+        assert_eq!(parse("8=FIX.4.2|9=5|35=0|10=162|"), Err(FixError::CheckSum { declared: 162, computed: 161 }));
+        assert_eq!(parse("8=FIX.4.2|9=6|35=0|10=161|x"), Err(FixError::BodyLength { declared: 6 }));
+        assert_eq!(parse("8=FIX.4.2|9=4|35=0|10=161|"), Err(FixError::BodyLength { declared: 4 }));
+        assert_eq!(parse("8=FIX.4.2|9=x|35=0|10=161|"), Err(FixError::InvalidValue(tag::BODY_LENGTH)));
+        assert_eq!(parse("8=FIX.5.0|9=5|35=0|10=161|"), Err(FixError::UnsupportedVersion));
+        assert_eq!(parse("9=5|8=FIX.4.2|35=0|10=161|"), Err(FixError::FieldOrder { tag: tag::BEGIN_STRING, offset: 0 }));
+        assert_eq!(parse("8=FIX.4.2|35=0|9=5|10=161|"), Err(FixError::FieldOrder { tag: tag::BODY_LENGTH, offset: 10 }));
+        assert_eq!(parse("8=FIX.4.2|9=5|34=1|10=161|"), Err(FixError::FieldOrder { tag: tag::MSG_TYPE, offset: 14 }));
+        assert_eq!(parse("8=FIX.4.2|9=4|35=|10=112|"), Err(FixError::MalformedField { offset: 14 }));
+        assert_eq!(parse("8=FIX.4.2|9=7|35=0|=|10=225|"), Err(FixError::MalformedField { offset: 19 }));
+        assert_eq!(parse("8=FIX.4.2|9=1000000000|"), Err(FixError::InvalidValue(tag::BODY_LENGTH)));
+
+        // raw data may hold SOH
+        let bytes = MessageBuilder::new(Version::Fix42, "A").data(95, 96, b"a\x01b").build().unwrap();
+        let (message, _) = Message::parse(&bytes).unwrap();
+        assert_eq!(message.get(96).unwrap().value, b"a\x01b");
+
+        assert_eq!(MessageBuilder::new(Version::Fix42, "A").field(58, "a\u{1}b").build(), Err(FixError::InvalidValue(58)));
+        assert_eq!(MessageBuilder::new(Version::Fix42, "").build(), Err(FixError::InvalidValue(tag::MSG_TYPE)));
     }
 
-    pub fn disconnect() -> Result<(), Box<dyn std::error::Error>> {
-        todo!();
+    proptest! {
+        #[test]
+        fn test_arbitrary_bytes_never_panic(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+            let _ = Message::parse(&bytes);
+        }
+
+        #[test]
+        fn test_corrupted_messages_never_panic(index in 0usize..128, byte in any::<u8>()) {
+            let mut bytes = order().build().unwrap();
+            let index = index % bytes.len();
+            bytes[index] = byte;
+            let _ = Message::parse(&bytes);
+        }
+
+        #[test]
+        fn test_round_trip(fields in proptest::collection::vec((11u32..10_000, "[ -~]{1,12}"), 0..16)) {
+            // data length tags would take the next field as their data
+            prop_assume!(fields.iter().all(|(tag, _)| tag::DATA_FIELDS.iter().all(|(length_tag, _)| length_tag != tag)));
+            let mut builder = MessageBuilder::new(Version::Fix42, "8");
+            for (tag, value) in &fields {
+                builder.push(*tag, value);
+            }
+            let bytes = builder.build().unwrap();
+            let (message, used) = Message::parse(&bytes).unwrap();
+            prop_assert_eq!(used, bytes.len());
+            let parsed: Vec<(Tag, &str)> = message.body().fields()[1..].iter().map(|field| (field.tag, field.as_str().unwrap())).collect();
+            let expected: Vec<(Tag, &str)> = fields.iter().map(|(tag, value)| (*tag, value.as_str())).collect();
+            prop_assert_eq!(parsed, expected);
+        }
     }
-}
\ No newline at end of file
+}
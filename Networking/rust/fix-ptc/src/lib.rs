@@ -0,0 +1,2 @@
+pub mod fix;
+pub mod session;
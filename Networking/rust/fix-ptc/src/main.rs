@@ -1,58 +1,44 @@
-use tokio::{
-    net::TcpListener, 
-    io::{AsyncReadExt, AsyncWriteExt}, 
-    time::{timeout, Duration}
+use tokio::time::Duration;
+use fix_ptc::{
+    fix::{fix_server, Message, Version},
+    session::SessionConfig,
 };
 
-use std::net::Shutdown;
-
 #[tokio::main]
 async fn main() {
-    let listener = TcpListener::bind("127.0.0.1:7000").await.unwrap();
-    
-    
-    let (mut socket, _) = listener.accept().await.unwrap();
-    let mut dur = [0u8; 4];
-    let timer;
-    let res1 = timeout(Duration::from_secs(5), socket.read_exact(&mut dur)).await;
+    let config = SessionConfig {
+        version: Version::Fix44,
+        sender_comp_id: "SERVER".to_string(),
+        target_comp_id: "CLIENT".to_string(),
+        // the client's Logon sets it
+        heartbeat_interval: 0,
+    };
+    let mut server = fix_server::new("127.0.0.1:7000", config);
+    server.listen().await.unwrap();
 
-    match res1 {
-        Ok(Ok(_)) =>{
-            timer = u32::from_be_bytes(dur);
-            println!("Duration recieved! ({})", timer);
+    let mut connection = match server.accept().await {
+        Ok(connection) => {
+            println!("Logon received! (HeartBtInt {})", connection.get_session().get_heartbeat_interval());
+            connection
         }
-        _ => {
-            println!("Invalid connection: no heartbeat set");
+        Err(error) => {
+            println!("Invalid connection: {}", error);
             return;
         }
-            //  stream.shutdown(std::net::Shutdown::Write).expect("Shutdown failed");
-    }
-     
-    
-    
-
-    tokio::spawn(async move {
-        let mut count = 0;
-        while count < 3 {
-            let mut buf = [0u8; 2];
-            let res2 = timeout(Duration::from_secs(timer.into()), socket.read_exact(&mut buf)).await;
+    };
 
-            match res2 {
-                Ok(Ok(_)) if &buf == b"HB" => {
-                    println!("Heartbeat received.");
-                    socket.write_all(b"OK").await.unwrap();
-                    
-                },
-                _ => {
-                    println!("Connection lost or timeout.");
-                    return;
-                }
+    // heartbeats and test requests are answered while waiting
+    loop {
+        match connection.recv(Duration::from_secs(60)).await {
+            Ok(Some(bytes)) => match Message::parse(&bytes) {
+                Ok((message, _)) => println!("Received {}", message),
+                Err(error) => println!("Unreadable message: {}", error),
+            },
+            Ok(None) => {}
+            Err(error) => {
+                println!("Session ended: {}", error);
+                break;
             }
-            count += 1;
         }
-        socket.shutdown().await.expect("Shutdown failed");
-        socket.flush().await.expect("Flush failed");
-        drop(socket);
-    }).await;
+    }
 }
-
@@ -0,0 +1,723 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+use chrono::Utc;
+use crate::fix::{tag, Field, FixError, Message, MessageBuilder, Version};
+
+pub mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const LOGON: &str = "A";
+}
+
+// SessionRejectReason (373)
+pub const REQUIRED_TAG_MISSING: u32 = 1;
+pub const VALUE_INCORRECT: u32 = 5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Role {
+    // connects and sends the first Logon
+    Initiator,
+    // waits for the Logon and answers it
+    Acceptor,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SessionState {
+    Idle,
+    LogonSent,
+    Active,
+    LogoutSent,
+    Closed,
+}
+
+#[derive(Clone, Debug)]
+pub struct SessionConfig {
+    pub version: Version,
+    pub sender_comp_id: String,
+    pub target_comp_id: String,
+    // seconds, 0 for none; the acceptor takes whatever the initiator's Logon asks for
+    pub heartbeat_interval: u64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Action {
+    Send(Vec<u8>),
+    // an application message, or a Reject of one of ours, in sequence
+    Deliver(Vec<u8>),
+    LoggedOn,
+    // the session is over and the connection should be closed
+    Disconnect(String),
+}
+
+#[derive(Clone, Debug)]
+struct Sent {
+    builder: MessageBuilder,
+    sending_time: String,
+}
+
+fn sending_time() -> String {
+    Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
+
+// Where the next message might start after one that couldn't be parsed.
+pub fn skip_garbled(buf: &[u8]) -> usize {
+    buf.windows(5).skip(1).position(|window| window == b"8=FIX").map_or(buf.len(), |index| index + 1)
+}
+
+// The FIX session layer without any I/O: feed it what arrives and the time, and
+// send what it hands back. Sequence numbers are kept both ways; application
+// messages are delivered strictly in order, and a gap is asked for again with
+// a ResendRequest. Our own application messages are kept so a ResendRequest
+// can be answered, with admin messages in between gap filled.
+#[derive(Debug)]
+pub struct Session {
+    config: SessionConfig,
+    role: Role,
+    state: SessionState,
+    next_outbound: u64,
+    next_inbound: u64,
+    sent: BTreeMap<u64, Sent>,
+    last_sent: Instant,
+    last_received: Instant,
+    // since a Logon or Logout of ours went unanswered
+    waiting_since: Instant,
+    // the id of an unanswered TestRequest and when it went
+    test_request: Option<(String, Instant)>,
+    test_requests_sent: u64,
+    // the highest sequence number seen while a ResendRequest is outstanding
+    resend_until: Option<u64>,
+}
+
+impl Session {
+    pub fn new(config: SessionConfig, role: Role, now: Instant) -> Result<Self, FixError> {
+        // every admin message carries them, so they have to encode
+        MessageBuilder::new(config.version, msg_type::HEARTBEAT)
+            .field(tag::SENDER_COMP_ID, &config.sender_comp_id)
+            .field(tag::TARGET_COMP_ID, &config.target_comp_id)
+            .build()?;
+        Ok(Self {
+            config,
+            role,
+            state: SessionState::Idle,
+            next_outbound: 1,
+            next_inbound: 1,
+            sent: BTreeMap::new(),
+            last_sent: now,
+            last_received: now,
+            waiting_since: now,
+            test_request: None,
+            test_requests_sent: 0,
+            resend_until: None,
+        })
+    }
+
+    pub const fn get_state(&self) -> SessionState {
+        self.state
+    }
+
+    pub const fn get_role(&self) -> Role {
+        self.role
+    }
+
+    pub const fn get_next_outbound(&self) -> u64 {
+        self.next_outbound
+    }
+
+    pub const fn get_next_inbound(&self) -> u64 {
+        self.next_inbound
+    }
+
+    pub const fn get_heartbeat_interval(&self) -> u64 {
+        self.config.heartbeat_interval
+    }
+
+    fn heartbeat(&self) -> Option<Duration> {
+        (self.config.heartbeat_interval > 0).then(|| Duration::from_secs(self.config.heartbeat_interval))
+    }
+
+    // `orig_sending_time` marks a possible duplicate being sent again
+    fn encode(&self, mut builder: MessageBuilder, seq: u64, sending_time: &str, orig_sending_time: Option<&str>) -> Vec<u8> {
+        builder
+            .push_header(tag::SENDER_COMP_ID, &self.config.sender_comp_id)
+            .push_header(tag::TARGET_COMP_ID, &self.config.target_comp_id)
+            .push_header(tag::MSG_SEQ_NUM, seq)
+            .push_header(tag::SENDING_TIME, sending_time);
+        if let Some(orig_sending_time) = orig_sending_time {
+            builder.push_header(tag::POSS_DUP_FLAG, 'Y').push_header(tag::ORIG_SENDING_TIME, orig_sending_time);
+        }
+        // Comp ids were checked in new, and logout leaves out a Text it can't
+        // encode. Everything else is our own or was parsed off the wire, so it's
+        // never empty and never holds SOH.
+        builder.build().expect("session header encodes")
+    }
+
+    // stamps the next sequence number on
+    fn next(&mut self, now: Instant, builder: MessageBuilder) -> Vec<u8> {
+        let seq = self.next_outbound;
+        self.next_outbound += 1;
+        self.last_sent = now;
+        self.encode(builder, seq, &sending_time(), None)
+    }
+
+    fn admin(&self, msg_type: &str) -> MessageBuilder {
+        MessageBuilder::new(self.config.version, msg_type)
+    }
+
+    // Sequence numbers aren't kept from one connection to the next, so every
+    // Logon of ours carries ResetSeqNumFlag for the other side to start from 1 too.
+    fn logon_message(&self) -> MessageBuilder {
+        self.admin(msg_type::LOGON)
+            .field(tag::ENCRYPT_METHOD, 0)
+            .field(tag::HEART_BT_INT, self.config.heartbeat_interval)
+            .flag(tag::RESET_SEQ_NUM_FLAG, true)
+    }
+
+    pub fn logon(&mut self, now: Instant) -> Vec<u8> {
+        self.state = SessionState::LogonSent;
+        self.waiting_since = now;
+        let logon = self.logon_message();
+        self.next(now, logon)
+    }
+
+    pub fn logout(&mut self, now: Instant, text: &str) -> Vec<u8> {
+        self.state = SessionState::LogoutSent;
+        self.waiting_since = now;
+        // Text is optional, so an empty one or one holding SOH is left out
+        let logout = self.admin(msg_type::LOGOUT);
+        let with_text = logout.clone().field(tag::TEXT, text);
+        self.next(now, if with_text.build().is_ok() { with_text } else { logout })
+    }
+
+    // An application message: MsgType and body from the builder, the header
+    // from the session. Kept until the session ends in case it's asked for again.
+    pub fn send(&mut self, now: Instant, builder: MessageBuilder) -> Result<Vec<u8>, FixError> {
+        // checked before it takes a sequence number
+        builder.build()?;
+        let sent = Sent { builder: builder.clone(), sending_time: sending_time() };
+        let seq = self.next_outbound;
+        self.next_outbound += 1;
+        self.last_sent = now;
+        let bytes = self.encode(builder, seq, &sent.sending_time, None);
+        self.sent.insert(seq, sent);
+        Ok(bytes)
+    }
+
+    // Logs out with `text` and ends the session straight away.
+    fn fail(&mut self, now: Instant, text: String) -> Vec<Action> {
+        let logout = self.logout(now, &text);
+        self.state = SessionState::Closed;
+        vec![Action::Send(logout), Action::Disconnect(text)]
+    }
+
+    fn reject(&mut self, now: Instant, ref_seq_num: u64, ref_msg_type: &str, ref_tag: u32, reason: u32, text: &str) -> Action {
+        let reject = self.admin(msg_type::REJECT)
+            .field(tag::REF_SEQ_NUM, ref_seq_num)
+            .field(tag::REF_TAG_ID, ref_tag)
+            .field(tag::REF_MSG_TYPE, ref_msg_type)
+            .field(tag::SESSION_REJECT_REASON, reason)
+            .field(tag::TEXT, text);
+        Action::Send(self.next(now, reject))
+    }
+
+    // Heartbeats when we've been quiet, a TestRequest when they have, and the
+    // end of the session when that or our Logon or Logout goes unanswered.
+    pub fn timer(&mut self, now: Instant) -> Vec<Action> {
+        let Some(interval) = self.heartbeat() else { return vec![] };
+        let mut actions = vec![];
+        match self.state {
+            SessionState::Idle | SessionState::Closed => return actions,
+            SessionState::LogonSent | SessionState::LogoutSent => {
+                if now.saturating_duration_since(self.waiting_since) >= interval {
+                    let text = if self.state == SessionState::LogonSent { "no reply to Logon" } else { "no reply to Logout" };
+                    self.state = SessionState::Closed;
+                    actions.push(Action::Disconnect(text.to_string()));
+                }
+                return actions;
+            }
+            SessionState::Active => {}
+        }
+        match &self.test_request {
+            Some((_, sent_at)) if now.saturating_duration_since(*sent_at) >= interval => {
+                return self.fail(now, "no reply to TestRequest".to_string());
+            }
+            Some(_) => {}
+            // a fifth over the interval for the heartbeat to get here
+            None if now.saturating_duration_since(self.last_received) >= interval + interval / 5 => {
+                self.test_requests_sent += 1;
+                let id = format!("TEST{}", self.test_requests_sent);
+                let test_request = self.admin(msg_type::TEST_REQUEST).field(tag::TEST_REQ_ID, &id);
+                actions.push(Action::Send(self.next(now, test_request)));
+                self.test_request = Some((id, now));
+            }
+            None => {}
+        }
+        if now.saturating_duration_since(self.last_sent) >= interval {
+            let heartbeat = self.admin(msg_type::HEARTBEAT);
+            actions.push(Action::Send(self.next(now, heartbeat)));
+        }
+        actions
+    }
+
+    pub fn on_message(&mut self, now: Instant, message: &Message) -> Vec<Action> {
+        if self.state == SessionState::Closed {
+            return vec![];
+        }
+        // anything at all shows they're still there
+        self.last_received = now;
+        self.test_request = None;
+
+        if message.get_version() != self.config.version {
+            let text = format!("BeginString {} when {} was expected", message.get_version().begin_string(), self.config.version.begin_string());
+            return self.fail(now, text);
+        }
+        let msg_type = message.msg_type();
+        let sender = message.get_str(tag::SENDER_COMP_ID).unwrap_or("");
+        let target = message.get_str(tag::TARGET_COMP_ID).unwrap_or("");
+        if sender != self.config.target_comp_id || target != self.config.sender_comp_id {
+            return self.fail(now, format!("CompID problem: {} to {}", sender, target));
+        }
+        let Ok(seq) = message.get_parsed::<u64>(tag::MSG_SEQ_NUM) else {
+            return self.fail(now, "MsgSeqNum missing".to_string());
+        };
+        let flag = |tag| message.get(tag).map_or(Ok(false), Field::as_bool).unwrap_or(false);
+        let (poss_dup, gap_fill) = (flag(tag::POSS_DUP_FLAG), flag(tag::GAP_FILL_FLAG));
+        let reset = msg_type == msg_type::LOGON && flag(tag::RESET_SEQ_NUM_FLAG);
+
+        let mut actions = vec![];
+        match (self.state, msg_type == msg_type::LOGON) {
+            (SessionState::Idle | SessionState::LogonSent, false) => {
+                return self.fail(now, format!("first message must be Logon, not {}", msg_type));
+            }
+            (SessionState::Idle | SessionState::LogonSent, true) => {
+                if self.role == Role::Acceptor {
+                    let Ok(interval) = message.get_parsed::<u64>(tag::HEART_BT_INT) else {
+                        return self.fail(now, "HeartBtInt missing".to_string());
+                    };
+                    self.config.heartbeat_interval = interval;
+                    let logon = self.logon_message();
+                    actions.push(Action::Send(self.next(now, logon)));
+                }
+                self.state = SessionState::Active;
+                actions.push(Action::LoggedOn);
+            }
+            // a reset in the middle of the session: both sides start again from 1
+            (SessionState::Active, true) if reset => {
+                self.next_outbound = 1;
+                self.sent.clear();
+                let logon = self.logon_message();
+                actions.push(Action::Send(self.next(now, logon)));
+            }
+            (_, true) => return self.fail(now, "Logon while logged on".to_string()),
+            _ => {}
+        }
+        if reset {
+            // their numbers start again with this Logon
+            self.next_inbound = 1;
+            self.resend_until = None;
+        }
+
+        if seq < self.next_inbound {
+            if poss_dup {
+                // already had it
+                return actions;
+            }
+            let text = format!("MsgSeqNum too low, expecting {} but received {}", self.next_inbound, seq);
+            actions.extend(self.fail(now, text));
+            return actions;
+        }
+
+        // SequenceReset-Reset moves the numbers on whatever its own is
+        if msg_type == msg_type::SEQUENCE_RESET && !gap_fill {
+            match message.get_parsed::<u64>(tag::NEW_SEQ_NO) {
+                Ok(new_seq) if new_seq >= self.next_inbound => self.set_next_inbound(new_seq),
+                Ok(_) => actions.push(self.reject(now, seq, msg_type, tag::NEW_SEQ_NO, VALUE_INCORRECT, "NewSeqNo lower than expected")),
+                Err(_) => actions.push(self.reject(now, seq, msg_type, tag::NEW_SEQ_NO, REQUIRED_TAG_MISSING, "NewSeqNo missing")),
+            }
+            return actions;
+        }
+
+        if seq > self.next_inbound {
+            // Asked for once, from the gap on. Anything else that turns up
+            // meanwhile is dropped; it's in what comes back.
+            if self.resend_until.is_none() {
+                let resend = self.admin(msg_type::RESEND_REQUEST)
+                    .field(tag::BEGIN_SEQ_NO, self.next_inbound)
+                    .field(tag::END_SEQ_NO, 0);
+                actions.push(Action::Send(self.next(now, resend)));
+            }
+            self.resend_until = Some(self.resend_until.map_or(seq, |until| until.max(seq)));
+            // what can't wait for the gap to fill; a TestRequest comes back as
+            // part of a GapFill, so it has to be answered now or never
+            match msg_type {
+                msg_type::TEST_REQUEST => actions.push(self.on_test_request(now, seq, message)),
+                msg_type::RESEND_REQUEST => actions.extend(self.on_resend_request(now, seq, message)),
+                msg_type::LOGOUT => actions.extend(self.on_logout(now)),
+                _ => {}
+            }
+            return actions;
+        }
+
+        self.set_next_inbound(self.next_inbound + 1);
+        match msg_type {
+            msg_type::LOGON | msg_type::HEARTBEAT => {}
+            msg_type::TEST_REQUEST => actions.push(self.on_test_request(now, seq, message)),
+            msg_type::RESEND_REQUEST => actions.extend(self.on_resend_request(now, seq, message)),
+            msg_type::SEQUENCE_RESET => match message.get_parsed::<u64>(tag::NEW_SEQ_NO) {
+                Ok(new_seq) if new_seq > seq => self.set_next_inbound(new_seq),
+                Ok(_) => actions.push(self.reject(now, seq, msg_type, tag::NEW_SEQ_NO, VALUE_INCORRECT, "NewSeqNo must move forward")),
+                Err(_) => actions.push(self.reject(now, seq, msg_type, tag::NEW_SEQ_NO, REQUIRED_TAG_MISSING, "NewSeqNo missing")),
+            },
+            msg_type::LOGOUT => actions.extend(self.on_logout(now)),
+            _ => actions.push(Action::Deliver(message.as_bytes().to_vec())),
+        }
+        actions
+    }
+
+    // past the highest number seen during a gap, the ResendRequest has been answered
+    fn set_next_inbound(&mut self, next: u64) {
+        self.next_inbound = next;
+        if self.resend_until.is_some_and(|until| next > until) {
+            self.resend_until = None;
+        }
+    }
+
+    fn on_test_request(&mut self, now: Instant, seq: u64, message: &Message) -> Action {
+        match message.get_str(tag::TEST_REQ_ID) {
+            Ok(id) => {
+                let heartbeat = self.admin(msg_type::HEARTBEAT).field(tag::TEST_REQ_ID, id);
+                Action::Send(self.next(now, heartbeat))
+            }
+            Err(_) => self.reject(now, seq, msg_type::TEST_REQUEST, tag::TEST_REQ_ID, REQUIRED_TAG_MISSING, "TestReqID missing"),
+        }
+    }
+
+    fn on_logout(&mut self, now: Instant) -> Vec<Action> {
+        let mut actions = vec![];
+        if self.state != SessionState::LogoutSent {
+            actions.push(Action::Send(self.logout(now, "logout acknowledged")));
+        }
+        self.state = SessionState::Closed;
+        actions.push(Action::Disconnect("logged out".to_string()));
+        actions
+    }
+
+    fn on_resend_request(&mut self, now: Instant, seq: u64, message: &Message) -> Vec<Action> {
+        let range = (message.get_parsed::<u64>(tag::BEGIN_SEQ_NO), message.get_parsed::<u64>(tag::END_SEQ_NO));
+        let (Ok(begin), Ok(end)) = range else {
+            let missing = if range.0.is_err() { tag::BEGIN_SEQ_NO } else { tag::END_SEQ_NO };
+            return vec![self.reject(now, seq, msg_type::RESEND_REQUEST, missing, REQUIRED_TAG_MISSING, "resend range missing")];
+        };
+        if begin == 0 {
+            return vec![self.reject(now, seq, msg_type::RESEND_REQUEST, tag::BEGIN_SEQ_NO, VALUE_INCORRECT, "BeginSeqNo must be positive")];
+        }
+        self.resend(now, begin, end)
+    }
+
+    // Application messages in the range go again as possible duplicates; runs
+    // of admin messages become one SequenceReset-GapFill. An end of 0 (or
+    // 999999 in 4.2) is everything sent so far.
+    fn resend(&mut self, now: Instant, begin: u64, end: u64) -> Vec<Action> {
+        let last = self.next_outbound - 1;
+        let end = if end == 0 || end > last { last } else { end };
+        let resent_at = sending_time();
+        let mut actions = vec![];
+        let mut gap_start = None;
+        for seq in begin..=end {
+            match self.sent.get(&seq) {
+                Some(sent) => {
+                    if let Some(start) = gap_start.take() {
+                        actions.push(Action::Send(self.gap_fill(start, seq, &resent_at)));
+                    }
+                    actions.push(Action::Send(self.encode(sent.builder.clone(), seq, &resent_at, Some(&sent.sending_time))));
+                }
+                None => {
+                    gap_start.get_or_insert(seq);
+                }
+            }
+        }
+        if let Some(start) = gap_start {
+            actions.push(Action::Send(self.gap_fill(start, end + 1, &resent_at)));
+        }
+        if !actions.is_empty() {
+            self.last_sent = now;
+        }
+        actions
+    }
+
+    fn gap_fill(&self, seq: u64, new_seq: u64, sending_time: &str) -> Vec<u8> {
+        let gap_fill = self.admin(msg_type::SEQUENCE_RESET).flag(tag::GAP_FILL_FLAG, true).field(tag::NEW_SEQ_NO, new_seq);
+        self.encode(gap_fill, seq, sending_time, Some(sending_time))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(sender: &str, target: &str, heartbeat_interval: u64) -> SessionConfig {
+        SessionConfig { version: Version::Fix44, sender_comp_id: sender.to_string(), target_comp_id: target.to_string(), heartbeat_interval }
+    }
+
+    fn pair(now: Instant) -> (Session, Session) {
+        let client = Session::new(config("CLIENT", "VENUE", 30), Role::Initiator, now).unwrap();
+        let venue = Session::new(config("VENUE", "CLIENT", 0), Role::Acceptor, now).unwrap();
+        (client, venue)
+    }
+
+    fn parse(bytes: &[u8]) -> Message<'_> {
+        Message::parse(bytes).unwrap().0
+    }
+
+    // what was sent, as (MsgType, MsgSeqNum)
+    fn sent(actions: &[Action]) -> Vec<(String, u64)> {
+        actions.iter()
+            .filter_map(|action| match action {
+                Action::Send(bytes) => {
+                    let message = parse(bytes);
+                    Some((message.msg_type().to_string(), message.get_parsed(tag::MSG_SEQ_NUM).unwrap()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn send_all(to: &mut Session, now: Instant, actions: &[Action]) -> Vec<Action> {
+        actions.iter()
+            .filter_map(|action| match action {
+                Action::Send(bytes) => Some(to.on_message(now, &parse(bytes))),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn news(text: &str) -> MessageBuilder {
+        MessageBuilder::new(Version::Fix44, "B").field(148, text)
+    }
+
+    #[test]
+    fn test_logon_heartbeats_and_test_requests(){
+        let start = Instant::now();
+        let (mut client, mut venue) = pair(start);
+        // nothing but a Logon is taken first
+        let early = client.send(start, news("early")).unwrap();
+        let actions = venue.on_message(start, &parse(&early));
+        assert_eq!(sent(&actions), vec![("5".to_string(), 1)]);
+        assert_eq!(venue.get_state(), SessionState::Closed);
+
+        let (mut client, mut venue) = pair(start);
+        let logon = client.logon(start);
+        assert_eq!(parse(&logon).get_str(tag::RESET_SEQ_NUM_FLAG), Ok("Y"));
+        let actions = venue.on_message(start, &parse(&logon));
+        assert_eq!(sent(&actions), vec![("A".to_string(), 1)]);
+        let Action::Send(bytes) = &actions[0] else { panic!() };
+        assert_eq!(parse(bytes).get_str(tag::RESET_SEQ_NUM_FLAG), Ok("Y"));
+        assert!(actions.contains(&Action::LoggedOn));
+        assert_eq!(venue.get_heartbeat_interval(), 30);
+        assert!(send_all(&mut client, start, &actions).contains(&Action::LoggedOn));
+        assert_eq!((client.get_state(), venue.get_state()), (SessionState::Active, SessionState::Active));
+
+        // quiet for an interval: a heartbeat each way
+        let later = start + Duration::from_secs(30);
+        let heartbeat = venue.timer(later);
+        assert_eq!(sent(&heartbeat), vec![("0".to_string(), 2)]);
+        assert!(send_all(&mut client, later, &heartbeat).is_empty());
+
+        // nothing from the client for a fifth over: TestRequest, answered with its id
+        let later = start + Duration::from_secs(36);
+        let test_request = venue.timer(later);
+        assert_eq!(sent(&test_request), vec![("1".to_string(), 3)]);
+        let reply = send_all(&mut client, later, &test_request);
+        let Action::Send(bytes) = &reply[0] else { panic!() };
+        assert_eq!(parse(bytes).get_str(tag::TEST_REQ_ID), Ok("TEST1"));
+        assert!(send_all(&mut venue, later, &reply).is_empty());
+
+        // unanswered this time
+        venue.timer(start + Duration::from_secs(80));
+        let actions = venue.timer(start + Duration::from_secs(110));
+        assert_eq!(actions.last(), Some(&Action::Disconnect("no reply to TestRequest".to_string())));
+
+        // the other side speaking another version of FIX ends it
+        let (mut client, mut venue) = pair(start);
+        let logon = venue.on_message(start, &parse(&client.logon(start)));
+        send_all(&mut client, start, &logon);
+        let mut heartbeat = MessageBuilder::new(Version::Fix42, "0");
+        heartbeat.push_header(tag::SENDER_COMP_ID, "CLIENT").push_header(tag::TARGET_COMP_ID, "VENUE").push_header(tag::MSG_SEQ_NUM, 2);
+        let actions = venue.on_message(start, &parse(&heartbeat.build().unwrap()));
+        assert_eq!(actions.last(), Some(&Action::Disconnect("BeginString FIX.4.2 when FIX.4.4 was expected".to_string())));
+        assert_eq!(venue.get_state(), SessionState::Closed);
+
+        // a TestRequest without its id is rejected, not fatal
+        let (mut client, mut venue) = pair(start);
+        let logon = venue.on_message(start, &parse(&client.logon(start)));
+        send_all(&mut client, start, &logon);
+        let bad = client.next(start, MessageBuilder::new(Version::Fix44, "1"));
+        let actions = venue.on_message(start, &parse(&bad));
+        assert_eq!(sent(&actions), vec![("3".to_string(), 2)]);
+        let Action::Send(bytes) = &actions[0] else { panic!() };
+        assert_eq!(parse(bytes).get_parsed::<u32>(tag::REF_TAG_ID), Ok(tag::TEST_REQ_ID));
+        assert_eq!((venue.get_state(), venue.get_next_inbound()), (SessionState::Active, 3));
+    }
+
+    #[test]
+    fn test_gaps_are_resent_in_order(){
+        let now = Instant::now();
+        let (mut client, mut venue) = pair(now);
+        let logon = venue.on_message(now, &parse(&client.logon(now)));
+        send_all(&mut client, now, &logon);
+
+        let first = client.send(now, news("one")).unwrap();
+        let heartbeat = client.timer(now + Duration::from_secs(30));
+        let lost = client.send(now, news("two")).unwrap();
+        let third = client.send(now, news("three")).unwrap();
+        assert_eq!(client.get_next_outbound(), 6);
+
+        assert_eq!(venue.on_message(now, &parse(&first)), vec![Action::Deliver(first.clone())]);
+        send_all(&mut venue, now, &heartbeat);
+        drop(lost);
+        // 5 when 4 was expected
+        let request = venue.on_message(now, &parse(&third));
+        assert_eq!(sent(&request), vec![("2".to_string(), 2)]);
+        let Action::Send(bytes) = &request[0] else { panic!() };
+        assert_eq!((parse(bytes).get_parsed::<u64>(tag::BEGIN_SEQ_NO), parse(bytes).get_parsed::<u64>(tag::END_SEQ_NO)), (Ok(4), Ok(0)));
+
+        // answered with both again as possible duplicates
+        let resent = send_all(&mut client, now, &request);
+        assert_eq!(sent(&resent), vec![("B".to_string(), 4), ("B".to_string(), 5)]);
+        let delivered: Vec<String> = send_all(&mut venue, now, &resent).iter()
+            .map(|action| match action {
+                Action::Deliver(bytes) => parse(bytes).get_str(148).unwrap().to_string(),
+                other => panic!("{:?}", other),
+            })
+            .collect();
+        assert_eq!(delivered, vec!["two", "three"]);
+        assert_eq!(venue.get_next_inbound(), 6);
+
+        // a duplicate is dropped quietly; an old number without PossDupFlag ends it
+        assert!(send_all(&mut venue, now, &resent).is_empty());
+        let actions = venue.on_message(now, &parse(&first));
+        assert_eq!(actions.last(), Some(&Action::Disconnect("MsgSeqNum too low, expecting 6 but received 2".to_string())));
+
+        // admin messages come back as a gap fill
+        let mut request = MessageBuilder::new(Version::Fix44, "2").field(tag::BEGIN_SEQ_NO, 1).field(tag::END_SEQ_NO, 3);
+        request.push_header(tag::SENDER_COMP_ID, "VENUE").push_header(tag::TARGET_COMP_ID, "CLIENT").push_header(tag::MSG_SEQ_NUM, 3);
+        let resent = client.on_message(now, &parse(&request.build().unwrap()));
+        assert_eq!(sent(&resent), vec![("4".to_string(), 1), ("B".to_string(), 2), ("4".to_string(), 3)]);
+        let Action::Send(bytes) = &resent[2] else { panic!() };
+        assert_eq!(parse(bytes).get_parsed::<u64>(tag::NEW_SEQ_NO), Ok(4));
+        assert_eq!(parse(bytes).get_str(tag::POSS_DUP_FLAG), Ok("Y"));
+    }
+
+    #[test]
+    fn test_gap_fill_answers_the_resend(){
+        let now = Instant::now();
+        let (mut client, mut venue) = pair(now);
+        let logon = venue.on_message(now, &parse(&client.logon(now)));
+        send_all(&mut client, now, &logon);
+
+        // heartbeats 2 and 3 are lost
+        let heartbeat = |venue: &mut Session| venue.next(now, venue.admin(msg_type::HEARTBEAT));
+        heartbeat(&mut venue);
+        heartbeat(&mut venue);
+        let request = client.on_message(now, &parse(&heartbeat(&mut venue)));
+        assert_eq!(sent(&request), vec![("2".to_string(), 2)]);
+        let gap_fill = send_all(&mut venue, now, &request);
+        assert_eq!(sent(&gap_fill), vec![("4".to_string(), 2)]);
+        assert!(send_all(&mut client, now, &gap_fill).is_empty());
+        assert_eq!(client.get_next_inbound(), 5);
+
+        // so losing 5 is a new gap, asked for again
+        heartbeat(&mut venue);
+        let request = client.on_message(now, &parse(&heartbeat(&mut venue)));
+        assert_eq!(sent(&request), vec![("2".to_string(), 3)]);
+        let Action::Send(bytes) = &request[0] else { panic!() };
+        assert_eq!(parse(bytes).get_parsed::<u64>(tag::BEGIN_SEQ_NO), Ok(5));
+    }
+
+    #[test]
+    fn test_reset_seq_num_logon_mid_session(){
+        let now = Instant::now();
+        let (mut client, mut venue) = pair(now);
+        let logon = venue.on_message(now, &parse(&client.logon(now)));
+        send_all(&mut client, now, &logon);
+        let news_from_venue = venue.send(now, news("one")).unwrap();
+        client.on_message(now, &parse(&news_from_venue));
+        client.send(now, news("two")).unwrap();
+        assert_eq!((client.get_next_inbound(), client.get_next_outbound()), (3, 3));
+
+        // the venue starts its numbers again, and so do we
+        let mut reset = MessageBuilder::new(Version::Fix44, "A").field(tag::ENCRYPT_METHOD, 0).field(tag::HEART_BT_INT, 30).flag(tag::RESET_SEQ_NUM_FLAG, true);
+        reset.push_header(tag::SENDER_COMP_ID, "VENUE").push_header(tag::TARGET_COMP_ID, "CLIENT").push_header(tag::MSG_SEQ_NUM, 1);
+        let actions = client.on_message(now, &parse(&reset.build().unwrap()));
+        assert_eq!(sent(&actions), vec![("A".to_string(), 1)]);
+        let Action::Send(bytes) = &actions[0] else { panic!() };
+        assert_eq!(parse(bytes).get_str(tag::RESET_SEQ_NUM_FLAG), Ok("Y"));
+        assert_eq!((client.get_state(), client.get_next_inbound(), client.get_next_outbound()), (SessionState::Active, 2, 2));
+
+        // nothing from before the reset can be asked for again
+        let mut request = MessageBuilder::new(Version::Fix44, "2").field(tag::BEGIN_SEQ_NO, 1).field(tag::END_SEQ_NO, 0);
+        request.push_header(tag::SENDER_COMP_ID, "VENUE").push_header(tag::TARGET_COMP_ID, "CLIENT").push_header(tag::MSG_SEQ_NUM, 2);
+        let resent = client.on_message(now, &parse(&request.build().unwrap()));
+        assert_eq!(sent(&resent), vec![("4".to_string(), 1)]);
+
+        // without the flag a Logon mid-session still ends it
+        let mut logon = MessageBuilder::new(Version::Fix44, "A").field(tag::ENCRYPT_METHOD, 0).field(tag::HEART_BT_INT, 30);
+        logon.push_header(tag::SENDER_COMP_ID, "VENUE").push_header(tag::TARGET_COMP_ID, "CLIENT").push_header(tag::MSG_SEQ_NUM, 3);
+        let actions = client.on_message(now, &parse(&logon.build().unwrap()));
+        assert_eq!(actions.last(), Some(&Action::Disconnect("Logon while logged on".to_string())));
+    }
+
+    #[test]
+    fn test_test_request_is_answered_across_a_gap(){
+        let now = Instant::now();
+        let (mut client, mut venue) = pair(now);
+        let logon = venue.on_message(now, &parse(&client.logon(now)));
+        send_all(&mut client, now, &logon);
+
+        // heartbeat 2 is lost, so the TestRequest at 3 is ahead of the gap
+        venue.next(now, venue.admin(msg_type::HEARTBEAT));
+        let test_request = venue.next(now, venue.admin(msg_type::TEST_REQUEST).field(tag::TEST_REQ_ID, "PING"));
+        let actions = client.on_message(now, &parse(&test_request));
+        assert_eq!(sent(&actions), vec![("2".to_string(), 2), ("0".to_string(), 3)]);
+        let Action::Send(bytes) = &actions[1] else { panic!() };
+        assert_eq!(parse(bytes).get_str(tag::TEST_REQ_ID), Ok("PING"));
+
+        // both come back as one gap fill, and nothing is answered twice
+        let gap_fill = send_all(&mut venue, now, &actions[..1]);
+        assert_eq!(sent(&gap_fill), vec![("4".to_string(), 2)]);
+        assert!(send_all(&mut client, now, &gap_fill).is_empty());
+        assert_eq!(client.get_next_inbound(), 4);
+    }
+
+    #[test]
+    fn test_logout_handshake(){
+        let now = Instant::now();
+        let (mut client, mut venue) = pair(now);
+        let logon = venue.on_message(now, &parse(&client.logon(now)));
+        send_all(&mut client, now, &logon);
+
+        let logout = client.logout(now, "done");
+        let reply = venue.on_message(now, &parse(&logout));
+        assert_eq!(sent(&reply), vec![("5".to_string(), 2)]);
+        assert_eq!(reply.last(), Some(&Action::Disconnect("logged out".to_string())));
+        assert_eq!(send_all(&mut client, now, &reply), vec![Action::Disconnect("logged out".to_string())]);
+        assert_eq!((client.get_state(), venue.get_state()), (SessionState::Closed, SessionState::Closed));
+
+        // a Text that can't be encoded is left off rather than breaking the Logout
+        let (mut client, _) = pair(now);
+        for text in ["", "bad\x01text"] {
+            let logout = client.logout(now, text);
+            assert_eq!(parse(&logout).msg_type(), "5");
+            assert!(parse(&logout).get(tag::TEXT).is_none());
+        }
+
+        assert_eq!(skip_garbled(b"8=FIX.4.4\x019=x8=FIX.4.4"), 13);
+        assert_eq!(skip_garbled(b"garbage"), 7);
+    }
+}
@@ -0,0 +1,264 @@
+// Order manager: follows every order we send from PendingNew to a terminal state,
+// fed either by a local `Orderbook` (through the helpers below) or by a venue
+// connection calling `apply` with what the venue reports. `positions` turns the
+// fills into positions and P&L.
+pub mod order;
+pub mod positions;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use orderbook::orderbook::{ModifyOutcome, Order, OrderModify, OrderType, Orderbook, Price, Quantity, Symbol, Trade};
+use orderbook::price::{PriceType, QuantityType};
+use tracing::debug;
+pub use order::{ManagedOrder, OmsError, OrderEvent, OrderId, OrderState};
+
+type OrderPointer<P, Q> = Arc<Mutex<Order<P, Q>>>;
+type Trades<P, Q> = Vec<Trade<P, Q>>;
+
+#[derive(Debug, Default)]
+pub struct OrderManager<P = Price, Q = Quantity> {
+    orders: HashMap<OrderId, ManagedOrder<P, Q>>,
+}
+
+impl<P: PriceType, Q: QuantityType> OrderManager<P, Q> {
+    pub fn new() -> Self {
+        Self { orders: HashMap::new() }
+    }
+
+    pub fn get_order(&self, order_id: OrderId) -> Option<&ManagedOrder<P, Q>> {
+        self.orders.get(&order_id)
+    }
+
+    // orders not yet in a terminal state
+    pub fn open_orders(&self) -> impl Iterator<Item = &ManagedOrder<P, Q>> {
+        self.orders.values().filter(|order| !order.get_state().is_terminal())
+    }
+
+    // starts following a new order in PendingNew
+    pub fn track(&mut self, order: ManagedOrder<P, Q>) -> Result<(), OmsError> {
+        let order_id = order.get_order_id();
+        if self.orders.contains_key(&order_id) {
+            return Err(OmsError::DuplicateOrderId(order_id));
+        }
+        self.orders.insert(order_id, order);
+        Ok(())
+    }
+
+    pub fn apply(&mut self, order_id: OrderId, event: OrderEvent<P, Q>) -> Result<OrderState, OmsError> {
+        let order = self.orders.get_mut(&order_id).ok_or(OmsError::UnknownOrder(order_id))?;
+        let from = order.get_state();
+        let result = order.apply(event);
+        match result {
+            Ok(to) => debug!(order_id, ?from, ?to, event = event.name(), "order state"),
+            Err(error) => debug!(order_id, ?from, event = event.name(), ?error, "order event refused"),
+        }
+        result
+    }
+
+    // Fills both sides of each trade that belong to us; the other side is someone else's.
+    pub fn apply_trades(&mut self, trades: &[Trade<P, Q>]) -> Result<(), OmsError> {
+        for trade in trades {
+            for info in [trade.get_bid_trade(), trade.get_ask_trade()] {
+                if self.orders.contains_key(&info.order_id) {
+                    self.apply(info.order_id, OrderEvent::Fill { price: trade.get_price(), quantity: info.quantity })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Sends a new order to a local book. The book answers synchronously, so the
+    // order is acknowledged (or rejected) and filled in one go. Whatever is left
+    // of an immediate order once the book lets go of it is cancelled.
+    pub fn submit(&mut self, book: &Orderbook<P, Q>, symbol: Symbol, order: OrderPointer<P, Q>) -> Result<Trades<P, Q>, OmsError> {
+        let managed = {
+            let ord = order.lock().unwrap();
+            ManagedOrder::new(ord.get_order_id(), symbol, ord.get_side(), ord.get_order_type(), ord.get_price(), ord.get_remaining_quantity())
+        };
+        let order_id = managed.get_order_id();
+        // the book would refuse it, and a later cancel would pull someone else's order
+        if book.contains(order_id) {
+            return Err(OmsError::DuplicateOrderId(order_id));
+        }
+        self.track(managed)?;
+
+        let trades = book.add_order(order);
+        let traded = trades.iter().any(|trade| trade.get_bid_trade().order_id == order_id || trade.get_ask_trade().order_id == order_id);
+        if !traded && !book.contains(order_id) {
+            self.apply(order_id, OrderEvent::Rejected)?;
+            return Ok(trades);
+        }
+
+        self.apply(order_id, OrderEvent::Acknowledged)?;
+        self.apply_trades(&trades)?;
+        if !book.contains(order_id) && !self.orders[&order_id].get_state().is_terminal() {
+            self.apply(order_id, OrderEvent::Cancelled)?;
+        }
+        Ok(trades)
+    }
+
+    pub fn cancel(&mut self, book: &Orderbook<P, Q>, order_id: OrderId) -> Result<Trades<P, Q>, OmsError> {
+        self.apply(order_id, OrderEvent::CancelRequested)?;
+        let resting = book.contains(order_id);
+        // repricing pegs can trade once the order is gone
+        let trades = book.cancel_order(order_id);
+        self.apply(order_id, if resting { OrderEvent::Cancelled } else { OrderEvent::CancelRejected })?;
+        self.apply_trades(&trades)?;
+        Ok(trades)
+    }
+
+    pub fn replace(&mut self, book: &Orderbook<P, Q>, modify: OrderModify<P, Q>) -> Result<ModifyOutcome<P, Q>, OmsError> {
+        let order_id = modify.get_order_id();
+        let (side, price, quantity) = (modify.get_side(), modify.get_price(), modify.get_quantity());
+        self.apply(order_id, OrderEvent::ReplaceRequested { side, price, quantity })?;
+        let outcome = book.modify_order(modify);
+        match &outcome {
+            ModifyOutcome::AmendedInPlace(trades) | ModifyOutcome::Replaced(trades) => {
+                self.apply(order_id, OrderEvent::Replaced)?;
+                self.apply_trades(trades)?;
+            }
+            // sized at or below what had already filled
+            ModifyOutcome::Cancelled(trades) => {
+                self.apply(order_id, OrderEvent::Cancelled)?;
+                self.apply_trades(trades)?;
+            }
+            ModifyOutcome::Rejected(_) => {
+                self.apply(order_id, OrderEvent::ReplaceRejected)?;
+            }
+        }
+        Ok(outcome)
+    }
+
+    // Good for day orders on `symbol` that the book has dropped without a fill or a
+    // cancel from us have expired. Returns the ones moved to Expired.
+    pub fn reconcile(&mut self, symbol: &Symbol, book: &Orderbook<P, Q>) -> Result<Vec<OrderId>, OmsError> {
+        let expired: Vec<OrderId> = self
+            .open_orders()
+            .filter(|order| order.get_symbol() == symbol && order.get_order_type() == OrderType::GoodForDay)
+            .filter(|order| order.get_state() != OrderState::PendingNew && !book.contains(order.get_order_id()))
+            .map(|order| order.get_order_id())
+            .collect();
+        for order_id in &expired {
+            self.apply(*order_id, OrderEvent::Expired)?;
+        }
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use orderbook::orderbook::Side;
+
+    fn symbol() -> Symbol {
+        "AAPL".to_string()
+    }
+
+    #[test]
+    fn test_orders_follow_the_book(){
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut oms = OrderManager::new();
+
+        oms.submit(&book, symbol(), Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10)).unwrap();
+        assert_eq!(oms.get_order(1).unwrap().get_state(), OrderState::New);
+
+        // someone else's sell fills us in part
+        let trades = book.add_order(Order::new(OrderType::GoodTillCancel, 50, Side::Sell, 100, 4));
+        oms.apply_trades(&trades).unwrap();
+        let order = oms.get_order(1).unwrap();
+        assert_eq!(order.get_state(), OrderState::PartiallyFilled);
+        assert_eq!((order.get_cum_quantity(), order.get_leaves_quantity()), (4, 6));
+
+        oms.replace(&book, OrderModify::new(1, Side::Buy, 101, 12)).unwrap();
+        let order = oms.get_order(1).unwrap();
+        assert_eq!(order.get_state(), OrderState::PartiallyFilled);
+        assert_eq!((order.get_price(), order.get_leaves_quantity()), (Some(Price::from(101)), 8));
+
+        oms.cancel(&book, 1).unwrap();
+        let order = oms.get_order(1).unwrap();
+        assert_eq!(order.get_state(), OrderState::Cancelled);
+        assert_eq!(
+            order.get_history(),
+            &[
+                OrderState::PendingNew,
+                OrderState::New,
+                OrderState::PartiallyFilled,
+                OrderState::PendingReplace,
+                OrderState::PartiallyFilled,
+                OrderState::PendingCancel,
+                OrderState::Cancelled,
+            ]
+        );
+        assert!(matches!(oms.cancel(&book, 1), Err(OmsError::IllegalTransition { .. })));
+        assert_eq!(oms.cancel(&book, 9).unwrap_err(), OmsError::UnknownOrder(9));
+        assert_eq!(oms.open_orders().count(), 0);
+    }
+
+    #[test]
+    fn test_rejects_and_immediate_orders(){
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut oms = OrderManager::new();
+
+        // a market order into an empty book is refused
+        oms.submit(&book, symbol(), Order::new_market(1, Side::Buy, 10)).unwrap();
+        assert_eq!(oms.get_order(1).unwrap().get_state(), OrderState::Rejected);
+        assert_eq!(
+            oms.submit(&book, symbol(), Order::new_market(1, Side::Buy, 10)).unwrap_err(),
+            OmsError::DuplicateOrderId(1)
+        );
+
+        // both sides are ours: the resting sell fills, the rest of the fill and kill is cancelled
+        oms.submit(&book, symbol(), Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 100, 5)).unwrap();
+        oms.submit(&book, symbol(), Order::new(OrderType::FillAndKill, 3, Side::Buy, 100, 8)).unwrap();
+        assert_eq!(oms.get_order(2).unwrap().get_state(), OrderState::Filled);
+        let order = oms.get_order(3).unwrap();
+        assert_eq!(order.get_state(), OrderState::Cancelled);
+        assert_eq!(order.get_cum_quantity(), 5);
+        assert_eq!(order.get_avg_price(), 100.0);
+    }
+
+    #[test]
+    fn test_replace_can_change_side(){
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut oms = OrderManager::new();
+        oms.submit(&book, symbol(), Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10)).unwrap();
+
+        oms.replace(&book, OrderModify::new(1, Side::Sell, 102, 10)).unwrap();
+        let order = oms.get_order(1).unwrap();
+        assert_eq!((order.get_state(), order.get_side(), order.get_price()), (OrderState::New, Side::Sell, Some(Price::from(102))));
+        assert_eq!(book.best_ask(), Some(Price::from(102)));
+    }
+
+    #[test]
+    fn test_submit_refuses_an_id_already_on_the_book(){
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut oms = OrderManager::new();
+        book.add_order(Order::new(OrderType::GoodTillCancel, 7, Side::Sell, 101, 5));
+
+        assert_eq!(
+            oms.submit(&book, symbol(), Order::new(OrderType::GoodTillCancel, 7, Side::Buy, 100, 10)).unwrap_err(),
+            OmsError::DuplicateOrderId(7)
+        );
+        assert!(oms.get_order(7).is_none());
+        assert_eq!(oms.cancel(&book, 7).unwrap_err(), OmsError::UnknownOrder(7));
+        assert!(book.contains(7));
+    }
+
+    #[test]
+    fn test_reconcile_expires_dropped_day_orders(){
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut oms = OrderManager::new();
+        oms.submit(&book, symbol(), Order::new(OrderType::GoodForDay, 1, Side::Buy, 100, 10)).unwrap();
+        oms.submit(&book, symbol(), Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 99, 10)).unwrap();
+        assert!(oms.reconcile(&symbol(), &book).unwrap().is_empty());
+
+        // what the end of day prune does to the book
+        book.cancel_order(1);
+        assert_eq!(oms.reconcile(&symbol(), &book).unwrap(), vec![1]);
+        assert_eq!(oms.get_order(1).unwrap().get_state(), OrderState::Expired);
+        assert_eq!(oms.get_order(2).unwrap().get_state(), OrderState::New);
+    }
+}
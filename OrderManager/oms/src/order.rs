@@ -0,0 +1,289 @@
+use orderbook::orderbook::{OrderType, Price, Quantity, Side, Symbol};
+use orderbook::price::{PriceType, QuantityType};
+
+pub type OrderId = u32;
+
+//  PendingNew -> New -> PartiallyFilled -> Filled
+//       |         |            |
+//       |         +------------+-> PendingCancel -> Cancelled
+//       |         +------------+-> PendingReplace -> New / PartiallyFilled
+//       +-> Rejected          +-> Expired
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum OrderState {
+    PendingNew,
+    New,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected,
+    Expired,
+    PendingCancel,
+    PendingReplace,
+}
+
+impl OrderState {
+    pub const fn is_terminal(&self) -> bool {
+        matches!(self, OrderState::Filled | OrderState::Cancelled | OrderState::Rejected | OrderState::Expired)
+    }
+
+    pub const fn is_pending(&self) -> bool {
+        matches!(self, OrderState::PendingNew | OrderState::PendingCancel | OrderState::PendingReplace)
+    }
+}
+
+// What the venue (or the book) tells us happened to an order. Requests we send
+// ourselves, like CancelRequested, move the order into a pending state until the
+// venue answers.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OrderEvent<P = Price, Q = Quantity> {
+    Acknowledged,
+    Rejected,
+    Fill { price: P, quantity: Q },
+    CancelRequested,
+    Cancelled,
+    CancelRejected,
+    // quantity is the new total size, fills so far included
+    ReplaceRequested { side: Side, price: P, quantity: Q },
+    Replaced,
+    ReplaceRejected,
+    Expired,
+}
+
+impl<P, Q> OrderEvent<P, Q> {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            OrderEvent::Acknowledged => "Acknowledged",
+            OrderEvent::Rejected => "Rejected",
+            OrderEvent::Fill { .. } => "Fill",
+            OrderEvent::CancelRequested => "CancelRequested",
+            OrderEvent::Cancelled => "Cancelled",
+            OrderEvent::CancelRejected => "CancelRejected",
+            OrderEvent::ReplaceRequested { .. } => "ReplaceRequested",
+            OrderEvent::Replaced => "Replaced",
+            OrderEvent::ReplaceRejected => "ReplaceRejected",
+            OrderEvent::Expired => "Expired",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OmsError {
+    UnknownOrder(OrderId),
+    DuplicateOrderId(OrderId),
+    IllegalTransition { order_id: OrderId, from: OrderState, event: &'static str },
+    // a fill for more than the order had left
+    Overfill(OrderId),
+}
+
+#[derive(Clone, Debug)]
+pub struct ManagedOrder<P = Price, Q = Quantity> {
+    order_id: OrderId,
+    symbol: Symbol,
+    side: Side,
+    order_type: OrderType,
+    price: Option<P>,
+    quantity: Q,
+    cum_quantity: Q,
+    leaves_quantity: Q,
+    avg_price: f64,
+    state: OrderState,
+    // side, price and size asked for by a replace still waiting on the venue
+    pending_replace: Option<(Side, P, Q)>,
+    history: Vec<OrderState>,
+}
+
+impl<P: PriceType, Q: QuantityType> ManagedOrder<P, Q> {
+    pub fn new(order_id: OrderId, symbol: Symbol, side: Side, order_type: OrderType, price: Option<P>, quantity: Q) -> Self {
+        Self {
+            order_id,
+            symbol,
+            side,
+            order_type,
+            price,
+            quantity,
+            cum_quantity: Q::zero(),
+            leaves_quantity: quantity,
+            avg_price: 0.0,
+            state: OrderState::PendingNew,
+            pending_replace: None,
+            history: vec![OrderState::PendingNew],
+        }
+    }
+
+    pub const fn get_order_id(&self) -> OrderId {
+        self.order_id
+    }
+    pub fn get_symbol(&self) -> &Symbol {
+        &self.symbol
+    }
+    pub const fn get_side(&self) -> Side {
+        self.side
+    }
+    pub const fn get_order_type(&self) -> OrderType {
+        self.order_type
+    }
+    pub const fn get_price(&self) -> Option<P> {
+        self.price
+    }
+    pub const fn get_quantity(&self) -> Q {
+        self.quantity
+    }
+    pub const fn get_cum_quantity(&self) -> Q {
+        self.cum_quantity
+    }
+    pub const fn get_leaves_quantity(&self) -> Q {
+        self.leaves_quantity
+    }
+    // volume weighted price of the fills so far; 0 before the first
+    pub const fn get_avg_price(&self) -> f64 {
+        self.avg_price
+    }
+    pub const fn get_state(&self) -> OrderState {
+        self.state
+    }
+    // every state the order has been in, oldest first
+    pub fn get_history(&self) -> &[OrderState] {
+        &self.history
+    }
+
+    // New or PartiallyFilled, whichever the fills so far make it
+    fn working_state(&self) -> OrderState {
+        if self.cum_quantity == Q::zero() { OrderState::New } else { OrderState::PartiallyFilled }
+    }
+
+    pub fn apply(&mut self, event: OrderEvent<P, Q>) -> Result<OrderState, OmsError> {
+        use OrderState::*;
+        let illegal = OmsError::IllegalTransition { order_id: self.order_id, from: self.state, event: event.name() };
+        let next = match (self.state, event) {
+            (PendingNew, OrderEvent::Acknowledged) => New,
+            (PendingNew, OrderEvent::Rejected) => Rejected,
+
+            // a fill can beat the ack, and can land while a cancel or replace is in flight
+            (PendingNew | New | PartiallyFilled | PendingCancel | PendingReplace, OrderEvent::Fill { price, quantity }) => {
+                if quantity > self.leaves_quantity {
+                    return Err(OmsError::Overfill(self.order_id));
+                }
+                let cum = self.cum_quantity + quantity;
+                self.avg_price = (self.avg_price * self.cum_quantity.to_f64() + price.to_f64() * quantity.to_f64()) / cum.to_f64();
+                self.cum_quantity = cum;
+                self.leaves_quantity -= quantity;
+                match self.state {
+                    _ if self.leaves_quantity == Q::zero() => {
+                        self.pending_replace = None;
+                        Filled
+                    }
+                    PendingCancel | PendingReplace => self.state,
+                    _ => PartiallyFilled,
+                }
+            }
+
+            (New | PartiallyFilled, OrderEvent::CancelRequested) => PendingCancel,
+            (New | PartiallyFilled | PendingCancel | PendingReplace, OrderEvent::Cancelled) => {
+                self.leaves_quantity = Q::zero();
+                self.pending_replace = None;
+                Cancelled
+            }
+            (PendingCancel, OrderEvent::CancelRejected) => self.working_state(),
+
+            (New | PartiallyFilled, OrderEvent::ReplaceRequested { side, price, quantity }) => {
+                self.pending_replace = Some((side, price, quantity));
+                PendingReplace
+            }
+            (PendingReplace, OrderEvent::Replaced) => {
+                let (side, price, quantity) = self.pending_replace.ok_or(illegal)?;
+                if quantity <= self.cum_quantity {
+                    return Err(illegal);
+                }
+                self.pending_replace = None;
+                self.side = side;
+                self.price = Some(price);
+                self.quantity = quantity;
+                self.leaves_quantity = quantity - self.cum_quantity;
+                self.working_state()
+            }
+            (PendingReplace, OrderEvent::ReplaceRejected) => {
+                self.pending_replace = None;
+                self.working_state()
+            }
+
+            (New | PartiallyFilled | PendingCancel | PendingReplace, OrderEvent::Expired) => {
+                self.leaves_quantity = Q::zero();
+                self.pending_replace = None;
+                Expired
+            }
+            _ => return Err(illegal),
+        };
+        if next != self.state {
+            self.history.push(next);
+        }
+        self.state = next;
+        Ok(next)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn order() -> ManagedOrder {
+        ManagedOrder::new(1, "AAPL".to_string(), Side::Buy, OrderType::GoodTillCancel, Some(Price::from(100)), 10)
+    }
+
+    #[test]
+    fn test_fills_track_cum_leaves_and_average(){
+        let mut order = order();
+        assert_eq!(order.apply(OrderEvent::Acknowledged), Ok(OrderState::New));
+        assert_eq!(order.apply(OrderEvent::Fill { price: Price::from(100), quantity: 4 }), Ok(OrderState::PartiallyFilled));
+        assert_eq!(order.apply(OrderEvent::Fill { price: Price::from(99), quantity: 6 }), Ok(OrderState::Filled));
+        assert_eq!(order.get_cum_quantity(), 10);
+        assert_eq!(order.get_leaves_quantity(), 0);
+        assert!((order.get_avg_price() - 99.4).abs() < 1e-9);
+        assert_eq!(order.get_history(), &[OrderState::PendingNew, OrderState::New, OrderState::PartiallyFilled, OrderState::Filled]);
+
+        // nothing moves a filled order
+        assert_eq!(
+            order.apply(OrderEvent::Cancelled),
+            Err(OmsError::IllegalTransition { order_id: 1, from: OrderState::Filled, event: "Cancelled" })
+        );
+        let mut order = self::order();
+        assert_eq!(order.apply(OrderEvent::Fill { price: Price::from(100), quantity: 11 }), Err(OmsError::Overfill(1)));
+    }
+
+    #[test]
+    fn test_pending_cancel_and_replace(){
+        let mut order = order();
+        order.apply(OrderEvent::Acknowledged).unwrap();
+        assert_eq!(order.apply(OrderEvent::CancelRequested), Ok(OrderState::PendingCancel));
+        // filled in part before the cancel was seen, then the cancel is refused
+        assert_eq!(order.apply(OrderEvent::Fill { price: Price::from(100), quantity: 3 }), Ok(OrderState::PendingCancel));
+        assert_eq!(order.apply(OrderEvent::CancelRejected), Ok(OrderState::PartiallyFilled));
+
+        assert_eq!(order.apply(OrderEvent::ReplaceRequested { side: Side::Buy, price: Price::from(101), quantity: 20 }), Ok(OrderState::PendingReplace));
+        assert!(order.apply(OrderEvent::CancelRequested).is_err());
+        assert_eq!(order.apply(OrderEvent::Replaced), Ok(OrderState::PartiallyFilled));
+        assert_eq!(order.get_price(), Some(Price::from(101)));
+        assert_eq!(order.get_leaves_quantity(), 17);
+
+        assert_eq!(order.apply(OrderEvent::Expired), Ok(OrderState::Expired));
+        assert_eq!(order.get_leaves_quantity(), 0);
+
+        let mut rejected = self::order();
+        assert_eq!(rejected.apply(OrderEvent::Rejected), Ok(OrderState::Rejected));
+        assert!(rejected.apply(OrderEvent::Acknowledged).is_err());
+    }
+
+    #[test]
+    fn test_refused_replace_keeps_the_request(){
+        let mut order = order();
+        order.apply(OrderEvent::Acknowledged).unwrap();
+        order.apply(OrderEvent::Fill { price: Price::from(100), quantity: 3 }).unwrap();
+        order.apply(OrderEvent::ReplaceRequested { side: Side::Buy, price: Price::from(101), quantity: 4 }).unwrap();
+        // filled up to the new size while the replace was in flight
+        order.apply(OrderEvent::Fill { price: Price::from(100), quantity: 1 }).unwrap();
+
+        assert!(order.apply(OrderEvent::Replaced).is_err());
+        assert_eq!(order.get_state(), OrderState::PendingReplace);
+        assert_eq!(order.pending_replace, Some((Side::Buy, Price::from(101), 4)));
+        assert_eq!(order.apply(OrderEvent::ReplaceRejected), Ok(OrderState::PartiallyFilled));
+    }
+}
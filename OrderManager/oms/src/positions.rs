@@ -0,0 +1,447 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::{self, Write},
+};
+use orderbook::fees::FeeEngine;
+use orderbook::orderbook::{Orderbook, Quantity, Side, Symbol, Trade};
+use orderbook::price::{PriceType, QuantityType};
+use orderbook::risk::AccountId;
+use orderbook::stats::Timestamp;
+use crate::order::OrderId;
+
+// How closing fills are matched against the open position to realize P&L.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CostMethod {
+    // against the oldest open lots first
+    Fifo,
+    // against the position's average cost
+    AverageCost,
+}
+
+// Where unrealized P&L is marked from.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MarkSource {
+    Mid,
+    Last,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Lot<Q> {
+    price: f64,
+    quantity: Q,
+}
+
+#[derive(Clone, Debug)]
+pub struct Position<Q = Quantity> {
+    method: CostMethod,
+    // direction of the open lots; None when flat
+    side: Option<Side>,
+    // oldest first; a single lot at the average cost under AverageCost
+    lots: VecDeque<Lot<Q>>,
+    realized: f64,
+    bought: Q,
+    sold: Q,
+    traded_notional: f64,
+    // net of rebates
+    fees: f64,
+}
+
+impl<Q: QuantityType> Position<Q> {
+    pub fn new(method: CostMethod) -> Self {
+        Self {
+            method,
+            side: None,
+            lots: VecDeque::new(),
+            realized: 0.0,
+            bought: Q::zero(),
+            sold: Q::zero(),
+            traded_notional: 0.0,
+            fees: 0.0,
+        }
+    }
+
+    pub fn on_fill(&mut self, side: Side, price: f64, quantity: Q) {
+        match side {
+            Side::Buy => self.bought += quantity,
+            Side::Sell => self.sold += quantity,
+        }
+        self.traded_notional += price * quantity.to_f64();
+
+        let mut left = quantity;
+        if let Some(open_side) = self.side.filter(|open_side| *open_side != side) {
+            // closing: a sale above cost is a gain on a long, a loss on a short
+            let direction = if open_side == Side::Buy { 1.0 } else { -1.0 };
+            while left > Q::zero() {
+                let Some(lot) = self.lots.front_mut() else { break };
+                let take = left.min(lot.quantity);
+                self.realized += (price - lot.price) * take.to_f64() * direction;
+                lot.quantity -= take;
+                left -= take;
+                if lot.quantity == Q::zero() {
+                    self.lots.pop_front();
+                }
+            }
+            if self.lots.is_empty() {
+                self.side = None;
+            }
+        }
+        if left == Q::zero() {
+            return;
+        }
+
+        // opening, or what's left after flipping through flat
+        self.side = Some(side);
+        match (self.method, self.lots.front_mut()) {
+            (CostMethod::AverageCost, Some(lot)) => {
+                let total = lot.quantity + left;
+                lot.price = (lot.price * lot.quantity.to_f64() + price * left.to_f64()) / total.to_f64();
+                lot.quantity = total;
+            }
+            _ => self.lots.push_back(Lot { price, quantity: left }),
+        }
+    }
+
+    pub fn get_side(&self) -> Option<Side> {
+        self.side
+    }
+
+    // open quantity, whichever way
+    pub fn get_quantity(&self) -> Q {
+        self.lots.iter().fold(Q::zero(), |total, lot| total + lot.quantity)
+    }
+
+    // long positive, short negative
+    pub fn net_quantity(&self) -> f64 {
+        match self.side {
+            Some(Side::Buy) => self.get_quantity().to_f64(),
+            Some(Side::Sell) => -self.get_quantity().to_f64(),
+            None => 0.0,
+        }
+    }
+
+    // cost of the open quantity per unit; None when flat
+    pub fn average_cost(&self) -> Option<f64> {
+        let quantity = self.get_quantity().to_f64();
+        if quantity == 0.0 {
+            return None;
+        }
+        Some(self.lots.iter().map(|lot| lot.price * lot.quantity.to_f64()).sum::<f64>() / quantity)
+    }
+
+    // a rebate is a negative fee
+    pub fn charge_fee(&mut self, amount: f64) {
+        self.fees += amount;
+    }
+
+    // closed out P&L less fees paid, plus rebates earned
+    pub fn realized_pnl(&self) -> f64 {
+        self.realized - self.fees
+    }
+
+    pub fn unrealized_pnl(&self, mark: f64) -> f64 {
+        self.average_cost().map_or(0.0, |cost| (mark - cost) * self.net_quantity())
+    }
+
+    // signed market value of the open position
+    pub fn exposure(&self, mark: f64) -> f64 {
+        self.net_quantity() * mark
+    }
+
+    pub fn get_bought(&self) -> Q {
+        self.bought
+    }
+    pub fn get_sold(&self) -> Q {
+        self.sold
+    }
+    // price times quantity of every fill, buys and sells
+    pub fn get_traded_notional(&self) -> f64 {
+        self.traded_notional
+    }
+    pub fn get_fees(&self) -> f64 {
+        self.fees
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PositionReport {
+    pub account: AccountId,
+    pub symbol: Symbol,
+    pub net_quantity: f64,
+    pub average_cost: Option<f64>,
+    // None until the symbol has been marked
+    pub mark: Option<f64>,
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+    pub exposure: f64,
+    pub traded_notional: f64,
+    // already taken out of realized_pnl
+    pub fees: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct PnlTotals {
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+    // sum of absolute exposures
+    pub gross_exposure: f64,
+    // longs minus shorts
+    pub net_exposure: f64,
+    pub traded_notional: f64,
+    pub fees: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub timestamp: Timestamp,
+    pub positions: Vec<PositionReport>,
+}
+
+impl Snapshot {
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "timestamp,account,symbol,net_quantity,average_cost,mark,realized_pnl,unrealized_pnl,exposure,traded_notional,fees")?;
+        let optional = |value: Option<f64>| value.map_or(String::new(), |value| value.to_string());
+        for report in &self.positions {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{},{}",
+                self.timestamp,
+                report.account,
+                report.symbol,
+                report.net_quantity,
+                optional(report.average_cost),
+                optional(report.mark),
+                report.realized_pnl,
+                report.unrealized_pnl,
+                report.exposure,
+                report.traded_notional,
+                report.fees,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Positions per account and symbol, built from the fills the books return.
+// Orders are registered against an account so either side of a `Trade` can be
+// credited; fills on unregistered orders belong to someone else. With a fee
+// engine set, each of those fills is also charged as maker or taker.
+#[derive(Debug)]
+pub struct PositionKeeper<Q = Quantity> {
+    method: CostMethod,
+    // BTreeMap so reports come out in a stable order
+    positions: BTreeMap<(AccountId, Symbol), Position<Q>>,
+    owners: HashMap<OrderId, (AccountId, Symbol)>,
+    marks: HashMap<Symbol, f64>,
+    snapshots: Vec<Snapshot>,
+    fees: Option<FeeEngine>,
+}
+
+impl<Q: QuantityType> PositionKeeper<Q> {
+    pub fn new(method: CostMethod) -> Self {
+        Self {
+            method,
+            positions: BTreeMap::new(),
+            owners: HashMap::new(),
+            marks: HashMap::new(),
+            snapshots: vec![],
+            fees: None,
+        }
+    }
+
+    pub fn set_fee_engine(&mut self, engine: FeeEngine) {
+        self.fees = Some(engine);
+    }
+
+    pub fn get_fee_engine(&self) -> Option<&FeeEngine> {
+        self.fees.as_ref()
+    }
+
+    pub fn register_order(&mut self, order_id: OrderId, account: AccountId, symbol: Symbol) {
+        self.owners.insert(order_id, (account, symbol));
+    }
+
+    pub fn on_fill(&mut self, account: AccountId, symbol: &Symbol, side: Side, price: f64, quantity: Q) {
+        let method = self.method;
+        self.positions
+            .entry((account, symbol.clone()))
+            .or_insert_with(|| Position::new(method))
+            .on_fill(side, price, quantity);
+    }
+
+    // for fees reported by the venue rather than worked out here
+    pub fn on_fee(&mut self, account: AccountId, symbol: &Symbol, amount: f64) {
+        let method = self.method;
+        self.positions
+            .entry((account, symbol.clone()))
+            .or_insert_with(|| Position::new(method))
+            .charge_fee(amount);
+    }
+
+    pub fn on_trades<P: PriceType>(&mut self, trades: &[Trade<P, Q>]) {
+        for trade in trades {
+            let price = trade.get_price().to_f64();
+            for (side, info) in [(Side::Buy, trade.get_bid_trade()), (Side::Sell, trade.get_ask_trade())] {
+                if let Some((account, symbol)) = self.owners.get(&info.order_id).cloned() {
+                    self.on_fill(account, &symbol, side, price, info.quantity);
+                    if let Some(engine) = &mut self.fees {
+                        let charge = engine.charge_trade(account, &symbol, trade, side);
+                        self.on_fee(account, &symbol, charge.amount);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn mark(&mut self, symbol: &Symbol, price: f64) {
+        self.marks.insert(symbol.clone(), price);
+    }
+
+    // Marks `symbol` off its book. Leaves the old mark alone if the book has no
+    // two-sided market (for Mid) or hasn't traded yet (for Last).
+    pub fn mark_from_book<P: PriceType>(&mut self, symbol: &Symbol, book: &Orderbook<P, Q>, source: MarkSource) {
+        let price = match source {
+            MarkSource::Mid => book.mid(),
+            MarkSource::Last => book.get_last_trade_price().map(|price| price.to_f64()),
+        };
+        if let Some(price) = price {
+            self.mark(symbol, price);
+        }
+    }
+
+    pub fn get_position(&self, account: AccountId, symbol: &Symbol) -> Option<&Position<Q>> {
+        self.positions.get(&(account, symbol.clone()))
+    }
+
+    // one report per symbol the account has traded; an unmarked position has no unrealized P&L
+    pub fn report(&self, account: AccountId) -> Vec<PositionReport> {
+        self.reports().filter(|report| report.account == account).collect()
+    }
+
+    pub fn totals(&self, account: AccountId) -> PnlTotals {
+        self.report(account).iter().fold(PnlTotals::default(), |mut totals, report| {
+            totals.realized_pnl += report.realized_pnl;
+            totals.unrealized_pnl += report.unrealized_pnl;
+            totals.gross_exposure += report.exposure.abs();
+            totals.net_exposure += report.exposure;
+            totals.traded_notional += report.traded_notional;
+            totals.fees += report.fees;
+            totals
+        })
+    }
+
+    // Freezes every account's positions at the current marks and keeps the copy.
+    pub fn end_of_day(&mut self, timestamp: Timestamp) -> &Snapshot {
+        let positions = self.reports().collect();
+        self.snapshots.push(Snapshot { timestamp, positions });
+        self.snapshots.last().unwrap()
+    }
+
+    pub fn get_snapshots(&self) -> &[Snapshot] {
+        &self.snapshots
+    }
+
+    fn reports(&self) -> impl Iterator<Item = PositionReport> + '_ {
+        self.positions.iter().map(|((account, symbol), position)| {
+            let mark = self.marks.get(symbol).copied();
+            PositionReport {
+                account: *account,
+                symbol: symbol.clone(),
+                net_quantity: position.net_quantity(),
+                average_cost: position.average_cost(),
+                mark,
+                realized_pnl: position.realized_pnl(),
+                unrealized_pnl: mark.map_or(0.0, |mark| position.unrealized_pnl(mark)),
+                exposure: mark.map_or(0.0, |mark| position.exposure(mark)),
+                traded_notional: position.get_traded_notional(),
+                fees: position.get_fees(),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use orderbook::fees::FeeSchedule;
+    use orderbook::orderbook::{Order, OrderType};
+
+    #[test]
+    fn test_fifo_and_average_cost(){
+        let fills = [(Side::Buy, 100.0, 10u64), (Side::Buy, 110.0, 10), (Side::Sell, 120.0, 15)];
+        let mut fifo = Position::new(CostMethod::Fifo);
+        let mut average = Position::new(CostMethod::AverageCost);
+        for (side, price, quantity) in fills {
+            fifo.on_fill(side, price, quantity);
+            average.on_fill(side, price, quantity);
+        }
+        // FIFO sells the 100s then five 110s; average cost sells fifteen at 105
+        assert_eq!(fifo.realized_pnl(), 10.0 * 20.0 + 5.0 * 10.0);
+        assert_eq!(average.realized_pnl(), 15.0 * 15.0);
+        assert_eq!(fifo.average_cost(), Some(110.0));
+        assert_eq!(average.average_cost(), Some(105.0));
+        assert_eq!(fifo.unrealized_pnl(115.0), 25.0);
+        assert_eq!(average.unrealized_pnl(115.0), 50.0);
+
+        // selling through flat opens a short at the fill price
+        fifo.on_fill(Side::Sell, 112.0, 8);
+        assert_eq!(fifo.get_side(), Some(Side::Sell));
+        assert_eq!(fifo.net_quantity(), -3.0);
+        assert_eq!(fifo.realized_pnl(), 250.0 + 10.0);
+        assert_eq!(fifo.unrealized_pnl(100.0), 36.0);
+        assert_eq!(fifo.exposure(100.0), -300.0);
+        fifo.on_fill(Side::Buy, 110.0, 3);
+        assert_eq!(fifo.get_side(), None);
+        assert_eq!(fifo.realized_pnl(), 266.0);
+    }
+
+    #[test]
+    fn test_positions_from_book_trades(){
+        let symbol = "AAPL".to_string();
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut keeper = PositionKeeper::new(CostMethod::Fifo);
+        keeper.register_order(1, 7, symbol.clone());
+        keeper.register_order(2, 8, symbol.clone());
+
+        book.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        keeper.on_trades(&book.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 100, 4)));
+        // an order we don't know about makes a two sided market around 101
+        book.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 102, 5));
+        keeper.mark_from_book(&symbol, &book, MarkSource::Mid);
+
+        let reports = keeper.report(7);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].net_quantity, 4.0);
+        assert_eq!(reports[0].mark, Some(101.0));
+        assert_eq!(reports[0].unrealized_pnl, 4.0);
+        assert_eq!(keeper.totals(8).net_exposure, -404.0);
+        assert_eq!(keeper.totals(8).unrealized_pnl, -4.0);
+
+        keeper.mark_from_book(&symbol, &book, MarkSource::Last);
+        let snapshot = keeper.end_of_day(1_700_000_000_000);
+        assert_eq!(snapshot.positions.len(), 2);
+        let mut csv = vec![];
+        snapshot.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().nth(1), Some("1700000000000,7,AAPL,4,100,100,0,0,400,400,0"));
+        assert_eq!(keeper.get_snapshots().len(), 1);
+    }
+
+    #[test]
+    fn test_fees_on_book_trades(){
+        let symbol = "AAPL".to_string();
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut keeper = PositionKeeper::new(CostMethod::Fifo);
+        keeper.set_fee_engine(FeeEngine::new(FeeSchedule::flat(-0.001, 0.002)));
+        keeper.register_order(1, 7, symbol.clone());
+        keeper.register_order(2, 8, symbol.clone());
+
+        // 7 rests and is rebated, 8 takes and pays
+        book.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        keeper.on_trades(&book.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 100, 4)));
+        assert_eq!(keeper.get_position(7, &symbol).unwrap().get_fees(), 400.0 * -0.001);
+        assert_eq!(keeper.totals(7).realized_pnl, 400.0 * 0.001);
+        assert_eq!(keeper.totals(8).fees, 400.0 * 0.002);
+        assert_eq!(keeper.totals(8).realized_pnl, -400.0 * 0.002);
+        assert_eq!(keeper.get_fee_engine().unwrap().get_fees(8), 400.0 * 0.002);
+    }
+}
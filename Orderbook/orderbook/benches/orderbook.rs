@@ -0,0 +1,302 @@
+// Replays seeded synthetic order flow through an Orderbook and reports per
+// operation latency (HDR histograms) and throughput.
+//
+//   cargo bench --bench orderbook -- [--ops N] [--seed S] [--save FILE] [--baseline FILE]
+//
+// The flow is fully determined by the seed and op count, so two runs with the same
+// arguments do the same work. --save writes the results as CSV and --baseline
+// prints each figure's change against a previously saved run.
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    time::{Duration, Instant},
+};
+use hdrhistogram::Histogram;
+use orderbook::orderbook::{Order, OrderModify, OrderType, Orderbook, Price, Quantity, Side};
+
+type OrderId = u32;
+
+const WARMUP_OPS: usize = 50_000;
+const INITIAL_DEPTH: usize = 2_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Operation {
+    Add,
+    Cancel,
+    Modify,
+    Market,
+    FillAndKill,
+}
+
+impl Operation {
+    const ALL: [Operation; 5] = [Operation::Add, Operation::Cancel, Operation::Modify, Operation::Market, Operation::FillAndKill];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Add => "add",
+            Operation::Cancel => "cancel",
+            Operation::Modify => "modify",
+            Operation::Market => "market",
+            Operation::FillAndKill => "fill_and_kill",
+        }
+    }
+}
+
+// splitmix64: small, fast and the same on every platform, so a seed always means the same flow
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn side(&mut self) -> Side {
+        if self.below(2) == 0 { Side::Buy } else { Side::Sell }
+    }
+}
+
+// Order flow around a slowly drifting mid. Most adds rest a few ticks off the
+// touch; some cross and take liquidity.
+struct Flow {
+    rng: Rng,
+    mid: Price,
+    next_id: OrderId,
+    live: Vec<OrderId>,
+}
+
+impl Flow {
+    fn new(seed: u64) -> Self {
+        Self { rng: Rng(seed), mid: Price::from(10_000), next_id: 1, live: vec![] }
+    }
+
+    fn next_operation(&mut self) -> Operation {
+        match self.rng.below(100) {
+            0..=54 => Operation::Add,
+            55..=79 => Operation::Cancel,
+            80..=89 => Operation::Modify,
+            90..=94 => Operation::Market,
+            _ => Operation::FillAndKill,
+        }
+    }
+
+    fn limit_price(&mut self, side: Side) -> Price {
+        if self.rng.below(100) == 0 {
+            self.mid = self.mid + Price::from(self.rng.below(3) as i64 - 1);
+        }
+        // -2..=9 ticks away from the mid on the passive side, so roughly one in six crosses
+        let offset = Price::from(self.rng.below(12) as i64 - 2);
+        match side {
+            Side::Buy => self.mid - offset,
+            Side::Sell => self.mid + offset,
+        }
+    }
+
+    fn quantity(&mut self) -> Quantity {
+        1 + self.rng.below(100)
+    }
+
+    fn new_id(&mut self) -> OrderId {
+        let order_id = self.next_id;
+        self.next_id += 1;
+        order_id
+    }
+
+    // a previously added order; it may since have filled, as in real flow
+    fn known_id(&mut self) -> Option<OrderId> {
+        if self.live.is_empty() {
+            return None;
+        }
+        let index = self.rng.below(self.live.len() as u64) as usize;
+        Some(self.live.swap_remove(index))
+    }
+
+    fn add(&mut self, orderbook: &Orderbook) -> Duration {
+        let side = self.rng.side();
+        let price = self.limit_price(side);
+        let quantity = self.quantity();
+        let order_id = self.new_id();
+        self.live.push(order_id);
+        let order = Order::new(OrderType::GoodTillCancel, order_id, side, price, quantity);
+        timed(|| orderbook.add_order(order))
+    }
+
+    fn run(&mut self, orderbook: &Orderbook, operation: Operation) -> Duration {
+        match operation {
+            Operation::Add => self.add(orderbook),
+            Operation::Cancel => match self.known_id() {
+                Some(order_id) => timed(|| orderbook.cancel_order(order_id)),
+                None => self.add(orderbook),
+            },
+            Operation::Modify => match self.known_id() {
+                Some(order_id) => {
+                    self.live.push(order_id);
+                    let side = self.rng.side();
+                    let modify = OrderModify::new(order_id, side, self.limit_price(side), self.quantity());
+                    timed(|| orderbook.modify_order(modify))
+                }
+                None => self.add(orderbook),
+            },
+            Operation::Market => {
+                let order = Order::new_market(self.new_id(), self.rng.side(), self.quantity());
+                timed(|| orderbook.add_order(order))
+            }
+            Operation::FillAndKill => {
+                let side = self.rng.side();
+                let order = Order::new(OrderType::FillAndKill, self.new_id(), side, self.limit_price(side), self.quantity());
+                timed(|| orderbook.add_order(order))
+            }
+        }
+    }
+}
+
+fn timed<T>(operation: impl FnOnce() -> T) -> Duration {
+    let start = Instant::now();
+    let result = operation();
+    let elapsed = start.elapsed();
+    drop(result);
+    elapsed
+}
+
+struct Report {
+    operation: &'static str,
+    count: u64,
+    ops_per_sec: f64,
+    p50: u64,
+    p99: u64,
+    p999: u64,
+    max: u64,
+}
+
+impl Report {
+    const HEADER: &'static str = "operation,count,ops_per_sec,p50_ns,p99_ns,p99.9_ns,max_ns";
+
+    fn new(operation: &'static str, histogram: &Histogram<u64>, busy: Duration) -> Self {
+        Self {
+            operation,
+            count: histogram.len(),
+            ops_per_sec: histogram.len() as f64 / busy.as_secs_f64().max(f64::EPSILON),
+            p50: histogram.value_at_quantile(0.50),
+            p99: histogram.value_at_quantile(0.99),
+            p999: histogram.value_at_quantile(0.999),
+            max: histogram.max(),
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        format!("{},{},{:.0},{},{},{},{}", self.operation, self.count, self.ops_per_sec, self.p50, self.p99, self.p999, self.max)
+    }
+
+    fn figures(&self) -> [f64; 5] {
+        [self.ops_per_sec, self.p50 as f64, self.p99 as f64, self.p999 as f64, self.max as f64]
+    }
+}
+
+fn parse_baseline(text: &str) -> HashMap<String, [f64; 5]> {
+    text.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').collect();
+            let numbers: Vec<f64> = fields.get(2..7)?.iter().filter_map(|field| field.parse().ok()).collect();
+            let figures: [f64; 5] = numbers.try_into().ok()?;
+            Some((fields[0].to_string(), figures))
+        })
+        .collect()
+}
+
+fn change(current: f64, baseline: f64) -> String {
+    if baseline == 0.0 {
+        return "n/a".to_string();
+    }
+    format!("{:+.1}%", (current - baseline) / baseline * 100.0)
+}
+
+fn main() {
+    let mut ops: usize = 1_000_000;
+    let mut seed: u64 = 42;
+    let mut save = None;
+    let mut baseline = None;
+
+    // cargo bench passes --bench through to harness = false targets
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ops" => ops = args.next().and_then(|value| value.parse().ok()).expect("--ops takes a number"),
+            "--seed" => seed = args.next().and_then(|value| value.parse().ok()).expect("--seed takes a number"),
+            "--save" => save = Some(args.next().expect("--save takes a file")),
+            "--baseline" => baseline = Some(args.next().expect("--baseline takes a file")),
+            _ => {}
+        }
+    }
+
+    let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+    let mut flow = Flow::new(seed);
+    for _ in 0..INITIAL_DEPTH {
+        flow.add(&orderbook);
+    }
+    for _ in 0..WARMUP_OPS {
+        let operation = flow.next_operation();
+        flow.run(&orderbook, operation);
+    }
+
+    let mut histograms: HashMap<Operation, Histogram<u64>> = Operation::ALL.iter()
+        .map(|operation| (*operation, Histogram::new_with_bounds(1, 60_000_000_000, 3).unwrap()))
+        .collect();
+    let mut busy: HashMap<Operation, Duration> = HashMap::new();
+    let mut all = Histogram::<u64>::new_with_bounds(1, 60_000_000_000, 3).unwrap();
+
+    let started = Instant::now();
+    for _ in 0..ops {
+        let operation = flow.next_operation();
+        let elapsed = flow.run(&orderbook, operation);
+        let nanos = (elapsed.as_nanos() as u64).max(1);
+        histograms.get_mut(&operation).unwrap().saturating_record(nanos);
+        all.saturating_record(nanos);
+        *busy.entry(operation).or_default() += elapsed;
+    }
+    let wall = started.elapsed();
+
+    let mut reports: Vec<Report> = Operation::ALL.iter()
+        .map(|operation| Report::new(operation.name(), &histograms[operation], busy.get(operation).copied().unwrap_or_default()))
+        .collect();
+    reports.push(Report::new("all", &all, wall));
+
+    println!("orderbook bench: seed={} ops={} resting={} wall={:.3}s", seed, ops, orderbook.size(), wall.as_secs_f64());
+    println!("{:<14} {:>9} {:>12} {:>9} {:>9} {:>9} {:>11}", "operation", "count", "ops/s", "p50 ns", "p99 ns", "p99.9 ns", "max ns");
+    for report in &reports {
+        println!(
+            "{:<14} {:>9} {:>12.0} {:>9} {:>9} {:>9} {:>11}",
+            report.operation, report.count, report.ops_per_sec, report.p50, report.p99, report.p999, report.max
+        );
+    }
+
+    if let Some(path) = baseline {
+        let text = fs::read_to_string(&path).expect("cannot read baseline");
+        let previous = parse_baseline(&text);
+        println!();
+        println!("change against {} (ops/s up is better, latency down is better)", path);
+        println!("{:<14} {:>9} {:>9} {:>9} {:>9} {:>9}", "operation", "ops/s", "p50", "p99", "p99.9", "max");
+        for report in &reports {
+            let Some(before) = previous.get(report.operation) else { continue };
+            let changes: Vec<String> = report.figures().iter().zip(before).map(|(now, before)| change(*now, *before)).collect();
+            println!(
+                "{:<14} {:>9} {:>9} {:>9} {:>9} {:>9}",
+                report.operation, changes[0], changes[1], changes[2], changes[3], changes[4]
+            );
+        }
+    }
+
+    if let Some(path) = save {
+        let mut csv = vec![Report::HEADER.to_string()];
+        csv.extend(reports.iter().map(Report::to_csv));
+        fs::write(&path, csv.join("\n") + "\n").expect("cannot write results");
+        println!("saved to {}", path);
+    }
+}
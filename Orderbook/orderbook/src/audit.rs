@@ -0,0 +1,607 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use sha2::{Digest, Sha256};
+use crate::orderbook::{ModifyOutcome, Order, OrderModify, Orderbook, Price, Quantity, Side, Trade};
+use crate::price::{PriceType, QuantityType};
+use crate::risk::AccountId;
+use crate::stats::Timestamp;
+
+type OrderId = u32;
+type OrderPointer<P, Q> = Arc<Mutex<Order<P, Q>>>;
+type Trades<P, Q> = Vec<Trade<P, Q>>;
+
+pub type Hash = [u8; 32];
+
+// what the chain starts from
+pub const GENESIS: Hash = [0; 32];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AuditEvent {
+    Received,
+    Accepted,
+    Rejected,
+    Modified,
+    Cancelled,
+    Filled,
+}
+
+impl AuditEvent {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            AuditEvent::Received => "received",
+            AuditEvent::Accepted => "accepted",
+            AuditEvent::Rejected => "rejected",
+            AuditEvent::Modified => "modified",
+            AuditEvent::Cancelled => "cancelled",
+            AuditEvent::Filled => "filled",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [AuditEvent::Received, AuditEvent::Accepted, AuditEvent::Rejected, AuditEvent::Modified, AuditEvent::Cancelled, AuditEvent::Filled]
+            .into_iter()
+            .find(|event| event.name() == name)
+    }
+}
+
+// Top of the book once the event had happened (before it, for Received).
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct BookState {
+    pub best_bid: Option<(f64, f64)>,
+    pub best_ask: Option<(f64, f64)>,
+}
+
+impl BookState {
+    pub fn of<P: PriceType, Q: QuantityType>(book: &Orderbook<P, Q>) -> Self {
+        let (best_bid, best_ask) = book.top_of_book();
+        let level = |level: Option<(P, Q)>| level.map(|(price, quantity)| (price.to_f64(), quantity.to_f64()));
+        Self { best_bid: level(best_bid), best_ask: level(best_ask) }
+    }
+}
+
+// One line of the trail. Prices and quantities are kept as f64 so the file
+// reads the same whatever types the book uses.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AuditRecord {
+    pub sequence: u64,
+    pub timestamp: Timestamp,
+    pub order_id: OrderId,
+    pub owner: AccountId,
+    pub event: AuditEvent,
+    pub side: Side,
+    // the order's limit, or the trade price for Filled; None for a market order
+    pub price: Option<f64>,
+    // asked for, or traded for Filled
+    pub quantity: f64,
+    // what the order has left working after the event
+    pub leaves: f64,
+    pub book: BookState,
+    // why, for rejects and cancels the owner didn't ask for; the contra order for fills
+    pub detail: String,
+    pub previous_hash: Hash,
+    pub hash: Hash,
+}
+
+fn to_hex(hash: &Hash) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Hash> {
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+    let mut hash = [0; 32];
+    for (index, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map_or(String::new(), |value| value.to_string())
+}
+
+impl AuditRecord {
+    // Everything but the hashes, comma separated:
+    // sequence,timestamp,order_id,owner,event,side,price,quantity,leaves,bid,bid_quantity,ask,ask_quantity,detail
+    fn body(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.sequence,
+            self.timestamp,
+            self.order_id,
+            self.owner,
+            self.event.name(),
+            if self.side == Side::Buy { "buy" } else { "sell" },
+            optional(self.price),
+            self.quantity,
+            self.leaves,
+            optional(self.book.best_bid.map(|(price, _)| price)),
+            optional(self.book.best_bid.map(|(_, quantity)| quantity)),
+            optional(self.book.best_ask.map(|(price, _)| price)),
+            optional(self.book.best_ask.map(|(_, quantity)| quantity)),
+            self.detail,
+        )
+    }
+
+    // each record's hash covers the one before it, so changing, dropping or
+    // reordering any record breaks every link after it
+    fn compute_hash(previous_hash: &Hash, body: &str) -> Hash {
+        let mut hasher = Sha256::new();
+        hasher.update(previous_hash);
+        hasher.update(body.as_bytes());
+        hasher.finalize().into()
+    }
+
+    pub fn to_line(&self) -> String {
+        format!("{},{},{}", self.body(), to_hex(&self.previous_hash), to_hex(&self.hash))
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != 16 {
+            return None;
+        }
+        let number = |index: usize| fields[index].parse::<f64>().ok();
+        let optional = |index: usize| if fields[index].is_empty() { Some(None) } else { number(index).map(Some) };
+        let level = |price: usize, quantity: usize| -> Option<Option<(f64, f64)>> {
+            Some(optional(price)?.zip(optional(quantity)?))
+        };
+        Some(Self {
+            sequence: fields[0].parse().ok()?,
+            timestamp: fields[1].parse().ok()?,
+            order_id: fields[2].parse().ok()?,
+            owner: fields[3].parse().ok()?,
+            event: AuditEvent::from_name(fields[4])?,
+            side: match fields[5] {
+                "buy" => Side::Buy,
+                "sell" => Side::Sell,
+                _ => return None,
+            },
+            price: optional(6)?,
+            quantity: number(7)?,
+            leaves: number(8)?,
+            book: BookState { best_bid: level(9, 10)?, best_ask: level(11, 12)? },
+            detail: fields[13].to_string(),
+            previous_hash: from_hex(fields[14])?,
+            hash: from_hex(fields[15])?,
+        })
+    }
+}
+
+impl fmt::Display for AuditRecord {
+    // "#3 1000 order 1 owner 7 filled buy 4@100 leaves 6 | bid 100x6 ask - | contra 50"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = |level: Option<(f64, f64)>| level.map_or("-".to_string(), |(price, quantity)| format!("{}x{}", price, quantity));
+        write!(
+            f,
+            "#{} {} order {} owner {} {} {} {}@{} leaves {} | bid {} ask {}",
+            self.sequence,
+            self.timestamp,
+            self.order_id,
+            self.owner,
+            self.event.name(),
+            if self.side == Side::Buy { "buy" } else { "sell" },
+            self.quantity,
+            self.price.map_or("market".to_string(), |price| price.to_string()),
+            self.leaves,
+            level(self.book.best_bid),
+            level(self.book.best_ask),
+        )?;
+        if !self.detail.is_empty() {
+            write!(f, " | {}", self.detail)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum AuditError {
+    Io(String),
+    // line numbers count from 1
+    Malformed { line: usize },
+    // the record's hash, or its link to the record before, doesn't check out
+    Tampered { line: usize, sequence: u64 },
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::Io(error) => write!(f, "{}", error),
+            AuditError::Malformed { line } => write!(f, "line {}: not an audit record", line),
+            AuditError::Tampered { line, sequence } => write!(f, "line {}: chain broken at record {}", line, sequence),
+        }
+    }
+}
+
+// Reads a whole trail, checking every link of the hash chain on the way.
+pub fn read_trail(reader: impl BufRead) -> Result<Vec<AuditRecord>, AuditError> {
+    let mut records: Vec<AuditRecord> = vec![];
+    let mut previous_hash = GENESIS;
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.map_err(|error| AuditError::Io(error.to_string()))?;
+        let record = AuditRecord::parse(&line).ok_or(AuditError::Malformed { line: line_number })?;
+        let expected_sequence = records.len() as u64;
+        if record.sequence != expected_sequence
+            || record.previous_hash != previous_hash
+            || record.hash != AuditRecord::compute_hash(&previous_hash, &record.body())
+        {
+            return Err(AuditError::Tampered { line: line_number, sequence: record.sequence });
+        }
+        previous_hash = record.hash;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// every event for `order_id`, oldest first
+pub fn lifecycle(records: &[AuditRecord], order_id: OrderId) -> Vec<&AuditRecord> {
+    records.iter().filter(|record| record.order_id == order_id).collect()
+}
+
+// Append-only file of hash-chained records. Opening an existing trail checks it
+// and carries its chain on.
+#[derive(Debug)]
+pub struct AuditTrail {
+    file: BufWriter<File>,
+    sequence: u64,
+    last_hash: Hash,
+}
+
+impl AuditTrail {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let (sequence, last_hash) = if path.exists() {
+            let records = read_trail(BufReader::new(File::open(path)?))
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), error)))?;
+            (records.len() as u64, records.last().map_or(GENESIS, |record| record.hash))
+        } else {
+            (0, GENESIS)
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: BufWriter::new(file), sequence, last_hash })
+    }
+
+    // Chains and writes one record. It's flushed straight away so nothing that
+    // happened on the book can be lost from the trail.
+    #[allow(clippy::too_many_arguments)]
+    pub fn append(
+        &mut self,
+        timestamp: Timestamp,
+        order_id: OrderId,
+        owner: AccountId,
+        event: AuditEvent,
+        side: Side,
+        price: Option<f64>,
+        quantity: f64,
+        leaves: f64,
+        book: BookState,
+        detail: &str,
+    ) -> io::Result<AuditRecord> {
+        let mut record = AuditRecord {
+            sequence: self.sequence,
+            timestamp,
+            order_id,
+            owner,
+            event,
+            side,
+            price,
+            quantity,
+            leaves,
+            book,
+            // commas would split the field
+            detail: detail.replace(',', ";"),
+            previous_hash: self.last_hash,
+            hash: GENESIS,
+        };
+        record.hash = AuditRecord::compute_hash(&record.previous_hash, &record.body());
+        writeln!(self.file, "{}", record.to_line())?;
+        self.file.flush()?;
+        self.sequence += 1;
+        self.last_hash = record.hash;
+        Ok(record)
+    }
+}
+
+#[derive(Debug)]
+struct AuditedOrder {
+    owner: AccountId,
+    side: Side,
+    price: Option<f64>,
+    quantity: f64,
+    filled: f64,
+}
+
+impl AuditedOrder {
+    fn leaves(&self) -> f64 {
+        (self.quantity - self.filled).max(0.0)
+    }
+}
+
+// A book with a drop copy: every order, amend and cancel goes through here and
+// each thing that happens to an order is written to the trail with its owner.
+#[derive(Debug)]
+pub struct AuditedBook<P = Price, Q = Quantity> {
+    book: Orderbook<P, Q>,
+    trail: AuditTrail,
+    orders: HashMap<OrderId, AuditedOrder>,
+}
+
+impl<P: PriceType, Q: QuantityType> AuditedBook<P, Q> {
+    pub fn new(book: Orderbook<P, Q>, trail: AuditTrail) -> Self {
+        Self { book, trail, orders: HashMap::new() }
+    }
+
+    pub const fn get_book(&self) -> &Orderbook<P, Q> {
+        &self.book
+    }
+
+    fn record(&mut self, now: Timestamp, order_id: OrderId, event: AuditEvent, book: BookState, detail: &str) -> io::Result<()> {
+        let Some(order) = self.orders.get(&order_id) else { return Ok(()) };
+        let gone = matches!(event, AuditEvent::Rejected | AuditEvent::Cancelled) && !self.book.contains(order_id);
+        let leaves = if gone { 0.0 } else { order.leaves() };
+        self.trail.append(now, order_id, order.owner, event, order.side, order.price, order.quantity, leaves, book, detail)?;
+        // that was the order's last record
+        if gone {
+            self.orders.remove(&order_id);
+        }
+        Ok(())
+    }
+
+    // a Filled record for each side of each trade that's one of ours
+    fn record_trades(&mut self, now: Timestamp, trades: &Trades<P, Q>) -> io::Result<()> {
+        let book = BookState::of(&self.book);
+        for trade in trades {
+            let (bid, ask) = (trade.get_bid_trade(), trade.get_ask_trade());
+            for (info, contra) in [(bid, ask), (ask, bid)] {
+                let Some(order) = self.orders.get_mut(&info.order_id) else { continue };
+                order.filled += info.quantity.to_f64();
+                let (owner, side, leaves) = (order.owner, order.side, order.leaves());
+                let price = Some(trade.get_price().to_f64());
+                let detail = format!("contra {}", contra.order_id);
+                self.trail.append(now, info.order_id, owner, AuditEvent::Filled, side, price, info.quantity.to_f64(), leaves, book, &detail)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn submit(&mut self, now: Timestamp, owner: AccountId, order: OrderPointer<P, Q>) -> io::Result<Trades<P, Q>> {
+        let (order_id, side, price, quantity) = {
+            let ord = order.lock().unwrap();
+            (ord.get_order_id(), ord.get_side(), ord.get_price().map(|price| price.to_f64()), ord.get_remaining_quantity().to_f64())
+        };
+        if self.orders.contains_key(&order_id) || self.book.contains(order_id) {
+            // recorded against the new owner without touching the order already there
+            self.trail.append(now, order_id, owner, AuditEvent::Rejected, side, price, quantity, 0.0, BookState::of(&self.book), "duplicate order id")?;
+            return Ok(vec![]);
+        }
+        self.orders.insert(order_id, AuditedOrder { owner, side, price, quantity, filled: 0.0 });
+        self.record(now, order_id, AuditEvent::Received, BookState::of(&self.book), "")?;
+
+        let trades = self.book.add_order(order);
+        let traded = trades.iter().any(|trade| trade.get_bid_trade().order_id == order_id || trade.get_ask_trade().order_id == order_id);
+        if !traded && !self.book.contains(order_id) {
+            self.record(now, order_id, AuditEvent::Rejected, BookState::of(&self.book), "refused by the book")?;
+            return Ok(trades);
+        }
+        self.record(now, order_id, AuditEvent::Accepted, BookState::of(&self.book), "")?;
+        self.record_trades(now, &trades)?;
+        if !self.book.contains(order_id) && self.orders[&order_id].leaves() > 0.0 {
+            self.record(now, order_id, AuditEvent::Cancelled, BookState::of(&self.book), "unfilled remainder")?;
+        }
+        self.forget_gone(now)?;
+        Ok(trades)
+    }
+
+    pub fn modify(&mut self, now: Timestamp, owner: AccountId, modify: OrderModify<P, Q>) -> io::Result<ModifyOutcome<P, Q>> {
+        let order_id = modify.get_order_id();
+        if !self.owns(owner, order_id) {
+            self.reject_unknown(now, owner, order_id, modify.get_side(), "modify of an order the owner doesn't have")?;
+            return Ok(ModifyOutcome::Rejected(crate::orderbook::ModifyReject::UnknownOrder));
+        }
+        let (side, price, quantity) = (modify.get_side(), modify.get_price().to_f64(), modify.get_quantity().to_f64());
+        let outcome = self.book.modify_order(modify);
+        let book = BookState::of(&self.book);
+        match &outcome {
+            ModifyOutcome::AmendedInPlace(trades) | ModifyOutcome::Replaced(trades) => {
+                if let Some(order) = self.orders.get_mut(&order_id) {
+                    // a replace can turn the order round
+                    order.side = side;
+                    order.price = Some(price);
+                    order.quantity = quantity;
+                }
+                self.record(now, order_id, AuditEvent::Modified, book, "")?;
+                self.record_trades(now, trades)?;
+            }
+            ModifyOutcome::Cancelled(trades) => {
+                self.record(now, order_id, AuditEvent::Cancelled, book, "modified to no more than filled")?;
+                self.record_trades(now, trades)?;
+            }
+            ModifyOutcome::Rejected(reject) => {
+                self.record(now, order_id, AuditEvent::Rejected, book, &format!("modify: {:?}", reject))?;
+            }
+        }
+        self.forget_gone(now)?;
+        Ok(outcome)
+    }
+
+    pub fn cancel(&mut self, now: Timestamp, owner: AccountId, order_id: OrderId) -> io::Result<Trades<P, Q>> {
+        if !self.owns(owner, order_id) || !self.book.contains(order_id) {
+            let side = self.orders.get(&order_id).map_or(Side::Buy, |order| order.side);
+            self.reject_unknown(now, owner, order_id, side, "cancel of an order the owner doesn't have")?;
+            return Ok(vec![]);
+        }
+        // repricing pegs can trade once the order is gone
+        let trades = self.book.cancel_order(order_id);
+        self.record(now, order_id, AuditEvent::Cancelled, BookState::of(&self.book), "")?;
+        self.record_trades(now, &trades)?;
+        self.forget_gone(now)?;
+        Ok(trades)
+    }
+
+    fn owns(&self, owner: AccountId, order_id: OrderId) -> bool {
+        self.orders.get(&order_id).is_some_and(|order| order.owner == owner)
+    }
+
+    // written against whoever asked, since the order isn't theirs to record against
+    fn reject_unknown(&mut self, now: Timestamp, owner: AccountId, order_id: OrderId, side: Side, detail: &str) -> io::Result<()> {
+        let leaves = self.orders.get(&order_id).map_or(0.0, |order| order.leaves());
+        self.trail.append(now, order_id, owner, AuditEvent::Rejected, side, None, 0.0, leaves, BookState::of(&self.book), detail)?;
+        Ok(())
+    }
+
+    // Orders that have left the book have nothing more to record. One that went
+    // with quantity left was dropped by the book itself, say a fired stop's
+    // unfilled remainder or a pruned GoodForDay order, so it gets a Cancelled.
+    fn forget_gone(&mut self, now: Timestamp) -> io::Result<()> {
+        let mut gone: Vec<OrderId> = self.orders.keys().copied().filter(|order_id| !self.book.contains(*order_id)).collect();
+        gone.sort_unstable();
+        let book = BookState::of(&self.book);
+        for order_id in gone {
+            let order = self.orders.remove(&order_id).unwrap();
+            if order.leaves() > 0.0 {
+                self.trail.append(now, order_id, order.owner, AuditEvent::Cancelled, order.side, order.price, order.quantity, 0.0, book, "removed by the book")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::orderbook::{OrderType, TrailAmount, TrailReference, TrailingStop};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("audit-{}-{}.csv", name, std::process::id()));
+        std::fs::remove_file(&path).ok();
+        path
+    }
+
+    fn read(path: &Path) -> Result<Vec<AuditRecord>, AuditError> {
+        read_trail(BufReader::new(File::open(path).unwrap()))
+    }
+
+    #[test]
+    fn test_lifecycle_is_reconstructed(){
+        let path = temp_path("lifecycle");
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut audited = AuditedBook::new(book, AuditTrail::open(&path).unwrap());
+
+        audited.submit(1_000, 7, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10)).unwrap();
+        audited.submit(1_001, 8, Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 100, 4)).unwrap();
+        audited.modify(1_002, 7, OrderModify::new(1, Side::Buy, 99, 8)).unwrap();
+        // only the owner can cancel
+        audited.cancel(1_003, 8, 1).unwrap();
+        audited.cancel(1_004, 7, 1).unwrap();
+        drop(audited);
+
+        let records = read(&path).unwrap();
+        let events: Vec<(Timestamp, AccountId, AuditEvent, f64)> = lifecycle(&records, 1).iter()
+            .map(|record| (record.timestamp, record.owner, record.event, record.leaves))
+            .collect();
+        assert_eq!(events, vec![
+            (1_000, 7, AuditEvent::Received, 10.0),
+            (1_000, 7, AuditEvent::Accepted, 10.0),
+            (1_001, 7, AuditEvent::Filled, 6.0),
+            (1_002, 7, AuditEvent::Modified, 4.0),
+            (1_003, 8, AuditEvent::Rejected, 4.0),
+            (1_004, 7, AuditEvent::Cancelled, 0.0),
+        ]);
+        let fill = lifecycle(&records, 1)[2];
+        assert_eq!((fill.price, fill.quantity, fill.detail.as_str()), (Some(100.0), 4.0, "contra 2"));
+        // resting bid 6 left after the fill, then moved to 99 with 4 left
+        assert_eq!(fill.book, BookState { best_bid: Some((100.0, 6.0)), best_ask: None });
+        assert_eq!(lifecycle(&records, 1)[3].book.best_bid, Some((99.0, 4.0)));
+        assert_eq!(lifecycle(&records, 2).last().unwrap().event, AuditEvent::Filled);
+
+        // reopening carries the chain on
+        let mut audited = AuditedBook::new(Orderbook::new(BTreeMap::new(), BTreeMap::new()), AuditTrail::open(&path).unwrap());
+        audited.submit(2_000, 7, Order::new_market(3, Side::Sell, 5)).unwrap();
+        drop(audited);
+        let records = read(&path).unwrap();
+        assert_eq!(records.len(), 11);
+        assert_eq!(lifecycle(&records, 3).iter().map(|record| record.event).collect::<Vec<_>>(), vec![AuditEvent::Received, AuditEvent::Rejected]);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_book_removals_are_recorded(){
+        let path = temp_path("removed");
+        let mut audited = AuditedBook::new(Orderbook::new(BTreeMap::new(), BTreeMap::new()), AuditTrail::open(&path).unwrap());
+        audited.submit(1_000, 7, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 4)).unwrap();
+        let trail = TrailingStop::new(TrailAmount::Offset(Price::from(5)), TrailReference::BestPrice, 10);
+        audited.submit(1_001, 8, Order::new_trailing_stop(OrderType::FillAndKill, 2, Side::Sell, trail, 10)).unwrap();
+        audited.submit(1_002, 7, Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 110, 10)).unwrap();
+        // the stop fires at 95 into the 100 bid and the 6 it can't fill are killed
+        audited.cancel(1_003, 7, 3).unwrap();
+        drop(audited);
+
+        let records = read(&path).unwrap();
+        let events: Vec<(Timestamp, AuditEvent, f64, &str)> = lifecycle(&records, 2).iter()
+            .map(|record| (record.timestamp, record.event, record.leaves, record.detail.as_str()))
+            .collect();
+        assert_eq!(events, vec![
+            (1_001, AuditEvent::Received, 10.0, ""),
+            (1_001, AuditEvent::Accepted, 10.0, ""),
+            (1_003, AuditEvent::Filled, 6.0, "contra 1"),
+            (1_003, AuditEvent::Cancelled, 0.0, "removed by the book"),
+        ]);
+        // filled and cancelled orders aren't cancelled again
+        assert_eq!(lifecycle(&records, 1).last().unwrap().event, AuditEvent::Filled);
+        assert_eq!(lifecycle(&records, 3).iter().filter(|record| record.event == AuditEvent::Cancelled).count(), 1);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_modify_can_change_side(){
+        let path = temp_path("side");
+        let mut audited = AuditedBook::new(Orderbook::new(BTreeMap::new(), BTreeMap::new()), AuditTrail::open(&path).unwrap());
+        audited.submit(1_000, 7, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10)).unwrap();
+        audited.modify(1_001, 7, OrderModify::new(1, Side::Sell, 102, 10)).unwrap();
+        audited.submit(1_002, 8, Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 102, 4)).unwrap();
+        audited.cancel(1_003, 7, 1).unwrap();
+        drop(audited);
+
+        let records = read(&path).unwrap();
+        let sides: Vec<(AuditEvent, Side)> = lifecycle(&records, 1).iter().map(|record| (record.event, record.side)).collect();
+        assert_eq!(sides, vec![
+            (AuditEvent::Received, Side::Buy),
+            (AuditEvent::Accepted, Side::Buy),
+            (AuditEvent::Modified, Side::Sell),
+            (AuditEvent::Filled, Side::Sell),
+            (AuditEvent::Cancelled, Side::Sell),
+        ]);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_tampering_breaks_the_chain(){
+        let path = temp_path("tamper");
+        let mut audited = AuditedBook::new(Orderbook::new(BTreeMap::new(), BTreeMap::new()), AuditTrail::open(&path).unwrap());
+        audited.submit(1_000, 7, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10)).unwrap();
+        audited.submit(1_001, 7, Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 101, 10)).unwrap();
+        drop(audited);
+
+        let original = std::fs::read_to_string(&path).unwrap();
+        // a changed quantity
+        std::fs::write(&path, original.replacen(",buy,100,10,", ",buy,100,1,", 1)).unwrap();
+        assert_eq!(read(&path), Err(AuditError::Tampered { line: 1, sequence: 0 }));
+        assert!(AuditTrail::open(&path).is_err());
+        // a record taken out
+        let lines: Vec<&str> = original.lines().collect();
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert_eq!(read(&path), Err(AuditError::Tampered { line: 2, sequence: 2 }));
+
+        std::fs::write(&path, original).unwrap();
+        assert_eq!(read(&path).unwrap().len(), 4);
+        std::fs::remove_file(&path).ok();
+    }
+}
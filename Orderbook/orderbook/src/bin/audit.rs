@@ -0,0 +1,33 @@
+use std::{env, fs::File, io::BufReader, process};
+use orderbook::audit::{lifecycle, read_trail};
+
+// audit <trail> [order id]
+// Checks the trail's hash chain, then prints every event for the order.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let Some(path) = args.get(1) else {
+        eprintln!("usage: audit <trail> [order id]");
+        process::exit(2);
+    };
+    let order_id: Option<u32> = args.get(2).map(|order_id| order_id.parse().expect("order id must be a number"));
+
+    let file = File::open(path).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+    let records = read_trail(BufReader::new(file)).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+    println!("{}: {} records, chain intact", path, records.len());
+
+    if let Some(order_id) = order_id {
+        let events = lifecycle(&records, order_id);
+        if events.is_empty() {
+            println!("no events for order {}", order_id);
+        }
+        for record in events {
+            println!("{}", record);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+// Property tests for the book. Random command streams are run against the real
+// book and, for the plain order types, against a deliberately naive reference
+// matcher; the book's structural invariants are checked after every command.
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+use proptest::prelude::*;
+use crate::orderbook::{
+    LevelInfo, ModifyOutcome, Order, OrderModify, OrderType, Orderbook, Peg, PegType, Price, Quantity,
+    Side, Trade, TrailAmount, TrailReference, TrailingStop,
+};
+
+type OrderId = u32;
+type OrderPointer = Arc<Mutex<Order>>;
+
+#[derive(Clone, Copy, Debug)]
+enum Command {
+    Add { order_type: OrderType, side: Side, price: Price, quantity: Quantity },
+    Market { side: Side, quantity: Quantity },
+    Hidden { side: Side, price: Price, quantity: Quantity },
+    Pegged { side: Side, peg_type: PegType, quantity: Quantity },
+    Stop { side: Side, trail: Price, quantity: Quantity },
+    // ids index into the orders added so far
+    Cancel { index: usize },
+    Modify { index: usize, side: Side, price: Price, quantity: Quantity },
+}
+
+fn side() -> impl Strategy<Value = Side> {
+    prop_oneof![Just(Side::Buy), Just(Side::Sell)]
+}
+
+// a narrow price band and small sizes so orders keep crossing and partially filling
+fn price() -> impl Strategy<Value = Price> {
+    (95..=105i64).prop_map(Price::from)
+}
+
+fn quantity() -> impl Strategy<Value = Quantity> {
+    1..=20u64
+}
+
+fn plain_order_type() -> impl Strategy<Value = OrderType> {
+    prop_oneof![
+        4 => Just(OrderType::GoodTillCancel),
+        1 => Just(OrderType::FillAndKill),
+        1 => Just(OrderType::FillOrKill),
+    ]
+}
+
+// the order types the reference matcher understands
+fn plain_command() -> impl Strategy<Value = Command> {
+    prop_oneof![
+        6 => (plain_order_type(), side(), price(), quantity())
+            .prop_map(|(order_type, side, price, quantity)| Command::Add { order_type, side, price, quantity }),
+        1 => (side(), quantity()).prop_map(|(side, quantity)| Command::Market { side, quantity }),
+        2 => any::<usize>().prop_map(|index| Command::Cancel { index }),
+        2 => (any::<usize>(), side(), price(), quantity())
+            .prop_map(|(index, side, price, quantity)| Command::Modify { index, side, price, quantity }),
+    ]
+}
+
+fn any_command() -> impl Strategy<Value = Command> {
+    let order_type = prop_oneof![
+        4 => Just(OrderType::GoodTillCancel),
+        1 => Just(OrderType::FillAndKill),
+        1 => Just(OrderType::FillOrKill),
+        1 => Just(OrderType::AllOrNone),
+    ];
+    let peg_type = prop_oneof![Just(PegType::Primary), Just(PegType::Midpoint), Just(PegType::Market)];
+    prop_oneof![
+        6 => (order_type, side(), price(), quantity())
+            .prop_map(|(order_type, side, price, quantity)| Command::Add { order_type, side, price, quantity }),
+        1 => (side(), quantity()).prop_map(|(side, quantity)| Command::Market { side, quantity }),
+        1 => (side(), price(), quantity()).prop_map(|(side, price, quantity)| Command::Hidden { side, price, quantity }),
+        1 => (side(), peg_type, quantity()).prop_map(|(side, peg_type, quantity)| Command::Pegged { side, peg_type, quantity }),
+        1 => (side(), (1..=3i64).prop_map(Price::from), quantity()).prop_map(|(side, trail, quantity)| Command::Stop { side, trail, quantity }),
+        2 => any::<usize>().prop_map(|index| Command::Cancel { index }),
+        2 => (any::<usize>(), side(), price(), quantity())
+            .prop_map(|(index, side, price, quantity)| Command::Modify { index, side, price, quantity }),
+    ]
+}
+
+// (bid id, ask id, price, quantity)
+type Fill = (OrderId, OrderId, Price, Quantity);
+
+fn fills(trades: &[Trade]) -> Vec<Fill> {
+    trades.iter()
+        .map(|trade| (trade.get_bid_trade().order_id, trade.get_ask_trade().order_id, trade.get_price(), trade.get_quantity()))
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+struct ReferenceOrder {
+    order_id: OrderId,
+    side: Side,
+    price: Price,
+    initial: Quantity,
+    filled: Quantity,
+    sequence: u64,
+}
+
+impl ReferenceOrder {
+    fn remaining(&self) -> Quantity {
+        self.initial - self.filled
+    }
+
+    fn crosses(&self, other: &ReferenceOrder) -> bool {
+        match self.side {
+            Side::Buy => self.price >= other.price,
+            Side::Sell => self.price <= other.price,
+        }
+    }
+}
+
+// Price-time priority matching done the slow, obvious way: a flat list of
+// resting orders searched in full for every fill.
+#[derive(Default)]
+struct ReferenceBook {
+    resting: Vec<ReferenceOrder>,
+    next_sequence: u64,
+}
+
+impl ReferenceBook {
+    fn best_contra(&self, incoming: &ReferenceOrder) -> Option<usize> {
+        self.resting.iter().enumerate()
+            .filter(|(_, resting)| resting.side != incoming.side && incoming.crosses(resting))
+            .min_by_key(|(_, resting)| {
+                let price = match resting.side {
+                    Side::Buy => -resting.price,
+                    Side::Sell => resting.price,
+                };
+                (price, resting.sequence)
+            })
+            .map(|(index, _)| index)
+    }
+
+    fn fillable(&self, incoming: &ReferenceOrder) -> Quantity {
+        self.resting.iter()
+            .filter(|resting| resting.side != incoming.side && incoming.crosses(resting))
+            .map(|resting| resting.remaining())
+            .sum()
+    }
+
+    fn contains(&self, order_id: OrderId) -> bool {
+        self.resting.iter().any(|resting| resting.order_id == order_id)
+    }
+
+    fn add(&mut self, order_type: OrderType, order_id: OrderId, side: Side, price: Price, quantity: Quantity) -> Vec<Fill> {
+        let incoming = ReferenceOrder { order_id, side, price, initial: quantity, filled: 0, sequence: 0 };
+        self.submit(order_type, incoming)
+    }
+
+    // a market order becomes a limit at the far side of the contra book
+    fn add_market(&mut self, order_id: OrderId, side: Side, quantity: Quantity) -> Vec<Fill> {
+        let contra = self.resting.iter().filter(|resting| resting.side != side).map(|resting| resting.price);
+        let worst = match side {
+            Side::Buy => contra.max(),
+            Side::Sell => contra.min(),
+        };
+        match worst {
+            Some(price) => self.add(OrderType::GoodTillCancel, order_id, side, price, quantity),
+            None => vec![],
+        }
+    }
+
+    fn submit(&mut self, order_type: OrderType, mut incoming: ReferenceOrder) -> Vec<Fill> {
+        if self.contains(incoming.order_id) {
+            return vec![];
+        }
+        if order_type == OrderType::FillAndKill && self.best_contra(&incoming).is_none() {
+            return vec![];
+        }
+        if order_type == OrderType::FillOrKill && self.fillable(&incoming) < incoming.remaining() {
+            return vec![];
+        }
+
+        let mut fills = vec![];
+        while incoming.remaining() > 0 {
+            let Some(index) = self.best_contra(&incoming) else { break };
+            let resting = &mut self.resting[index];
+            let quantity = incoming.remaining().min(resting.remaining());
+            incoming.filled += quantity;
+            resting.filled += quantity;
+            fills.push(match incoming.side {
+                Side::Buy => (incoming.order_id, resting.order_id, resting.price, quantity),
+                Side::Sell => (resting.order_id, incoming.order_id, resting.price, quantity),
+            });
+            if resting.remaining() == 0 {
+                self.resting.remove(index);
+            }
+        }
+
+        if incoming.remaining() > 0 && order_type == OrderType::GoodTillCancel {
+            incoming.sequence = self.next_sequence;
+            self.next_sequence += 1;
+            self.resting.push(incoming);
+        }
+        fills
+    }
+
+    fn cancel(&mut self, order_id: OrderId) {
+        self.resting.retain(|resting| resting.order_id != order_id);
+    }
+
+    fn modify(&mut self, order_id: OrderId, side: Side, price: Price, quantity: Quantity) -> Vec<Fill> {
+        let Some(index) = self.resting.iter().position(|resting| resting.order_id == order_id) else { return vec![] };
+        let order = &mut self.resting[index];
+        if quantity <= order.filled {
+            self.resting.remove(index);
+            return vec![];
+        }
+        if order.side == side && order.price == price && quantity <= order.initial {
+            order.initial = quantity;
+            return vec![];
+        }
+        let mut order = self.resting.remove(index);
+        order.side = side;
+        order.price = price;
+        order.initial = quantity;
+        self.submit(OrderType::GoodTillCancel, order)
+    }
+
+    // displayed quantity per price, best first, the same shape as get_order_infos
+    fn levels(&self, side: Side) -> Vec<(Price, Quantity)> {
+        let mut levels: BTreeMap<Price, Quantity> = BTreeMap::new();
+        for resting in self.resting.iter().filter(|resting| resting.side == side) {
+            *levels.entry(resting.price).or_default() += resting.remaining();
+        }
+        levels.into_iter().collect()
+    }
+}
+
+// Runs the commands against the book, checking invariants as it goes, and
+// returns every fill along with the orders it created for the final checks.
+fn run_book(orderbook: &Orderbook, commands: &[Command], mut on_fills: impl FnMut(&Command, OrderId, &[Fill]) -> Result<(), TestCaseError>) -> Result<HashMap<OrderId, OrderPointer>, TestCaseError> {
+    let mut created: HashMap<OrderId, OrderPointer> = HashMap::new();
+    let mut ids: Vec<OrderId> = vec![];
+    for (step, command) in commands.iter().enumerate() {
+        let order_id = step as OrderId + 1;
+        let order = match *command {
+            Command::Add { order_type, side, price, quantity } => Some(Order::new(order_type, order_id, side, price, quantity)),
+            Command::Market { side, quantity } => Some(Order::new_market(order_id, side, quantity)),
+            Command::Hidden { side, price, quantity } => Some(Order::new_hidden(OrderType::GoodTillCancel, order_id, side, price, quantity)),
+            Command::Pegged { side, peg_type, quantity } => {
+                let peg = Peg::new(peg_type, 0, None);
+                Some(Order::new_pegged(OrderType::GoodTillCancel, order_id, side, peg, quantity))
+            }
+            Command::Stop { side, trail, quantity } => {
+                let trail = TrailingStop::new(TrailAmount::Offset(trail), TrailReference::BestPrice, 0);
+                Some(Order::new_trailing_stop(OrderType::Market, order_id, side, trail, quantity))
+            }
+            _ => None,
+        };
+        let trades = match (*command, order) {
+            (_, Some(order)) => {
+                ids.push(order_id);
+                created.insert(order_id, order.clone());
+                orderbook.add_order(order)
+            }
+            (Command::Cancel { index }, _) if !ids.is_empty() => orderbook.cancel_order(ids[index % ids.len()]),
+            (Command::Modify { index, side, price, quantity }, _) if !ids.is_empty() => {
+                let target = ids[index % ids.len()];
+                match orderbook.modify_order(OrderModify::new(target, side, price, quantity)) {
+                    ModifyOutcome::AmendedInPlace(trades) | ModifyOutcome::Replaced(trades) | ModifyOutcome::Cancelled(trades) => trades,
+                    ModifyOutcome::Rejected(_) => vec![],
+                }
+            }
+            _ => vec![],
+        };
+
+        orderbook.check_invariants().map_err(|error| TestCaseError::fail(format!("after {:?}: {}", command, error)))?;
+        on_fills(command, order_id, &fills(&trades))?;
+    }
+    Ok(created)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    // The book and the reference agree on every fill and on the resulting depth.
+    #[test]
+    fn test_matches_reference(commands in prop::collection::vec(plain_command(), 1..80)) {
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut reference = ReferenceBook::default();
+        let mut ids: Vec<OrderId> = vec![];
+
+        run_book(&orderbook, &commands, |command, order_id, fills| {
+            let expected = match *command {
+                Command::Add { order_type, side, price, quantity } => {
+                    ids.push(order_id);
+                    reference.add(order_type, order_id, side, price, quantity)
+                }
+                Command::Market { side, quantity } => {
+                    ids.push(order_id);
+                    reference.add_market(order_id, side, quantity)
+                }
+                Command::Cancel { index } if !ids.is_empty() => {
+                    reference.cancel(ids[index % ids.len()]);
+                    vec![]
+                }
+                Command::Modify { index, side, price, quantity } if !ids.is_empty() => {
+                    reference.modify(ids[index % ids.len()], side, price, quantity)
+                }
+                _ => vec![],
+            };
+            prop_assert_eq!(fills, &expected[..], "fills after {:?}", command);
+
+            let infos = orderbook.get_order_infos();
+            let published = |levels: &Vec<LevelInfo>| levels.iter().map(|level| (level.price, level.quantity)).collect::<Vec<_>>();
+            prop_assert_eq!(published(infos.get_bids()), reference.levels(Side::Buy), "bids after {:?}", command);
+            prop_assert_eq!(published(infos.get_asks()), reference.levels(Side::Sell), "asks after {:?}", command);
+            Ok(())
+        })?;
+    }
+
+    // With every order type in the mix there is no reference, but the book must stay
+    // consistent and no quantity may be created or lost: each order's fills add up
+    // to its filled quantity, and both sides of every trade agree.
+    #[test]
+    fn test_invariants_hold(commands in prop::collection::vec(any_command(), 1..80)) {
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut traded: HashMap<OrderId, Quantity> = HashMap::new();
+
+        let created = run_book(&orderbook, &commands, |_, _, fills| {
+            for (bid_id, ask_id, _, quantity) in fills {
+                prop_assert!(*quantity > 0);
+                *traded.entry(*bid_id).or_default() += quantity;
+                *traded.entry(*ask_id).or_default() += quantity;
+            }
+            Ok(())
+        })?;
+
+        for (order_id, order) in created {
+            let order = order.lock().unwrap();
+            prop_assert_eq!(order.get_initial_quantity(), order.get_remaining_quantity() + order.get_filled_quantity());
+            prop_assert_eq!(traded.get(&order_id).copied().unwrap_or(0), order.get_filled_quantity(), "order {}", order_id);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use crate::orderbook::{Side, Symbol, Trade};
+use crate::price::{PriceType, QuantityType};
+use crate::risk::AccountId;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Liquidity {
+    // rested on the book and was traded against
+    Maker,
+    // traded on arrival against a resting order
+    Taker,
+}
+
+impl Liquidity {
+    // what the order on `side` of `trade` did
+    pub fn of<P: PriceType, Q: QuantityType>(trade: &Trade<P, Q>, side: Side) -> Self {
+        if trade.get_maker_side() == side {
+            Liquidity::Maker
+        } else {
+            Liquidity::Taker
+        }
+    }
+}
+
+// Fractions of notional. A negative rate is a rebate.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FeeRates {
+    pub maker: f64,
+    pub taker: f64,
+}
+
+impl FeeRates {
+    pub const fn get(&self, liquidity: Liquidity) -> f64 {
+        match liquidity {
+            Liquidity::Maker => self.maker,
+            Liquidity::Taker => self.taker,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FeeTier {
+    // notional traded so far this month needed to reach the tier
+    pub min_volume: f64,
+    pub rates: FeeRates,
+}
+
+// A venue's price list: tiers by monthly traded notional, and instruments that
+// are charged their own rates whatever the tier.
+#[derive(Clone, Debug)]
+pub struct FeeSchedule {
+    // ascending by min_volume, the first always from zero
+    tiers: Vec<FeeTier>,
+    overrides: HashMap<Symbol, FeeRates>,
+}
+
+impl FeeSchedule {
+    pub fn new(base: FeeRates) -> Self {
+        Self { tiers: vec![FeeTier { min_volume: 0.0, rates: base }], overrides: HashMap::new() }
+    }
+
+    pub fn flat(maker: f64, taker: f64) -> Self {
+        Self::new(FeeRates { maker, taker })
+    }
+
+    pub fn add_tier(&mut self, min_volume: f64, rates: FeeRates) {
+        self.tiers.retain(|tier| tier.min_volume != min_volume);
+        self.tiers.push(FeeTier { min_volume, rates });
+        self.tiers.sort_by(|a, b| a.min_volume.total_cmp(&b.min_volume));
+    }
+
+    pub fn set_override(&mut self, symbol: Symbol, rates: FeeRates) {
+        self.overrides.insert(symbol, rates);
+    }
+
+    pub fn get_tiers(&self) -> &[FeeTier] {
+        &self.tiers
+    }
+
+    pub fn rates(&self, symbol: &Symbol, monthly_volume: f64) -> FeeRates {
+        if let Some(rates) = self.overrides.get(symbol) {
+            return *rates;
+        }
+        self.tiers.iter()
+            .take_while(|tier| tier.min_volume <= monthly_volume)
+            .last()
+            .map_or(FeeRates { maker: 0.0, taker: 0.0 }, |tier| tier.rates)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FeeCharge {
+    pub liquidity: Liquidity,
+    pub rate: f64,
+    // paid when positive, a rebate when negative
+    pub amount: f64,
+}
+
+// Applies a schedule fill by fill, keeping each account's monthly volume so
+// tiers move as it trades.
+#[derive(Debug)]
+pub struct FeeEngine {
+    schedule: FeeSchedule,
+    monthly_volume: HashMap<AccountId, f64>,
+    fees: HashMap<AccountId, f64>,
+}
+
+impl FeeEngine {
+    pub fn new(schedule: FeeSchedule) -> Self {
+        Self { schedule, monthly_volume: HashMap::new(), fees: HashMap::new() }
+    }
+
+    pub const fn get_schedule(&self) -> &FeeSchedule {
+        &self.schedule
+    }
+
+    pub fn monthly_volume(&self, account: AccountId) -> f64 {
+        self.monthly_volume.get(&account).copied().unwrap_or(0.0)
+    }
+
+    // net of rebates, since the engine was created
+    pub fn get_fees(&self, account: AccountId) -> f64 {
+        self.fees.get(&account).copied().unwrap_or(0.0)
+    }
+
+    // the rate the account's next fill on `symbol` would be charged
+    pub fn rate(&self, account: AccountId, symbol: &Symbol, liquidity: Liquidity) -> f64 {
+        self.schedule.rates(symbol, self.monthly_volume(account)).get(liquidity)
+    }
+
+    // Charges one fill at the tier the account was in before it, then counts it
+    // towards the month's volume.
+    pub fn charge(&mut self, account: AccountId, symbol: &Symbol, liquidity: Liquidity, price: f64, quantity: f64) -> FeeCharge {
+        let rate = self.rate(account, symbol, liquidity);
+        let notional = price * quantity;
+        let amount = notional * rate;
+        *self.monthly_volume.entry(account).or_insert(0.0) += notional;
+        *self.fees.entry(account).or_insert(0.0) += amount;
+        FeeCharge { liquidity, rate, amount }
+    }
+
+    // charges the order on `side` of `trade`
+    pub fn charge_trade<P: PriceType, Q: QuantityType>(&mut self, account: AccountId, symbol: &Symbol, trade: &Trade<P, Q>, side: Side) -> FeeCharge {
+        let quantity = trade.get_quantity().to_f64();
+        self.charge(account, symbol, Liquidity::of(trade, side), trade.get_price().to_f64(), quantity)
+    }
+
+    // tiers start again from nothing
+    pub fn start_month(&mut self) {
+        self.monthly_volume.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::orderbook::{Order, OrderType, Orderbook};
+
+    #[test]
+    fn test_tiers_overrides_and_rebates(){
+        let mut schedule = FeeSchedule::flat(-0.0001, 0.0003);
+        schedule.add_tier(1_000_000.0, FeeRates { maker: -0.0002, taker: 0.0002 });
+        schedule.set_override("ODD".to_string(), FeeRates { maker: 0.0, taker: 0.001 });
+        let mut engine = FeeEngine::new(schedule);
+        let symbol = "ABC".to_string();
+
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        book.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Sell, 100, 10_000));
+        let trades = book.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 100, 10_000));
+        assert_eq!(trades[0].get_maker_side(), Side::Sell);
+
+        // the resting seller is rebated, the buyer pays
+        let maker = engine.charge_trade(7, &symbol, &trades[0], Side::Sell);
+        assert_eq!((maker.liquidity, maker.amount), (Liquidity::Maker, -100.0));
+        let taker = engine.charge_trade(8, &symbol, &trades[0], Side::Buy);
+        assert_eq!((taker.liquidity, taker.amount), (Liquidity::Taker, 300.0));
+
+        // a million traded moves account 8 up a tier, but not for the fill that got it there
+        assert_eq!(engine.monthly_volume(8), 1_000_000.0);
+        assert_eq!(engine.rate(8, &symbol, Liquidity::Taker), 0.0002);
+        assert_eq!(engine.rate(8, &"ODD".to_string(), Liquidity::Taker), 0.001);
+        assert_eq!(engine.charge(8, &symbol, Liquidity::Maker, 100.0, 100.0).amount, -2.0);
+        assert_eq!(engine.get_fees(8), 298.0);
+
+        engine.start_month();
+        assert_eq!(engine.rate(8, &symbol, Liquidity::Taker), 0.0003);
+        assert_eq!(engine.get_fees(8), 298.0);
+    }
+}
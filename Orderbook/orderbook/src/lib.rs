@@ -0,0 +1,9 @@
+pub mod audit;
+pub mod fees;
+pub mod orderbook;
+pub mod price;
+pub mod queue;
+pub mod risk;
+pub mod stats;
+#[cfg(test)]
+mod differential;
@@ -1,13 +1,14 @@
-mod orderbook;
-use std::{
-    rc::Rc,
-    cell::RefCell,
-    collections::{BTreeMap, HashMap}
-};
-use crate::orderbook::{Orderbook, Order, OrderType, Side};
+use std::collections::BTreeMap;
+use orderbook::orderbook::{Orderbook, Order, OrderType, Side};
+use tracing_subscriber::EnvFilter;
 
 
 fn main() {
+    // RUST_LOG=orderbook=trace shows every fill; info otherwise
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+
     let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
 
         orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
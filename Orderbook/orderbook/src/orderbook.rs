@@ -2,13 +2,15 @@
 use std::{
     rc::Rc,
     cell::RefCell,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     thread::{self, JoinHandle},
-    sync::{Arc, Mutex, Condvar},
+    sync::{Arc, Mutex, Condvar, Weak},
     sync::atomic::{AtomicBool, Ordering},
-    time::{Duration, SystemTime, UNIX_EPOCH}
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH}
 };
 use chrono::{Local, NaiveDateTime, TimeDelta, DateTime, Timelike};
+use crate::price::{PriceType, QuantityType, Ticks};
+use tracing::{debug, info, trace};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum OrderType {
@@ -17,14 +19,32 @@ pub enum OrderType {
     FillAndKill,
     FillOrKill,
     Market,
+    // rests like GoodTillCancel, but only ever trades for its whole remaining quantity
+    AllOrNone,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Side {
     Buy,
     Sell,
 }
 
+// What a pegged order's price follows: its own side's best (primary), the
+// contra side's best (market) or the midpoint between the two.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PegType {
+    Primary,
+    Midpoint,
+    Market,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrailReference {
+    // best bid for a sell stop, best ask for a buy stop
+    BestPrice,
+    LastTrade,
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 enum LevelDataAction {
     Add,
@@ -32,53 +52,182 @@ enum LevelDataAction {
     Match
 }
 
-type Price = i32;
-type Quantity = u32;
+pub type Price = Ticks;
+pub type Quantity = u64;
 type OrderId = u32;
+type OwnerId = u32;
+pub type Symbol = String;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Peg<P = Price> {
+    pub peg_type: PegType,
+    // added to the reference price, so negative offsets make a buy less aggressive
+    pub offset: P,
+    // a buy peg never goes above its limit, a sell peg never below
+    pub limit: Option<P>,
+}
+
+impl<P: PriceType> Peg<P> {
+    pub fn new(peg_type: PegType, offset: impl Into<P>, limit: Option<P>) -> Self {
+        Self { peg_type, offset: offset.into(), limit }
+    }
+
+    // None when the reference side of the book is empty
+    fn price(&self, side: Side, best_bid: Option<P>, best_ask: Option<P>) -> Option<P> {
+        let reference = match (self.peg_type, side) {
+            (PegType::Primary, Side::Buy) | (PegType::Market, Side::Sell) => best_bid?,
+            (PegType::Primary, Side::Sell) | (PegType::Market, Side::Buy) => best_ask?,
+            (PegType::Midpoint, _) => midpoint(side, best_bid?, best_ask?),
+        };
+        let price = reference + self.offset;
+        Some(match (side, self.limit) {
+            (Side::Buy, Some(limit)) => price.min(limit),
+            (Side::Sell, Some(limit)) => price.max(limit),
+            (_, None) => price,
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TrailAmount<P = Price> {
+    Offset(P),
+    Percent(f64),
+}
+
+impl<P: PriceType> TrailAmount<P> {
+    fn distance(&self, reference: P) -> P {
+        match *self {
+            TrailAmount::Offset(offset) => offset,
+            TrailAmount::Percent(percent) => P::from_f64(reference.to_f64() * percent / 100.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TrailingStop<P = Price> {
+    pub amount: TrailAmount<P>,
+    pub reference: TrailReference,
+    // how far past the trigger a limit stop may trade once fired; unused by market stops
+    pub limit_offset: P,
+}
+
+impl<P: PriceType> TrailingStop<P> {
+    pub fn new(amount: TrailAmount<P>, reference: TrailReference, limit_offset: impl Into<P>) -> Self {
+        Self { amount, reference, limit_offset: limit_offset.into() }
+    }
+
+    // The trigger trails the reference by the trail amount and only ever moves
+    // towards the market: up for sell stops, down for buy stops.
+    fn ratchet(&self, side: Side, reference: P, trigger: Option<P>) -> P {
+        let distance = self.amount.distance(reference);
+        match side {
+            Side::Sell => trigger.map_or(reference - distance, |trigger| trigger.max(reference - distance)),
+            Side::Buy => trigger.map_or(reference + distance, |trigger| trigger.min(reference + distance)),
+        }
+    }
+
+    fn limit_price(&self, side: Side, trigger: P) -> P {
+        match side {
+            Side::Sell => trigger - self.limit_offset,
+            Side::Buy => trigger + self.limit_offset,
+        }
+    }
+}
+
+// The default Ticks price holds the half tick, so the midpoint between two whole
+// ticks is exact. Only when it falls between two representable prices (a spread
+// of one unit of the type's resolution) is it rounded away from the contra side:
+// down for buys, up for sells.
+fn midpoint<P: PriceType>(side: Side, best_bid: P, best_ask: P) -> P {
+    P::midpoint_rounded(best_bid, best_ask, side == Side::Sell)
+}
 #[derive(Debug)]
-pub struct LevelInfo {
-    pub price: Price,
-    pub quantity: Quantity,
+pub struct LevelInfo<P = Price, Q = Quantity> {
+    pub price: P,
+    pub quantity: Q,
 }
 
-type LevelInfos = Vec<LevelInfo>;
+type LevelInfos<P = Price, Q = Quantity> = Vec<LevelInfo<P, Q>>;
+// best displayed price and quantity on one side, if it has any
+type BestLevel<P = Price, Q = Quantity> = Option<(P, Q)>;
 #[derive(Debug)]
-pub struct OrderbookLevelInfos {
-    bid_infos: LevelInfos,
-    ask_infos: LevelInfos,
+pub struct OrderbookLevelInfos<P = Price, Q = Quantity> {
+    bid_infos: LevelInfos<P, Q>,
+    ask_infos: LevelInfos<P, Q>,
 }
 
-impl OrderbookLevelInfos {
-    pub fn new(bids: LevelInfos, asks: LevelInfos) -> Self {
+impl<P: PriceType, Q: QuantityType> OrderbookLevelInfos<P, Q> {
+    pub fn new(bids: LevelInfos<P, Q>, asks: LevelInfos<P, Q>) -> Self {
         Self { bid_infos: bids, ask_infos: asks }
     }
-    pub const fn get_bids(&self) -> &LevelInfos {
+    pub const fn get_bids(&self) -> &LevelInfos<P, Q> {
         &self.bid_infos
     }
-    pub const fn get_asks(&self) -> &LevelInfos {
+    pub const fn get_asks(&self) -> &LevelInfos<P, Q> {
         &self.ask_infos
     }
 }
+
+// What it would cost to take `filled` from the displayed levels, best price first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepCost<P = Price, Q = Quantity> {
+    pub filled: Q,
+    pub notional: f64,
+    // the last level the sweep reached
+    pub worst_price: P,
+}
+
+impl<P: PriceType, Q: QuantityType> SweepCost<P, Q> {
+    pub fn vwap(&self) -> f64 {
+        self.notional / self.filled.to_f64()
+    }
+}
+// Where a resting order stands in its level's time priority queue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueuePosition<P = Price, Q = Quantity> {
+    pub side: Side,
+    pub price: P,
+    pub orders_ahead: usize,
+    // remaining quantity of the orders ahead, hidden included
+    pub ahead_quantity: Q,
+    // everything resting at the level, this order included
+    pub level_quantity: Q,
+}
+
 #[derive(Debug)]
-pub struct Order {
+pub struct Order<P = Price, Q = Quantity> {
     order_type: OrderType,
     order_id: OrderId,
     side: Side,
-    price: Price,
-    initial_quantity: Quantity,
-    remaining_quantity: Quantity,
-    filled_quantity: Quantity,
+    // None until the order has a limit: market orders, and pegs/stops before the book prices them
+    price: Option<P>,
+    initial_quantity: Q,
+    remaining_quantity: Q,
+    filled_quantity: Q,
     filled: bool,
+    peg: Option<Peg<P>>,
+    trail: Option<TrailingStop<P>>,
+    hidden: bool,
 }
 
-impl Order {
+impl<P: PriceType, Q: QuantityType> Order<P, Q> {
     //new pointer to order; will be used most of the time
     pub fn new(
         order_type: OrderType,
         order_id: OrderId,
         side: Side,
-        price: Price,
-        quantity: Quantity,
+        price: impl Into<P>,
+        quantity: Q,
+    ) -> Arc<Mutex<Self>> {
+        Self::with_price(order_type, order_id, side, Some(price.into()), quantity)
+    }
+
+    fn with_price(
+        order_type: OrderType,
+        order_id: OrderId,
+        side: Side,
+        price: Option<P>,
+        quantity: Q,
     ) -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(Self{
             order_type,
@@ -87,34 +236,78 @@ impl Order {
             price,
             initial_quantity: quantity,
             remaining_quantity: quantity,
-            filled_quantity: 0,
+            filled_quantity: Q::zero(),
             filled: false,
+            peg: None,
+            trail: None,
+            hidden: false,
         }))
     }
 
+    pub fn new_pegged(
+        order_type: OrderType,
+        order_id: OrderId,
+        side: Side,
+        peg: Peg<P>,
+        quantity: Q,
+    ) -> Arc<Mutex<Self>> {
+        // priced by the book from the peg when it's added
+        let order = Self::with_price(order_type, order_id, side, None, quantity);
+        order.lock().unwrap().peg = Some(peg);
+        order
+    }
+
+    // Matches like any other order but is never published, and queues behind
+    // the displayed orders at its price.
+    pub fn new_hidden(
+        order_type: OrderType,
+        order_id: OrderId,
+        side: Side,
+        price: impl Into<P>,
+        quantity: Q,
+    ) -> Arc<Mutex<Self>> {
+        let order = Self::new(order_type, order_id, side, price, quantity);
+        order.lock().unwrap().hidden = true;
+        order
+    }
+
+    // Held off the book until the trail is crossed, then added as `order_type`:
+    // a market order, or a limit at the trigger plus the stop's limit offset.
+    pub fn new_trailing_stop(
+        order_type: OrderType,
+        order_id: OrderId,
+        side: Side,
+        trail: TrailingStop<P>,
+        quantity: Q,
+    ) -> Arc<Mutex<Self>> {
+        let order = Self::with_price(order_type, order_id, side, None, quantity);
+        order.lock().unwrap().trail = Some(trail);
+        order
+    }
+
     pub fn new_market(
         order_id: OrderId,
         side: Side,
-        quantity: Quantity, 
+        quantity: Q, 
     ) -> Arc<Mutex<Self>> {
-        // Use an obviously invalid price for market orders, e.g., i32::MIN
-        Self::new(
+        // market orders have no price until the book converts them
+        Self::with_price(
             OrderType::Market,
             order_id,
             side,
-            i32::MIN,
+            None,
             quantity
         )
     }
 
-    pub fn to_good_till_cancel(&mut self, price: Price) -> Result<(), String> {
+    pub fn make_good_till_cancel(&mut self, price: P) -> Result<(), String> {
         match self.get_order_type(){
             OrderType::Market => {
-                self.price = price;
+                self.price = Some(price);
                 self.order_type = OrderType::GoodTillCancel;
                 Ok(())
             }
-            _ => return Err("Order cannot have its price adjusted, only market orders can.".to_string()),
+            _ => Err("Order cannot have its price adjusted, only market orders can.".to_string()),
         }
     }
 
@@ -124,59 +317,95 @@ impl Order {
     pub const fn get_side(&self) -> Side {
         self.side
     }
-    pub const fn get_price(&self) -> Price {
+    pub const fn get_price(&self) -> Option<P> {
         self.price
     }
     pub const fn get_order_type(&self) -> OrderType {
         self.order_type
     }
-    pub const fn get_initial_quantity(&self) -> Quantity {
+    pub const fn get_initial_quantity(&self) -> Q {
         self.initial_quantity
     }
-    pub const fn get_remaining_quantity(&self) -> Quantity {
+    pub const fn get_remaining_quantity(&self) -> Q {
         self.remaining_quantity
     }
-    pub const fn get_filled_quantity(&self) -> Quantity {
+    pub const fn get_filled_quantity(&self) -> Q {
         self.filled_quantity
     }
     pub const fn is_filled(&self) -> bool {
         self.filled
     }
+    // only for orders already on the book, which always carry a price
+    fn resting_price(&self) -> P {
+        self.price.expect("resting order without a price")
+    }
+    pub const fn get_peg(&self) -> Option<Peg<P>> {
+        self.peg
+    }
+    pub const fn get_trail(&self) -> Option<TrailingStop<P>> {
+        self.trail
+    }
+    pub const fn is_hidden(&self) -> bool {
+        self.hidden
+    }
 
-    pub fn fill(&mut self, quantity: Quantity) -> Result<(), String> {
+    pub fn fill(&mut self, quantity: Q) -> Result<(), String> {
         if quantity <= self.remaining_quantity {
             self.remaining_quantity -= quantity;
             self.filled_quantity += quantity;
-            if self.remaining_quantity == 0 {
+            if self.remaining_quantity == Q::zero() {
                 self.filled = true;
             }
-            println!("Filling {} by {}", self.order_id, quantity);
-            println!("Before: rem={}, filled={}", self.remaining_quantity, self.filled_quantity);   
+            trace!(
+                order_id = self.order_id,
+                side = ?self.side,
+                price = ?self.price,
+                quantity = ?quantity,
+                remaining = ?self.remaining_quantity,
+                "order filled"
+            );
             Ok(())
         } else {
             Err("Order cannot be filled for more than it's remaining quantity.".to_string())
         }
     }
 
-    
+    // changes the total size of the order; whatever was already filled stays filled
+    pub fn amend_quantity(&mut self, quantity: Q) -> Result<(), String> {
+        if quantity <= self.filled_quantity {
+            return Err("Order cannot be amended to less than it's filled quantity.".to_string());
+        }
+        self.initial_quantity = quantity;
+        self.remaining_quantity = quantity - self.filled_quantity;
+        Ok(())
+    }
+
+    // cancel/replace keeps the same order (and its fill state) but may change everything else
+    pub fn replace(&mut self, order_type: OrderType, side: Side, price: P, quantity: Q) -> Result<(), String> {
+        self.amend_quantity(quantity)?;
+        self.order_type = order_type;
+        self.side = side;
+        self.price = Some(price);
+        Ok(())
+    }
 }
 
-type OrderPointer = Arc<Mutex<Order>>;
-type OrderPointers = Vec<OrderPointer>;
+type OrderPointer<P = Price, Q = Quantity> = Arc<Mutex<Order<P, Q>>>;
+type OrderPointers<P = Price, Q = Quantity> = Vec<OrderPointer<P, Q>>;
 #[derive(Debug)]
-pub struct OrderModify {
+pub struct OrderModify<P = Price, Q = Quantity> {
     order_id: OrderId,
-    price: Price,
+    price: P,
     side: Side,
-    quantity: Quantity,
+    quantity: Q,
 }
 
-impl OrderModify {
-    pub fn new(order_id: OrderId, side: Side, price: Price, quantity: Quantity) -> Self {
+impl<P: PriceType, Q: QuantityType> OrderModify<P, Q> {
+    pub fn new(order_id: OrderId, side: Side, price: impl Into<P>, quantity: Q) -> Self {
         Self {
             order_id,
             side,
-            price,
+            price: price.into(),
             quantity,
         }
     }
@@ -187,14 +416,14 @@ impl OrderModify {
     pub const fn get_side(&self) -> Side {
         self.side
     }
-    pub const fn get_price(&self) -> Price {
+    pub const fn get_price(&self) -> P {
         self.price
     }
-    pub const fn get_quantity(&self) -> Quantity {
+    pub const fn get_quantity(&self) -> Q {
         self.quantity
     }
 
-    pub fn to_order_pointer(&self, order_type: OrderType) -> OrderPointer {
+    pub fn to_order_pointer(&self, order_type: OrderType) -> OrderPointer<P, Q> {
         Order::new(
             order_type,
             self.get_order_id(),
@@ -206,67 +435,248 @@ impl OrderModify {
 }
 
 #[derive(Debug, Clone, Copy)]
-pub struct TradeInfo {
+pub struct TradeInfo<P = Price, Q = Quantity> {
     pub order_id: OrderId,
-    pub price: Price,
-    pub quantity: Quantity,
+    pub price: P,
+    pub quantity: Q,
 }
 #[derive(Debug)]
-pub struct Trade{
-    bid_trade: TradeInfo,
-    ask_trade: TradeInfo,
+pub struct Trade<P = Price, Q = Quantity>{
+    bid_trade: TradeInfo<P, Q>,
+    ask_trade: TradeInfo<P, Q>,
+    // where the trade printed: the resting order's price
+    price: P,
+    // the resting side, which provided the liquidity
+    maker_side: Side,
 }
 
-impl Trade{
-    pub fn new(bid_trade: TradeInfo, ask_trade: TradeInfo) -> Self{
+impl<P: PriceType, Q: QuantityType> Trade<P, Q>{
+    pub fn new(bid_trade: TradeInfo<P, Q>, ask_trade: TradeInfo<P, Q>, price: P, maker_side: Side) -> Self{
         Self{
             bid_trade,
             ask_trade,
+            price,
+            maker_side,
         }
     }
 
-    pub const fn get_bid_trade(&self) -> TradeInfo {
+    pub const fn get_price(&self) -> P {
+        self.price
+    }
+
+    pub const fn get_maker_side(&self) -> Side {
+        self.maker_side
+    }
+
+    pub const fn get_quantity(&self) -> Q {
+        self.bid_trade.quantity
+    }
+
+    pub const fn get_bid_trade(&self) -> TradeInfo<P, Q> {
         self.bid_trade
     }
 
-    pub const fn get_ask_trade(&self) -> TradeInfo {
+    pub const fn get_ask_trade(&self) -> TradeInfo<P, Q> {
         self.ask_trade
     }
 }
 
-type Trades = Vec<Trade>;
+type Trades<P = Price, Q = Quantity> = Vec<Trade<P, Q>>;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ModifyReject {
+    UnknownOrder,
+    InvalidQuantity,
+    InvalidOrderType,
+    // a quote leg only moves with a new quote; it can still be reduced in place
+    QuoteLeg,
+}
+
+#[derive(Debug)]
+pub enum ModifyOutcome<P = Price, Q = Quantity> {
+    // quantity was reduced (or left alone) at the same price; queue priority kept.
+    // A smaller all-or-none order may become fillable, so this can trade too
+    AmendedInPlace(Trades<P, Q>),
+    // price, side, type changed or quantity increased; order went to the back of the queue
+    Replaced(Trades<P, Q>),
+    // new quantity was at or below what had already been filled, nothing left to rest;
+    // holds any trades from pegged orders repricing off the new top of book
+    Cancelled(Trades<P, Q>),
+    Rejected(ModifyReject),
+}
+
+// One side of a two-sided quote. A zero quantity means the owner isn't quoting that side.
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteLeg<P = Price, Q = Quantity> {
+    pub order_id: OrderId,
+    pub price: P,
+    pub quantity: Q,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Quote<P = Price, Q = Quantity> {
+    owner: OwnerId,
+    bid: QuoteLeg<P, Q>,
+    ask: QuoteLeg<P, Q>,
+}
+
+impl<P: PriceType, Q: QuantityType> Quote<P, Q> {
+    pub fn new(owner: OwnerId, bid: QuoteLeg<P, Q>, ask: QuoteLeg<P, Q>) -> Self {
+        Self { owner, bid, ask }
+    }
+
+    pub const fn get_owner(&self) -> OwnerId {
+        self.owner
+    }
+    pub const fn get_bid(&self) -> QuoteLeg<P, Q> {
+        self.bid
+    }
+    pub const fn get_ask(&self) -> QuoteLeg<P, Q> {
+        self.ask
+    }
+
+    fn legs(&self) -> impl Iterator<Item = (Side, QuoteLeg<P, Q>)> {
+        [(Side::Buy, self.bid), (Side::Sell, self.ask)]
+            .into_iter()
+            .filter(|(_, leg)| leg.quantity > Q::zero())
+    }
+}
+
+// Pulls all of an owner's quotes once they take `max_fills` fills inside `window`.
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteProtection {
+    pub max_fills: u32,
+    pub window: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QuoteReject {
+    CrossedQuote,
+    DuplicateOrderId,
+    ProtectionTripped,
+    UnknownSymbol,
+}
+
+#[derive(Debug)]
+pub enum QuoteOutcome<P = Price, Q = Quantity> {
+    Accepted(Trades<P, Q>),
+    // the new quote traded enough to trip the owner's protection and was pulled
+    Pulled(Trades<P, Q>),
+    Rejected(QuoteReject),
+}
 
 ///////////////////////////////////////
 #[derive(Debug)]
-struct OrderEntry {
-    order: OrderPointer,
+struct OrderEntry<P, Q> {
+    order: OrderPointer<P, Q>,
     location: usize,
     side: Side,
-    price: Price,
+    price: P,
+    // when the order last joined the book; the lower one in a trade was resting
+    sequence: u64,
+}
+
+#[derive(Debug)]
+struct StopEntry<P, Q> {
+    order: OrderPointer<P, Q>,
+    // None until there's a reference price to trail
+    trigger: Option<P>,
+}
+
+#[derive(Debug, Default)]
+struct QuoteEntry {
+    legs: Vec<OrderId>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct OwnerProtection {
+    protection: Option<QuoteProtection>,
+    fills: VecDeque<Instant>,
+    pulled: bool,
 }
 
+// Quote protection shared by a group of books: an owner's fills count together
+// across all of them, and a trip on one pulls the owner's quotes from every one.
 #[derive(Debug)]
-struct LevelData{
-    pub quantity: Quantity,
-    pub count: Quantity,
+struct ProtectionGroup<P, Q> {
+    owners: HashMap<OwnerId, OwnerProtection>,
+    books: Vec<Weak<Mutex<InnerOrderbook<P, Q>>>>,
+}
+
+impl<P, Q> ProtectionGroup<P, Q> {
+    fn new() -> Self {
+        Self { owners: HashMap::new(), books: vec![] }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LevelData<Q>{
+    pub displayed_quantity: Q,
+    pub hidden_quantity: Q,
+    pub count: u32,
+}
+
+impl<Q: QuantityType> LevelData<Q> {
+    // everything an incoming order could trade against, published or not
+    fn total_quantity(&self) -> Q {
+        self.displayed_quantity + self.hidden_quantity
+    }
 }
 
 
 #[derive(Debug)]
-pub struct Orderbook {
-    inner: Arc<Mutex<InnerOrderbook>>,
+pub struct Orderbook<P = Price, Q = Quantity> {
+    inner: Arc<Mutex<InnerOrderbook<P, Q>>>,
 }
 
+// new/build are pinned to the default Price/Quantity so integer literals in
+// callers keep inferring; use with_levels/build_with_levels for other types.
 impl Orderbook {
     pub fn new(bids: BTreeMap<Price, OrderPointers>, asks: BTreeMap<Price, OrderPointers>) -> Self {
-        let inner = InnerOrderbook::new(bids, asks);
-        Self {
-            inner: Arc::new(Mutex::new(inner)),
-        }
+        Self::with_levels(bids, asks)
     }
 
     pub fn build(bids: BTreeMap<Price, OrderPointers>, asks: BTreeMap<Price, OrderPointers>, test_mode: bool) -> Self {
-        let mut book = Self::new(bids, asks);
+        Self::build_with_levels(bids, asks, test_mode)
+    }
+}
+
+impl<P: PriceType, Q: QuantityType> Orderbook<P, Q> {
+    pub fn with_levels(bids: BTreeMap<P, OrderPointers<P, Q>>, asks: BTreeMap<P, OrderPointers<P, Q>>) -> Self {
+        let inner = Arc::new(Mutex::new(InnerOrderbook::new(bids, asks)));
+        let protection = Arc::clone(&inner.lock().unwrap().protection);
+        protection.lock().unwrap().books.push(Arc::downgrade(&inner));
+        Self { inner }
+    }
+
+    // Runs an operation on the book, then pulls any owner it tripped from the
+    // other books in its protection group. Those books only drop the legs; their
+    // pegs and stops catch up on their next event.
+    fn with_inner<R>(&self, operation: impl FnOnce(&mut InnerOrderbook<P, Q>) -> R) -> R {
+        let (result, tripped, protection) = {
+            let mut inner = self.inner.lock().unwrap();
+            let result = operation(&mut inner);
+            (result, std::mem::take(&mut inner.tripped_owners), Arc::clone(&inner.protection))
+        };
+        if tripped.is_empty() {
+            return result;
+        }
+
+        let books = protection.lock().unwrap().books.clone();
+        for book in books.iter().filter_map(Weak::upgrade) {
+            if Arc::ptr_eq(&book, &self.inner) {
+                continue;
+            }
+            let mut book = book.lock().unwrap();
+            for owner in &tripped {
+                book.drop_quote(*owner);
+            }
+        }
+        result
+    }
+
+    pub fn build_with_levels(bids: BTreeMap<P, OrderPointers<P, Q>>, asks: BTreeMap<P, OrderPointers<P, Q>>, test_mode: bool) -> Self {
+        let mut book = Self::with_levels(bids, asks);
         let inner = Arc::clone(&book.inner);
         let handle = thread::spawn(move || {
             let mut ob = inner.lock().unwrap();
@@ -276,44 +686,183 @@ impl Orderbook {
         book
     }
 
-    pub fn add_order(&self, order: OrderPointer) -> Trades {
-        self.inner.lock().unwrap().add_order(order)
+    pub fn add_order(&self, order: OrderPointer<P, Q>) -> Trades<P, Q> {
+        self.with_inner(|inner| inner.add_order(order))
+    }
+
+    pub fn cancel_order(&self, order_id: OrderId) -> Trades<P, Q> {
+        self.with_inner(|inner| inner.cancel_order(order_id))
+    }
+
+    pub fn modify_order(&self, order: OrderModify<P, Q>) -> ModifyOutcome<P, Q> {
+        self.with_inner(|inner| inner.modify_order(order))
+    }
+
+    pub fn replace_order(&self, order: OrderModify<P, Q>, order_type: OrderType) -> ModifyOutcome<P, Q> {
+        self.with_inner(|inner| inner.replace_order(order, order_type))
+    }
+
+    pub fn submit_quote(&self, quote: Quote<P, Q>) -> QuoteOutcome<P, Q> {
+        self.with_inner(|inner| inner.submit_quote(quote))
+    }
+
+    pub fn cancel_quote(&self, owner: OwnerId) -> Trades<P, Q> {
+        self.with_inner(|inner| inner.cancel_quote(owner))
+    }
+
+    pub fn set_quote_protection(&self, owner: OwnerId, protection: QuoteProtection) {
+        self.inner.lock().unwrap().set_quote_protection(owner, protection)
+    }
+
+    pub fn reset_quote_protection(&self, owner: OwnerId) {
+        self.inner.lock().unwrap().reset_quote_protection(owner)
     }
 
-    pub fn cancel_order(&self, order_id: OrderId) {
-        self.inner.lock().unwrap().cancel_order(order_id)
+    // Moves `other` into this book's protection group. Owners it already had
+    // settings for keep them unless the group has its own.
+    pub fn share_quote_protection(&self, other: &Orderbook<P, Q>) {
+        let protection = Arc::clone(&self.inner.lock().unwrap().protection);
+        let previous = std::mem::replace(&mut other.inner.lock().unwrap().protection, Arc::clone(&protection));
+        if Arc::ptr_eq(&previous, &protection) {
+            return;
+        }
+
+        let handle = Arc::downgrade(&other.inner);
+        let owners = {
+            let mut previous = previous.lock().unwrap();
+            previous.books.retain(|book| !book.ptr_eq(&handle));
+            previous.owners.clone()
+        };
+        let mut protection = protection.lock().unwrap();
+        protection.books.push(handle);
+        for (owner, state) in owners {
+            protection.owners.entry(owner).or_insert(state);
+        }
     }
 
-    pub fn modify_order(&self, order: OrderModify) -> Trades {
-        self.inner.lock().unwrap().modify_order(order)
+    // Each book's quote is replaced atomically. The books in the map share quote
+    // protection, so a trip on any of them pulls the owner from all of them.
+    pub fn mass_quote(books: &HashMap<Symbol, Orderbook<P, Q>>, quotes: Vec<(Symbol, Quote<P, Q>)>) -> Vec<(Symbol, QuoteOutcome<P, Q>)> {
+        let mut group = books.values();
+        if let Some(first) = group.next() {
+            for book in group {
+                first.share_quote_protection(book);
+            }
+        }
+
+        quotes.into_iter()
+            .map(|(symbol, quote)| {
+                let outcome = match books.get(&symbol) {
+                    Some(book) => book.submit_quote(quote),
+                    None => QuoteOutcome::Rejected(QuoteReject::UnknownSymbol),
+                };
+                (symbol, outcome)
+            })
+            .collect()
+    }
+
+    pub fn get_stop_trigger(&self, order_id: OrderId) -> Option<P> {
+        self.inner.lock().unwrap().get_stop_trigger(order_id)
+    }
+
+    pub fn get_last_trade_price(&self) -> Option<P> {
+        self.inner.lock().unwrap().get_last_trade_price()
     }
 
     pub fn size(&self) -> usize {
         self.inner.lock().unwrap().size()
     }
 
-    pub fn get_order_infos(&self) -> OrderbookLevelInfos {
+    pub fn contains(&self, order_id: OrderId) -> bool {
+        self.inner.lock().unwrap().contains(order_id)
+    }
+
+    pub fn queue_position(&self, order_id: OrderId) -> Option<QueuePosition<P, Q>> {
+        self.inner.lock().unwrap().queue_position(order_id)
+    }
+
+    #[cfg(test)]
+    pub fn check_invariants(&self) -> Result<(), String> {
+        self.inner.lock().unwrap().check_invariants()
+    }
+
+    pub fn get_order_infos(&self) -> OrderbookLevelInfos<P, Q> {
         self.inner.lock().unwrap().get_order_infos()
     }
+
+    pub fn best_bid(&self) -> Option<P> {
+        self.inner.lock().unwrap().best_displayed_price(Side::Buy)
+    }
+
+    pub fn best_ask(&self) -> Option<P> {
+        self.inner.lock().unwrap().best_displayed_price(Side::Sell)
+    }
+
+    pub fn top_of_book(&self) -> (BestLevel<P, Q>, BestLevel<P, Q>) {
+        self.inner.lock().unwrap().top_of_book()
+    }
+
+    pub fn spread(&self) -> Option<P> {
+        self.inner.lock().unwrap().spread()
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        self.inner.lock().unwrap().mid()
+    }
+
+    pub fn microprice(&self) -> Option<f64> {
+        self.inner.lock().unwrap().microprice()
+    }
+
+    pub fn imbalance(&self, levels: usize) -> Option<f64> {
+        self.inner.lock().unwrap().imbalance(levels)
+    }
+
+    pub fn depth_at(&self, side: Side, price: P) -> Q {
+        self.inner.lock().unwrap().depth_at(side, price)
+    }
+
+    pub fn sweep_cost(&self, side: Side, quantity: Q) -> Option<SweepCost<P, Q>> {
+        self.inner.lock().unwrap().sweep_cost(side, quantity)
+    }
 }
 
 #[derive(Debug)]
-pub struct InnerOrderbook {
-    data: HashMap<Price, LevelData>,
-    bids: BTreeMap<Price, OrderPointers>,
-    asks: BTreeMap<Price, OrderPointers>,
-    orders: HashMap<OrderId, OrderEntry>,
+pub struct InnerOrderbook<P = Price, Q = Quantity> {
+    data: HashMap<(Side, P), LevelData<Q>>,
+    bids: BTreeMap<P, OrderPointers<P, Q>>,
+    asks: BTreeMap<P, OrderPointers<P, Q>>,
+    orders: HashMap<OrderId, OrderEntry<P, Q>>,
+    quotes: HashMap<OwnerId, QuoteEntry>,
+    quote_legs: HashMap<OrderId, OwnerId>,
+    protection: Arc<Mutex<ProtectionGroup<P, Q>>>,
+    // owners tripped since the wrapper last pulled them from the rest of the group
+    tripped_owners: Vec<OwnerId>,
+    pegged: BTreeSet<OrderId>,
+    all_or_none: BTreeSet<OrderId>,
+    stops: BTreeMap<OrderId, StopEntry<P, Q>>,
+    next_sequence: u64,
+    last_trade_price: Option<P>,
     orders_prune_thread: Option<JoinHandle<()>>,
     shutdown_condition_variable: Condvar,
     shutdown: AtomicBool,
 }
 
-impl InnerOrderbook {
-    pub fn new(bids: BTreeMap<Price, OrderPointers>, asks: BTreeMap<Price, OrderPointers>) -> Self {
+impl<P: PriceType, Q: QuantityType> InnerOrderbook<P, Q> {
+    pub fn new(bids: BTreeMap<P, OrderPointers<P, Q>>, asks: BTreeMap<P, OrderPointers<P, Q>>) -> Self {
         Self {
             bids,
             asks,
             orders: HashMap::new(),
+            quotes: HashMap::new(),
+            quote_legs: HashMap::new(),
+            protection: Arc::new(Mutex::new(ProtectionGroup::new())),
+            tripped_owners: vec![],
+            pegged: BTreeSet::new(),
+            all_or_none: BTreeSet::new(),
+            stops: BTreeMap::new(),
+            next_sequence: 0,
+            last_trade_price: None,
             orders_prune_thread: None,
             shutdown_condition_variable: Condvar::new(),
             shutdown: AtomicBool::new(false),
@@ -325,272 +874,791 @@ impl InnerOrderbook {
         self.orders.len()
     }
 
-    pub fn get_order_infos(&self) -> OrderbookLevelInfos {
-        let mut bid_infos: LevelInfos = Vec::with_capacity(self.orders.len());
-        let mut ask_infos: LevelInfos = Vec::with_capacity(self.orders.len());
+    // Structural checks for the property tests: the book is never left with a
+    // trade it should have made, LevelData matches the orders it summarises, and
+    // every order in `orders` is where its entry says it is.
+    #[cfg(test)]
+    pub fn check_invariants(&self) -> Result<(), String> {
+        if self.next_match().is_some() {
+            return Err("book left with an executable cross".to_string());
+        }
+        if self.all_or_none.is_empty() {
+            if let (Some((bid, _)), Some((ask, _))) = (self.bids.last_key_value(), self.asks.first_key_value()) {
+                if bid >= ask {
+                    return Err(format!("crossed book without all-or-none orders: {:?} >= {:?}", bid, ask));
+                }
+            }
+        }
 
-        let create_level_infos = |price: Price, orders: &OrderPointers| {
-            let total_quantity = orders.iter().fold(0, |sum, order| {
-                sum + order.lock().unwrap().get_remaining_quantity()
-            });
-            LevelInfo { price, quantity: total_quantity }
+        let mut resting = 0;
+        let mut levels = 0;
+        for (side, book) in [(Side::Buy, &self.bids), (Side::Sell, &self.asks)] {
+            for (price, orders) in book {
+                if orders.is_empty() {
+                    return Err(format!("empty {:?} level left at {:?}", side, price));
+                }
+                let mut expected = LevelData { count: orders.len() as u32, ..Default::default() };
+                for (location, order) in orders.iter().enumerate() {
+                    let ord = order.lock().unwrap();
+                    if ord.get_initial_quantity() != ord.get_remaining_quantity() + ord.get_filled_quantity() {
+                        return Err(format!("order {} quantities don't add up", ord.get_order_id()));
+                    }
+                    if ord.is_filled() || ord.get_remaining_quantity() == Q::zero() {
+                        return Err(format!("filled order {} still resting", ord.get_order_id()));
+                    }
+                    match self.orders.get(&ord.get_order_id()) {
+                        Some(entry) if entry.side == side && entry.price == *price && entry.location == location && ord.price == Some(*price) => {}
+                        _ => return Err(format!("order {} is not where its entry says", ord.get_order_id())),
+                    }
+                    if ord.is_hidden() {
+                        expected.hidden_quantity += ord.get_remaining_quantity();
+                    } else {
+                        expected.displayed_quantity += ord.get_remaining_quantity();
+                    }
+                }
+                let data = self.data.get(&(side, *price)).ok_or(format!("no level data for {:?} {:?}", side, price))?;
+                if (data.displayed_quantity, data.hidden_quantity, data.count) != (expected.displayed_quantity, expected.hidden_quantity, expected.count) {
+                    return Err(format!("level data for {:?} {:?} is {:?}, orders add up to {:?}", side, price, data, expected));
+                }
+                resting += orders.len();
+                levels += 1;
+            }
+        }
+        if resting != self.orders.len() {
+            return Err(format!("{} orders on the book but {} entries", resting, self.orders.len()));
+        }
+        if levels != self.data.len() {
+            return Err(format!("{} levels on the book but {} level data entries", levels, self.data.len()));
+        }
+        let tracked = self.pegged.iter().chain(&self.all_or_none).chain(self.quote_legs.keys());
+        if let Some(order_id) = tracked.into_iter().find(|order_id| !self.orders.contains_key(order_id)) {
+            return Err(format!("order {} is tracked but not on the book", order_id));
+        }
+        Ok(())
+    }
+
+    // resting on the book or waiting as a stop
+    pub fn contains(&self, order_id: OrderId) -> bool {
+        self.orders.contains_key(&order_id) || self.stops.contains_key(&order_id)
+    }
+
+    // None for orders that aren't resting, stops included
+    pub fn queue_position(&self, order_id: OrderId) -> Option<QueuePosition<P, Q>> {
+        let entry = self.orders.get(&order_id)?;
+        let level = match entry.side {
+            Side::Buy => self.bids.get(&entry.price)?,
+            Side::Sell => self.asks.get(&entry.price)?,
         };
+        let ahead_quantity = level[..entry.location].iter().map(|order| order.lock().unwrap().get_remaining_quantity()).sum();
+        let level_quantity = self.data.get(&(entry.side, entry.price)).map_or(Q::zero(), |data| data.total_quantity());
+        Some(QueuePosition { side: entry.side, price: entry.price, orders_ahead: entry.location, ahead_quantity, level_quantity })
+    }
+
+    pub fn get_stop_trigger(&self, order_id: OrderId) -> Option<P> {
+        self.stops.get(&order_id).and_then(|stop| stop.trigger)
+    }
+
+    pub const fn get_last_trade_price(&self) -> Option<P> {
+        self.last_trade_price
+    }
+
+    // Published depth: hidden quantity is left out, as are levels with nothing displayed.
+    pub fn get_order_infos(&self) -> OrderbookLevelInfos<P, Q> {
+        let mut bid_infos: LevelInfos<P, Q> = Vec::with_capacity(self.bids.len());
+        let mut ask_infos: LevelInfos<P, Q> = Vec::with_capacity(self.asks.len());
 
-        for (price, orders) in &self.bids {
-            bid_infos.push(create_level_infos(*price, orders));
+        let create_level_infos = |side: Side, price: P| {
+            self.data.get(&(side, price))
+                .filter(|data| data.displayed_quantity > Q::zero())
+                .map(|data| LevelInfo { price, quantity: data.displayed_quantity })
+        };
+
+        for price in self.bids.keys() {
+            bid_infos.extend(create_level_infos(Side::Buy, *price));
         }
 
-        for (price, orders) in &self.asks {
-            ask_infos.push(create_level_infos(*price, orders));
+        for price in self.asks.keys() {
+            ask_infos.extend(create_level_infos(Side::Sell, *price));
         }
 
         OrderbookLevelInfos { bid_infos, ask_infos }
     }
 
-    pub fn add_order(&mut self, order: OrderPointer) -> Trades {
+    // Top of book analytics. These only see displayed quantity, the same view
+    // get_order_infos publishes, and walk LevelData from the best price out so
+    // they cost one lookup per level touched.
+
+    // displayed levels on `side`, best price first
+    fn displayed_levels(&self, side: Side) -> impl Iterator<Item = (P, Q)> + '_ {
+        let prices: Box<dyn Iterator<Item = &P>> = match side {
+            Side::Buy => Box::new(self.bids.keys().rev()),
+            Side::Sell => Box::new(self.asks.keys()),
+        };
+        prices.filter_map(move |price| {
+            self.data.get(&(side, *price))
+                .filter(|data| data.displayed_quantity > Q::zero())
+                .map(|data| (*price, data.displayed_quantity))
+        })
+    }
+
+    // bid then ask
+    pub fn top_of_book(&self) -> (BestLevel<P, Q>, BestLevel<P, Q>) {
+        (self.displayed_levels(Side::Buy).next(), self.displayed_levels(Side::Sell).next())
+    }
+
+    pub fn spread(&self) -> Option<P> {
+        let best_bid = self.best_displayed_price(Side::Buy)?;
+        let best_ask = self.best_displayed_price(Side::Sell)?;
+        Some(best_ask - best_bid)
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        let best_bid = self.best_displayed_price(Side::Buy)?;
+        let best_ask = self.best_displayed_price(Side::Sell)?;
+        Some((best_bid.to_f64() + best_ask.to_f64()) / 2.0)
+    }
+
+    // Mid weighted by the size on the other side of the touch: a heavy bid
+    // pulls the fair price towards the ask.
+    pub fn microprice(&self) -> Option<f64> {
+        let (bid_price, bid_quantity) = self.displayed_levels(Side::Buy).next()?;
+        let (ask_price, ask_quantity) = self.displayed_levels(Side::Sell).next()?;
+        let (bid_quantity, ask_quantity) = (bid_quantity.to_f64(), ask_quantity.to_f64());
+        Some((bid_price.to_f64() * ask_quantity + ask_price.to_f64() * bid_quantity) / (bid_quantity + ask_quantity))
+    }
+
+    // (bid - ask) / (bid + ask) over the top `levels` of each side, from -1
+    // (all asks) to 1 (all bids). None on an empty book.
+    pub fn imbalance(&self, levels: usize) -> Option<f64> {
+        let bid: Q = self.displayed_levels(Side::Buy).take(levels).map(|(_, quantity)| quantity).sum();
+        let ask: Q = self.displayed_levels(Side::Sell).take(levels).map(|(_, quantity)| quantity).sum();
+        let total = bid.to_f64() + ask.to_f64();
+        if total == 0.0 {
+            return None;
+        }
+        Some((bid.to_f64() - ask.to_f64()) / total)
+    }
+
+    // displayed quantity on `side` at `price` or better
+    pub fn depth_at(&self, side: Side, price: P) -> Q {
+        self.displayed_levels(side)
+            .take_while(|(level_price, _)| match side {
+                Side::Buy => *level_price >= price,
+                Side::Sell => *level_price <= price,
+            })
+            .map(|(_, quantity)| quantity)
+            .sum()
+    }
+
+    // Cost for an order on `side` to take `quantity` from the other side's
+    // displayed levels. Stops short with a partial `filled` if the book is too thin.
+    pub fn sweep_cost(&self, side: Side, quantity: Q) -> Option<SweepCost<P, Q>> {
+        let contra_side = match side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+        let mut sweep: Option<SweepCost<P, Q>> = None;
+        let mut left = quantity;
+        for (price, level_quantity) in self.displayed_levels(contra_side) {
+            if left == Q::zero() {
+                break;
+            }
+            let take = left.min(level_quantity);
+            left -= take;
+            let cost = sweep.get_or_insert(SweepCost { filled: Q::zero(), notional: 0.0, worst_price: price });
+            cost.filled += take;
+            cost.notional += price.to_f64() * take.to_f64();
+            cost.worst_price = price;
+        }
+        sweep
+    }
+
+    pub fn add_order(&mut self, order: OrderPointer<P, Q>) -> Trades<P, Q> {
         {
             let mut ord = order.lock().unwrap();
-            if self.orders.contains_key(&ord.get_order_id()){
+            let order_id = ord.get_order_id();
+            if self.orders.contains_key(&order_id) || self.stops.contains_key(&order_id) {
+                debug!(order_id, "rejected: duplicate order id");
                 return vec![];
             }
 
+            if ord.get_trail().is_some() {
+                drop(ord);
+                self.stops.insert(order_id, StopEntry { order, trigger: None });
+                return self.update_trailing_stops();
+            }
+
             if ord.get_order_type() == OrderType::Market {
                 let result = match ord.get_side() {
                     Side::Buy if !self.asks.is_empty() => {
                         let (worst_ask, _) = self.asks.iter().next_back().unwrap();
-                        ord.to_good_till_cancel(*worst_ask)
+                        ord.make_good_till_cancel(*worst_ask)
                     }
                     Side::Sell if !self.bids.is_empty() => {
                         let (worst_bid, _) = self.bids.iter().next().unwrap();
-                        ord.to_good_till_cancel(*worst_bid)
+                        ord.make_good_till_cancel(*worst_bid)
+                    }
+                    _ => {
+                        debug!(order_id, "rejected: market order with no contra side");
+                        return vec![];
                     }
-                    _ => return vec![],
                 };
                 if result.is_err() {
                     return vec![];
                 }
             }
 
+            if let Some(peg) = ord.get_peg() {
+                let best_bid = self.best_unpegged_price(Side::Buy);
+                let best_ask = self.best_unpegged_price(Side::Sell);
+                match peg.price(ord.get_side(), best_bid, best_ask) {
+                    Some(price) => ord.price = Some(price),
+                    None => {
+                        debug!(order_id, "rejected: no reference price for peg");
+                        return vec![];
+                    }
+                }
+            }
+
             let order_type = ord.get_order_type();
             let side = ord.get_side();
-            let price = ord.get_price();
-            let initial_quantity = ord.get_initial_quantity();
+            let price = ord.resting_price();
+            let remaining_quantity = ord.get_remaining_quantity();
 
             if order_type == OrderType::FillAndKill && !self.can_match(side, price) {
+                debug!(order_id, ?side, ?price, "rejected: fill and kill can't match");
                 return vec![];
             }
 
-            if order_type == OrderType::FillOrKill && !self.can_fully_fill(side, price, initial_quantity) {
+            if order_type == OrderType::FillOrKill && !self.can_fully_fill(side, price, remaining_quantity) {
+                debug!(order_id, ?side, ?price, quantity = ?remaining_quantity, "rejected: fill or kill can't fully fill");
                 return vec![];
             }
+        }
+        let (order_id, order_type) = {
+            let ord = order.lock().unwrap();
+            (ord.get_order_id(), ord.get_order_type())
+        };
+        self.rest_order(order);
+        if !matches!(order_type, OrderType::FillAndKill | OrderType::FillOrKill) {
+            return self.match_orders();
+        }
 
-            let mut index: usize = 0;
-            if side == Side::Buy {
-                let orders = &mut self.bids.entry(price).or_default();
-                orders.push(order.clone());
-                index = orders.len() - 1;
+        // An immediate order only crosses. Whatever is left (all-or-none liquidity can
+        // leave it short) is killed before pegs or trailing stops can see its price.
+        self.drop_pulled_quotes();
+        let mut trades = self.cross_orders();
+        if self.orders.contains_key(&order_id) {
+            self.drop_order(order_id);
+        }
+        trades.extend(self.match_orders());
+        trades
+    }
+
+    // puts an already validated order at the back of its price level
+    fn rest_order(&mut self, order: OrderPointer<P, Q>) {
+        {
+            let ord = order.lock().unwrap();
+            let (order_id, side, price) = (ord.get_order_id(), ord.get_side(), ord.resting_price());
+
+            let orders = match side {
+                Side::Buy => self.bids.entry(price).or_default(),
+                Side::Sell => self.asks.entry(price).or_default(),
+            };
+            // displayed orders go ahead of any hidden ones already at the level
+            let index = if ord.is_hidden() {
+                orders.len()
             } else {
-                let orders = &mut self.asks.entry(price).or_default();
-                orders.push(order.clone());
-                index = orders.len() - 1;
+                orders.len() - orders.iter().rev().take_while(|order| order.lock().unwrap().is_hidden()).count()
+            };
+            orders.insert(index, order.clone());
+            for hidden in &orders[index + 1..] {
+                let hidden_id = hidden.lock().unwrap().get_order_id();
+                if let Some(entry) = self.orders.get_mut(&hidden_id) {
+                    entry.location += 1;
+                }
             }
 
-            let order_id = ord.get_order_id();
-            self.orders.insert(order_id, OrderEntry {order: order.clone(), location: index, side, price,});
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+            trace!(order_id, ?side, ?price, quantity = ?ord.get_remaining_quantity(), sequence, "order rested");
+            self.orders.insert(order_id, OrderEntry {order: order.clone(), location: index, side, price, sequence});
+            if ord.get_peg().is_some() {
+                self.pegged.insert(order_id);
+            }
+            if ord.get_order_type() == OrderType::AllOrNone {
+                self.all_or_none.insert(order_id);
+            }
         }
-        self.on_order_added(order.clone());
+        self.on_order_added(order);
+    }
+
+
+    pub fn cancel_order(&mut self, order_id: OrderId) -> Trades<P, Q> {
+        debug!(order_id, "cancel");
+        self.drop_order(order_id);
+        // the top of book may have moved under pegged orders
         self.match_orders()
     }
 
+    // takes an order out of the book and its level data without rematching
+    fn drop_order(&mut self, order_id: OrderId) {
+        if self.stops.remove(&order_id).is_some() {
+            return;
+        }
+        if let Some(order) = self.remove_order_from_book(order_id) {
+            self.on_order_cancelled(order);
+        }
+    }
 
-    pub fn cancel_order(&mut self, order_id: OrderId) {
-        if let Some(entry) = self.orders.remove(&order_id) {
-            let OrderEntry { order, location, side, price } = entry;
+    pub fn modify_order(&mut self, order: OrderModify<P, Q>) -> ModifyOutcome<P, Q> {
+        self.amend_order(order, None)
+    }
 
-            let maybe_queue = match side {
-                Side::Buy => self.bids.get_mut(&price),
-                Side::Sell => self.asks.get_mut(&price),
-            };
+    pub fn replace_order(&mut self, order: OrderModify<P, Q>, order_type: OrderType) -> ModifyOutcome<P, Q> {
+        self.amend_order(order, Some(order_type))
+    }
 
-            if let Some(queue) = maybe_queue {
-                let last_index = queue.len() - 1;
-                queue.swap_remove(location);
+    // Exchange style amend: a quantity decrease at the same price is done in place and keeps
+    // time priority; anything else is a cancel/replace that sends the order to the back.
+    // The order's quantity is its new total size, so fills so far count against it.
+    fn amend_order(&mut self, modify: OrderModify<P, Q>, new_order_type: Option<OrderType>) -> ModifyOutcome<P, Q> {
+        let order_id = modify.get_order_id();
+        let order = match self.orders.get(&order_id) {
+            Some(entry) => entry.order.clone(),
+            None => return ModifyOutcome::Rejected(ModifyReject::UnknownOrder),
+        };
 
-                if location < queue.len() {
-                    let moved_order = &queue[location];
-                    let moved_id = moved_order.lock().unwrap().get_order_id();
-                    if let Some(moved_entry) = self.orders.get_mut(&moved_id) {
-                        moved_entry.location = location;
-                    }
-                }
+        if modify.get_quantity() == Q::zero() {
+            return ModifyOutcome::Rejected(ModifyReject::InvalidQuantity);
+        }
+        if new_order_type == Some(OrderType::Market) {
+            return ModifyOutcome::Rejected(ModifyReject::InvalidOrderType);
+        }
 
-                if queue.is_empty() {
-                    match side {
-                        Side::Buy => { self.bids.remove(&price); }
-                        Side::Sell => { self.asks.remove(&price); }
-                    }
-                }
+        let (current_type, side, price, initial_quantity, filled_quantity, hidden) = {
+            let ord = order.lock().unwrap();
+            (ord.get_order_type(), ord.get_side(), ord.resting_price(), ord.get_initial_quantity(), ord.get_filled_quantity(), ord.is_hidden())
+        };
+        let order_type = new_order_type.unwrap_or(current_type);
+
+        if modify.get_quantity() <= filled_quantity {
+            return ModifyOutcome::Cancelled(self.cancel_order(order_id));
+        }
+
+        let keeps_priority = order_type == current_type
+            && modify.get_side() == side
+            && modify.get_price() == price
+            && modify.get_quantity() <= initial_quantity;
+
+        if keeps_priority {
+            order.lock().unwrap().amend_quantity(modify.get_quantity()).ok();
+            // same count, less quantity at the level
+            self.update_level_data(side, price, initial_quantity - modify.get_quantity(), hidden, LevelDataAction::Match);
+            return ModifyOutcome::AmendedInPlace(self.match_orders());
+        }
+
+        // replacing would take the leg out of its quote, and out of quote protection with it
+        if self.quote_legs.contains_key(&order_id) {
+            return ModifyOutcome::Rejected(ModifyReject::QuoteLeg);
+        }
+        self.drop_order(order_id);
+        order.lock().unwrap()
+            .replace(order_type, modify.get_side(), modify.get_price(), modify.get_quantity())
+            .ok();
+        ModifyOutcome::Replaced(self.add_order(order))
+    }
+
+    pub fn submit_quote(&mut self, quote: Quote<P, Q>) -> QuoteOutcome<P, Q> {
+        let owner = quote.get_owner();
+        if self.is_pulled(owner) {
+            return QuoteOutcome::Rejected(QuoteReject::ProtectionTripped);
+        }
+
+        let (bid, ask) = (quote.get_bid(), quote.get_ask());
+        if bid.quantity > Q::zero() && ask.quantity > Q::zero() {
+            if bid.price >= ask.price {
+                return QuoteOutcome::Rejected(QuoteReject::CrossedQuote);
+            }
+            if bid.order_id == ask.order_id {
+                return QuoteOutcome::Rejected(QuoteReject::DuplicateOrderId);
             }
+        }
+        // leg ids may reuse the owner's current legs, but nobody else's orders or stops
+        let taken = quote.legs().any(|(_, leg)| {
+            self.contains(leg.order_id) && self.quote_legs.get(&leg.order_id) != Some(&owner)
+        });
+        if taken {
+            return QuoteOutcome::Rejected(QuoteReject::DuplicateOrderId);
+        }
+
+        self.drop_quote(owner);
+
+        let mut trades = vec![];
+        for (side, leg) in quote.legs() {
+            // the ids are free and legs are plain limits, so each one rests; it's
+            // registered before matching so fills on entry count towards the protection
+            self.rest_order(Order::new(OrderType::GoodTillCancel, leg.order_id, side, leg.price, leg.quantity));
+            self.quote_legs.insert(leg.order_id, owner);
+            self.quotes.entry(owner).or_default().legs.push(leg.order_id);
+            trades.extend(self.match_orders());
 
-            self.on_order_cancelled(order.clone());
+            if self.is_pulled(owner) {
+                return QuoteOutcome::Pulled(trades);
+            }
         }
+        QuoteOutcome::Accepted(trades)
     }
 
+    pub fn cancel_quote(&mut self, owner: OwnerId) -> Trades<P, Q> {
+        self.drop_quote(owner);
+        self.match_orders()
+    }
 
-    pub fn modify_order(&mut self, order: OrderModify) -> Trades {
-        let order_type = self.orders.get(&order.get_order_id())
-            .map(|entry| entry.order.lock().unwrap().get_order_type());
+    fn drop_quote(&mut self, owner: OwnerId) {
+        let legs = match self.quotes.get_mut(&owner) {
+            Some(entry) => std::mem::take(&mut entry.legs),
+            None => return,
+        };
+        for order_id in legs {
+            self.drop_order(order_id);
+        }
+    }
 
-        if order_type.is_none() {
-            return vec![];
+    // drops the legs of owners another book in the group has pulled
+    fn drop_pulled_quotes(&mut self) {
+        if self.quote_legs.is_empty() {
+            return;
+        }
+        let pulled: Vec<OwnerId> = self.quotes.iter()
+            .filter(|(owner, entry)| !entry.legs.is_empty() && self.is_pulled(**owner))
+            .map(|(owner, _)| *owner)
+            .collect();
+        for owner in pulled {
+            self.drop_quote(owner);
+        }
+    }
+
+    fn is_pulled(&self, owner: OwnerId) -> bool {
+        self.protection.lock().unwrap().owners.get(&owner).is_some_and(|entry| entry.pulled)
+    }
+
+    pub fn set_quote_protection(&mut self, owner: OwnerId, protection: QuoteProtection) {
+        self.protection.lock().unwrap().owners.entry(owner).or_default().protection = Some(protection);
+    }
+
+    // lets an owner quote again after their protection pulled them
+    pub fn reset_quote_protection(&mut self, owner: OwnerId) {
+        if let Some(entry) = self.protection.lock().unwrap().owners.get_mut(&owner) {
+            entry.pulled = false;
+            entry.fills.clear();
+        }
+    }
+
+    // Records a fill against a quote leg; returns the owner if that fill trips their protection.
+    fn on_quote_filled(&mut self, order_id: OrderId) -> Option<OwnerId> {
+        let owner = *self.quote_legs.get(&order_id)?;
+        let mut group = self.protection.lock().unwrap();
+        let entry = group.owners.get_mut(&owner)?;
+        let protection = entry.protection?;
+
+        let now = Instant::now();
+        entry.fills.push_back(now);
+        while entry.fills.front().is_some_and(|fill| now.duration_since(*fill) > protection.window) {
+            entry.fills.pop_front();
         }
 
-        self.cancel_order(order.get_order_id());
-        self.add_order(order.to_order_pointer(order_type.unwrap()))
+        if entry.pulled || (entry.fills.len() as u32) < protection.max_fills {
+            return None;
+        }
+        entry.pulled = true;
+        Some(owner)
     }
-    fn update_level_data(&mut self, price: Price, quantity: Quantity, action: LevelDataAction) {
-        let data = self.data.entry(price).or_insert(LevelData { quantity: 0, count: 0 });
+
+    fn update_level_data(&mut self, side: Side, price: P, quantity: Q, hidden: bool, action: LevelDataAction) {
+        let data = self.data.entry((side, price)).or_default();
+        let level_quantity = if hidden {
+            &mut data.hidden_quantity
+        } else {
+            &mut data.displayed_quantity
+        };
 
         match action {
             LevelDataAction::Remove => {
                 data.count -= 1;
-                data.quantity -= quantity;
+                *level_quantity -= quantity;
             },
             LevelDataAction::Add => {
                 data.count += 1;
-                data.quantity += quantity;
+                *level_quantity += quantity;
             },
             LevelDataAction::Match => {
-                data.quantity -= quantity;
+                *level_quantity -= quantity;
             },
         }
 
         if data.count == 0 {
-            self.data.remove(&price);
+            self.data.remove(&(side, price));
         }
     }
-    fn on_order_cancelled(&mut self, order: OrderPointer){
+    fn on_order_cancelled(&mut self, order: OrderPointer<P, Q>){
         let ord = order.lock().unwrap();
-        self.update_level_data(ord.get_price(), ord.get_initial_quantity(), LevelDataAction::Remove)
+        self.update_level_data(ord.get_side(), ord.resting_price(), ord.get_remaining_quantity(), ord.is_hidden(), LevelDataAction::Remove)
     }
-    fn on_order_added(&mut self, order: OrderPointer) {
+    fn on_order_added(&mut self, order: OrderPointer<P, Q>) {
         let ord = order.lock().unwrap();
-        self.update_level_data(ord.get_price(), ord.get_initial_quantity(), LevelDataAction::Add)
+        self.update_level_data(ord.get_side(), ord.resting_price(), ord.get_remaining_quantity(), ord.is_hidden(), LevelDataAction::Add)
     }
-    fn on_order_matched(&mut self, price: Price, quantity: Quantity, is_fully_filled: bool) {
+    fn on_order_matched(&mut self, side: Side, price: P, quantity: Q, hidden: bool, is_fully_filled: bool) {
         let action = if is_fully_filled {
             LevelDataAction::Remove
         } else {
             LevelDataAction::Match
         };
-        self.update_level_data(price, quantity, action);
+        self.update_level_data(side, price, quantity, hidden, action);
     }
 
-    fn can_match(&mut self, side: Side, price: Price) -> bool {
+    fn can_match(&mut self, side: Side, price: P) -> bool {
         match side {
-            Side::Buy => self.asks.first_key_value().map_or(false, |(ask, _)| price >= *ask),
-            Side::Sell => self.bids.first_key_value().map_or(false, |(bid, _)| price <= *bid),
+            Side::Buy => self.asks.first_key_value().is_some_and(|(ask, _)| price >= *ask),
+            Side::Sell => self.bids.last_key_value().is_some_and(|(bid, _)| price <= *bid),
         }
     }
 
-    fn can_fully_fill(&mut self, side: Side, price: Price, mut quantity: Quantity) -> bool {
+    fn can_fully_fill(&mut self, side: Side, price: P, quantity: Q) -> bool {
 
         if !self.can_match(side, price){
             return false
         }
 
-        let threshold: Option<Price> = None;
+        let contra_side = match side {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        };
+        quantity <= self.executable_quantity(contra_side, price)
+    }
 
-        // Since bids or asks are guaranteed to be non-empty, unwrap directly.
-        let threshold = Some(
-            if side == Side::Buy {
-            *self.asks.iter().next().unwrap().0
-            } else {
-            *self.bids.iter().next_back().unwrap().0
-            }
-        );
+    // Quantity resting on `side` that an order at `price` from the other side could
+    // trade against, hidden included. All-or-none orders are left out since they
+    // can't be counted on to take part of a fill.
+    fn executable_quantity(&self, side: Side, price: P) -> Q {
+        let level_quantity = |level_price: &P| {
+            self.data.get(&(side, *level_price)).map_or(Q::zero(), |data| data.total_quantity())
+        };
+        let total: Q = match side {
+            Side::Buy => self.bids.range(price..).map(|(level_price, _)| level_quantity(level_price)).sum(),
+            Side::Sell => self.asks.range(..=price).map(|(level_price, _)| level_quantity(level_price)).sum(),
+        };
 
-        for (level_price, level_data) in self.data.iter() {
-            if let Some(threshold_price) = threshold {
-                let outside_bounds = match side {
-                    Side::Buy => threshold_price > *level_price,
-                    Side::Sell => threshold_price < *level_price,
-                };
-                if outside_bounds {
-                    continue;
+        let all_or_none: Q = self.all_or_none.iter()
+            .filter_map(|order_id| self.orders.get(order_id))
+            .filter(|entry| entry.side == side && match side {
+                Side::Buy => entry.price >= price,
+                Side::Sell => entry.price <= price,
+            })
+            .map(|entry| entry.order.lock().unwrap().get_remaining_quantity())
+            .sum();
+        total - all_or_none
+    }
+
+    // Whether a crossing bid and ask may trade: an all-or-none order only trades
+    // if there is enough executable contra quantity to fill all of it.
+    fn can_execute(&self, bid: &OrderPointer<P, Q>, ask: &OrderPointer<P, Q>) -> bool {
+        let (bid_type, bid_price, bid_quantity) = {
+            let bid = bid.lock().unwrap();
+            (bid.get_order_type(), bid.resting_price(), bid.get_remaining_quantity())
+        };
+        let (ask_type, ask_price, ask_quantity) = {
+            let ask = ask.lock().unwrap();
+            (ask.get_order_type(), ask.resting_price(), ask.get_remaining_quantity())
+        };
+
+        match (bid_type == OrderType::AllOrNone, ask_type == OrderType::AllOrNone) {
+            (true, true) => bid_quantity == ask_quantity,
+            (true, false) => bid_quantity <= self.executable_quantity(Side::Sell, bid_price),
+            (false, true) => ask_quantity <= self.executable_quantity(Side::Buy, ask_price),
+            (false, false) => true,
+        }
+    }
+
+    // Next bid and ask to trade in price-time priority, stepping over all-or-none
+    // orders that can't be filled so they don't block the orders behind them.
+    fn next_match(&self) -> Option<(OrderPointer<P, Q>, OrderPointer<P, Q>)> {
+        let (best_bid, bids) = self.bids.last_key_value()?;
+        let (best_ask, asks) = self.asks.first_key_value()?;
+        if best_bid < best_ask {
+            return None;
+        }
+        if self.all_or_none.is_empty() {
+            return Some((bids.first()?.clone(), asks.first()?.clone()));
+        }
+
+        for (bid_price, bids) in self.bids.range(best_ask..).rev() {
+            for bid in bids {
+                for (_, asks) in self.asks.range(..=bid_price) {
+                    if let Some(ask) = asks.iter().find(|ask| self.can_execute(bid, ask)) {
+                        return Some((bid.clone(), ask.clone()));
+                    }
                 }
             }
+        }
+        None
+    }
 
-            if (side == Side::Buy && *level_price > price) || (side == Side::Sell && *level_price < price){
-                continue;
+    fn remove_order_from_book(&mut self, order_id: OrderId) -> Option<OrderPointer<P, Q>> {
+        let entry = self.orders.remove(&order_id)?;
+        self.pegged.remove(&order_id);
+        self.all_or_none.remove(&order_id);
+        if let Some(owner) = self.quote_legs.remove(&order_id) {
+            if let Some(quote) = self.quotes.get_mut(&owner) {
+                quote.legs.retain(|leg| *leg != order_id);
             }
+        }
 
-            if quantity <= level_data.quantity{
-                return true
-            }
+        let book = match entry.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
 
-            quantity -= level_data.quantity
+        if let Some(queue) = book.get_mut(&entry.price) {
+            // Vec::remove rather than swap_remove so the orders behind keep their time priority
+            queue.remove(entry.location);
+            for order in &queue[entry.location..] {
+                let moved_id = order.lock().unwrap().get_order_id();
+                if let Some(moved_entry) = self.orders.get_mut(&moved_id) {
+                    moved_entry.location -= 1;
+                }
+            }
+            if queue.is_empty() {
+                book.remove(&entry.price);
+            }
+        }
+        Some(entry.order)
+    }
 
+    // best displayed price on a side ignoring levels made up only of pegged orders,
+    // so pegs never chase each other or give away hidden liquidity
+    fn best_unpegged_price(&self, side: Side) -> Option<P> {
+        let has_unpegged = |orders: &OrderPointers<P, Q>| {
+            orders.iter().any(|order| {
+                let ord = order.lock().unwrap();
+                ord.get_peg().is_none() && !ord.is_hidden()
+            })
+        };
+        match side {
+            Side::Buy => self.bids.iter().rev().find(|(_, orders)| has_unpegged(orders)).map(|(price, _)| *price),
+            Side::Sell => self.asks.iter().find(|(_, orders)| has_unpegged(orders)).map(|(price, _)| *price),
         }
-        return false
     }
 
-    fn remove_order_from_book(&mut self, order_id: OrderId, price: Price, side: Side) {
-        // Remove from orders map and get the entry (contains location)
-        if let Some(entry) = self.orders.remove(&order_id) {
-            let book = match side {
-                Side::Buy => &mut self.bids,
-                Side::Sell => &mut self.asks,
+    fn best_displayed_price(&self, side: Side) -> Option<P> {
+        self.displayed_levels(side).next().map(|(price, _)| price)
+    }
+
+    // Moves every pegged order whose peg price changed to the back of its new level.
+    // Returns true if anything moved.
+    fn reprice_pegged_orders(&mut self) -> bool {
+        if self.pegged.is_empty() {
+            return false;
+        }
+        let best_bid = self.best_unpegged_price(Side::Buy);
+        let best_ask = self.best_unpegged_price(Side::Sell);
+
+        let mut repriced = false;
+        for order_id in self.pegged.clone() {
+            let order = match self.orders.get(&order_id) {
+                Some(entry) => entry.order.clone(),
+                None => continue,
+            };
+            let (side, peg, price) = {
+                let ord = order.lock().unwrap();
+                (ord.get_side(), ord.get_peg(), ord.resting_price())
+            };
+            // with no reference price the order stays where it is
+            let new_price = match peg.and_then(|peg| peg.price(side, best_bid, best_ask)) {
+                Some(new_price) if new_price != price => new_price,
+                _ => continue,
             };
 
-            if let Some(queue) = book.get_mut(&price) {
-                let idx = entry.location;
-                let last_idx = queue.len() - 1;
-                queue.swap_remove(idx);
-                // If we swapped with another order, update its location in orders map
-                if idx < queue.len() {
-                    let swapped_order_id = queue[idx].lock().unwrap().get_order_id();
-                    if let Some(swapped_entry) = self.orders.get_mut(&swapped_order_id) {
-                        swapped_entry.location = idx;
-                    }
-                }
-                if queue.is_empty() {
-                    book.remove(&price);
-                }
-            }
+            self.drop_order(order_id);
+            order.lock().unwrap().price = Some(new_price);
+            self.rest_order(order);
+            repriced = true;
         }
+        repriced
     }
 
-    fn match_orders(&mut self) -> Trades {
-        let mut trades = Vec::with_capacity(self.orders.len());
-
-        loop {
-            if self.bids.is_empty() || self.asks.is_empty() {
-                break;
-            }
+    // Ratchets every trailing stop off its reference price and adds the ones
+    // the market has come back through to the book.
+    fn update_trailing_stops(&mut self) -> Trades<P, Q> {
+        if self.stops.is_empty() {
+            return vec![];
+        }
+        let best_bid = self.best_displayed_price(Side::Buy);
+        let best_ask = self.best_displayed_price(Side::Sell);
+
+        let mut fired = vec![];
+        for (order_id, stop) in self.stops.iter_mut() {
+            let (side, trail) = {
+                let ord = stop.order.lock().unwrap();
+                (ord.get_side(), ord.get_trail())
+            };
+            let Some(trail) = trail else { continue };
 
-            let (bid_price, bids) = match self.bids.iter_mut().next_back() {
-                Some((p, b)) => (*p, b),
-                None => break,
+            let reference = match (trail.reference, side) {
+                (TrailReference::LastTrade, _) => self.last_trade_price,
+                (TrailReference::BestPrice, Side::Sell) => best_bid,
+                (TrailReference::BestPrice, Side::Buy) => best_ask,
             };
-            let (ask_price, asks) = match self.asks.iter_mut().next() {
-                Some((p, a)) => (*p, a),
-                None => break,
+            let Some(reference) = reference else { continue };
+
+            let trigger = trail.ratchet(side, reference, stop.trigger);
+            stop.trigger = Some(trigger);
+            let triggered = match side {
+                Side::Sell => reference <= trigger,
+                Side::Buy => reference >= trigger,
             };
+            if triggered {
+                fired.push(*order_id);
+            }
+        }
 
-            if bid_price < ask_price {
-                break;
+        let mut trades = vec![];
+        for order_id in fired {
+            let Some(StopEntry { order, trigger }) = self.stops.remove(&order_id) else { continue };
+            {
+                let mut ord = order.lock().unwrap();
+                if let (Some(trail), Some(trigger)) = (ord.trail.take(), trigger) {
+                    if ord.get_order_type() != OrderType::Market {
+                        ord.price = Some(trail.limit_price(ord.get_side(), trigger));
+                    }
+                }
             }
+            trades.extend(self.add_order(order));
+        }
+        trades
+    }
 
-            let bid_order_ptr = bids.get(0).cloned();
-            let ask_order_ptr = asks.get(0).cloned();
+    fn match_orders(&mut self) -> Trades<P, Q> {
+        self.drop_pulled_quotes();
+        self.reprice_pegged_orders();
+        let mut trades = self.cross_orders();
 
-            let (bid_order_ptr, ask_order_ptr) = match (bid_order_ptr, ask_order_ptr) {
-                (Some(b), Some(a)) => (b, a),
-                _ => break,
-            };
+        // trading (or pulled quotes) can move the top of book, so let the pegs follow
+        if self.reprice_pegged_orders() {
+            trades.extend(self.match_orders());
+        }
 
-            let (bid_filled, ask_filled, bid_id, ask_id, trade_quantity, final_bid_price, final_ask_price, bid_type, ask_type);
+        trades.extend(self.update_trailing_stops());
+        trades
+    }
+
+    // trades everything that crosses, without repricing pegs or touching stops
+    fn cross_orders(&mut self) -> Trades<P, Q> {
+        let mut trades = Vec::with_capacity(self.orders.len());
+        while let Some((bid_order_ptr, ask_order_ptr)) = self.next_match() {
+
+            let (bid_filled, ask_filled, bid_id, ask_id, trade_quantity, final_bid_price, final_ask_price, bid_hidden, ask_hidden);
             {
                 let mut bid = bid_order_ptr.lock().unwrap();
                 let mut ask = ask_order_ptr.lock().unwrap();
@@ -598,7 +1666,7 @@ impl InnerOrderbook {
                 trade_quantity = bid.get_remaining_quantity().min(ask.get_remaining_quantity());
 
                 // If nothing to match, break or handle F&K
-                if trade_quantity == 0 {
+                if trade_quantity == Q::zero() {
                     break;
                 }
 
@@ -611,40 +1679,51 @@ impl InnerOrderbook {
                 bid_id = bid.get_order_id();
                 ask_id = ask.get_order_id();
 
-                final_bid_price = bid.get_price();
-                final_ask_price = ask.get_price();
+                final_bid_price = bid.resting_price();
+                final_ask_price = ask.resting_price();
 
-                bid_type = bid.get_order_type();
-                ask_type = ask.get_order_type();
+                bid_hidden = bid.is_hidden();
+                ask_hidden = ask.is_hidden();
             }
 
+            // trades print at the resting order's price
+            let bid_sequence = self.orders.get(&bid_id).map(|entry| entry.sequence);
+            let ask_sequence = self.orders.get(&ask_id).map(|entry| entry.sequence);
+            let (maker_side, trade_price) = if bid_sequence < ask_sequence { (Side::Buy, final_bid_price) } else { (Side::Sell, final_ask_price) };
+            self.last_trade_price = Some(trade_price);
+            debug!(bid_id, ask_id, price = ?trade_price, quantity = ?trade_quantity, ?maker_side, "trade");
+
             trades.push(Trade::new(
                 TradeInfo { order_id: bid_id, price: final_bid_price, quantity: trade_quantity },
                 TradeInfo { order_id: ask_id, price: final_ask_price, quantity: trade_quantity },
+                trade_price,
+                maker_side,
             ));
 
-            self.on_order_matched(final_bid_price, trade_quantity, bid_filled);
-            self.on_order_matched(final_ask_price, trade_quantity, ask_filled);
+            self.on_order_matched(Side::Buy, final_bid_price, trade_quantity, bid_hidden, bid_filled);
+            self.on_order_matched(Side::Sell, final_ask_price, trade_quantity, ask_hidden, ask_filled);
+
+            let mut tripped = vec![];
+            if !self.quote_legs.is_empty() {
+                tripped.extend(self.on_quote_filled(bid_id));
+                tripped.extend(self.on_quote_filled(ask_id));
+            }
 
             // Fully filled orders
             if bid_filled {
-                self.remove_order_from_book(bid_id, final_bid_price, Side::Buy);
+                self.remove_order_from_book(bid_id);
             }
 
             if ask_filled {
-                self.remove_order_from_book(ask_id, final_ask_price, Side::Sell);
+                self.remove_order_from_book(ask_id);
             }
 
-            // Remove partially filled F&K orders (should not persist)
-            if !bid_filled && bid_type == OrderType::FillAndKill {
-                self.remove_order_from_book(bid_id, final_bid_price, Side::Buy);
-            }
-
-            if !ask_filled && ask_type == OrderType::FillAndKill {
-                self.remove_order_from_book(ask_id, final_ask_price, Side::Sell);
+            // a tripped owner's quotes can't take another fill
+            for owner in tripped {
+                self.drop_quote(owner);
+                self.tripped_owners.push(owner);
             }
         }
-
         trades
     }
 
@@ -653,42 +1732,22 @@ impl InnerOrderbook {
 
     fn prune_gfd_orders(&mut self, test_mode: bool) {
         let end_hour = 16;
-        println!("end_hour: {}", end_hour);
 
         loop {
-            println!("Started Loop!");
             let now = SystemTime::now();
-            let now_duration = now.duration_since(UNIX_EPOCH).unwrap();
-            println!("now_duration: {:?}", now_duration);
-            let now_secs = now_duration.as_secs() as i64;
-            println!("now_secs: {}", now_secs);
-            
+            let now_secs = now.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
             let now_parts = DateTime::from_timestamp(now_secs, 0).unwrap();
-            println!("now_parts: {:?}", now_parts);
             let mut date = now_parts.date_naive();
-            println!("date: {}", date);
-            let hour = now_parts.hour();
-            println!("hour: {}", hour);
-
-            println!("Comparing hours!");
-            println!("Current hour is {}, end hour is {}", hour, end_hour);
-            if hour >= end_hour {
+            if now_parts.hour() >= end_hour {
                 date = date.succ_opt().unwrap(); // move to next day
-                println!("Moved to next day, new date: {}", date);
             }
 
             let next_cutoff = date.and_hms_opt(end_hour, 0, 0).unwrap();
-            println!("next_cutoff: {}", next_cutoff);
             let cutoff_ts = UNIX_EPOCH + Duration::from_secs(next_cutoff.and_utc().timestamp() as u64);
-            println!("cutoff_ts: {:?}", cutoff_ts);
-            let now_system_time = SystemTime::now();
-            println!("now_system_time: {:?}", now_system_time);
-            
-            println!("Finding wait duration");
             let wait_duration = cutoff_ts
-                .duration_since(now_system_time)
+                .duration_since(SystemTime::now())
                 .unwrap_or(Duration::from_secs(0)) + Duration::from_millis(100);
-            println!("wait_duration: {:?}", wait_duration);
+            debug!(%next_cutoff, ?wait_duration, "waiting for good for day cutoff");
 
             // Use a dummy mutex for waiting on the condition variable.
             let dummy_mutex = Mutex::new(());
@@ -696,56 +1755,37 @@ impl InnerOrderbook {
             let (guard, result) = self.shutdown_condition_variable
                 .wait_timeout(guard, wait_duration)
                 .unwrap();
-            
-            println!("result.timed_out(): {}", result.timed_out());
-            println!("self.shutdown: {}", self.shutdown.load(Ordering::Acquire));
-            
-            println!("DEBUG: About to check shutdown condition");
+
             if self.shutdown.load(Ordering::Acquire) {
-                println!("Shutdown requested, exiting prune_gfd_orders.");
+                debug!("shutdown requested, good for day pruning stopped");
                 return;
             }
 
-            println!("DEBUG: About to check timeout condition");
             if !result.timed_out() {
-                println!("Woke up early (not timed out), skipping pruning.");
+                trace!("woke before the cutoff, skipping pruning");
                 continue;
             }
 
-            println!("DEBUG: About to start pruning logic");
-            
-            // Pruning logic
-            println!("Pruning Orders!");
-            let mut order_ids = vec![];
-
-            println!("DEBUG: About to iterate over orders");
-            for (order_id, entry) in &self.orders {
-                println!("DEBUG: Checking order {}", order_id);
-                let order = entry.order.lock().unwrap();
-                println!("DEBUG: Order type: {:?}", order.get_order_type());
-                if order.get_order_type() == OrderType::GoodForDay {
-                    println!("DEBUG: Adding GFD order {} to cancellation list", order_id);
-                    order_ids.push(*order_id);
-                }
-            }
-            
-            println!("Found {} GFD orders to cancel", order_ids.len());
+            let order_ids: Vec<OrderId> = self.orders.iter()
+                .filter(|(_, entry)| entry.order.lock().unwrap().get_order_type() == OrderType::GoodForDay)
+                .map(|(order_id, _)| *order_id)
+                .collect();
+            info!(count = order_ids.len(), "pruning good for day orders");
 
-            for id in order_ids {
-                println!("Canceling order with id: {}", id);
-                self.cancel_order(id);
+            for order_id in order_ids {
+                debug!(order_id, "good for day order expired");
+                self.drop_order(order_id);
             }
-            
-            println!("Orders left: {}", self.orders.len());
+            self.match_orders();
+            debug!(resting = self.orders.len(), "good for day pruning done");
 
             if test_mode{
-                println!("Finished pruning! test mode on");
                 break;
             }
         }
     }
 }
-impl Drop for InnerOrderbook {
+impl<P, Q> Drop for InnerOrderbook<P, Q> {
     fn drop(&mut self) {
         self.shutdown.store(true, Ordering::Release);
         self.shutdown_condition_variable.notify_one();
@@ -757,12 +1797,13 @@ impl Drop for InnerOrderbook {
         
 
 
-/// Tests:
+// Tests:
 
 //Each test implicitly assumes a working match_orders() functionality
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::price::{FixedPrice, FixedQuantity};
 
     #[test]
     fn test_orderbook_new(){
@@ -807,8 +1848,84 @@ mod test {
         //should match and fill order with id 1
         orderbook.modify_order(order_mod);
         assert_eq!(orderbook.size(), 0);
-        
 
+
+    }
+
+    #[test]
+    fn test_modify_reduce_keeps_priority(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let first = Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10);
+        orderbook.add_order(first.clone());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 100, 10));
+
+        let outcome = orderbook.modify_order(OrderModify::new(1, Side::Buy, 100, 4));
+        assert!(matches!(outcome, ModifyOutcome::AmendedInPlace(trades) if trades.is_empty()));
+        assert_eq!(first.lock().unwrap().get_remaining_quantity(), 4);
+
+        // order 1 is still at the front of the queue
+        let trades = orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 100, 4));
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].get_bid_trade().order_id, 1);
+        assert_eq!(orderbook.get_order_infos().get_bids()[0].quantity, 10);
+    }
+
+    #[test]
+    fn test_modify_increase_loses_priority(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 100, 10));
+
+        let outcome = orderbook.modify_order(OrderModify::new(1, Side::Buy, 100, 20));
+        assert!(matches!(outcome, ModifyOutcome::Replaced(ref trades) if trades.is_empty()));
+
+        let trades = orderbook.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Sell, 100, 25));
+        let filled: Vec<OrderId> = trades.iter().map(|trade| trade.get_bid_trade().order_id).collect();
+        assert_eq!(filled, vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn test_modify_keeps_fill_state(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let order = Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10);
+        orderbook.add_order(order.clone());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 100, 6));
+
+        // moving the price re-queues it, but the 6 already filled still count
+        let outcome = orderbook.modify_order(OrderModify::new(1, Side::Buy, 99, 10));
+        assert!(matches!(outcome, ModifyOutcome::Replaced(_)));
+        {
+            let ord = order.lock().unwrap();
+            assert_eq!(ord.get_filled_quantity(), 6);
+            assert_eq!(ord.get_remaining_quantity(), 4);
+        }
+
+        // amending to no more than the filled quantity leaves nothing to rest
+        let outcome = orderbook.modify_order(OrderModify::new(1, Side::Buy, 99, 6));
+        assert!(matches!(outcome, ModifyOutcome::Cancelled(_)));
+        assert_eq!(orderbook.size(), 0);
+
+        let outcome = orderbook.modify_order(OrderModify::new(1, Side::Buy, 99, 6));
+        assert!(matches!(outcome, ModifyOutcome::Rejected(ModifyReject::UnknownOrder)));
+    }
+
+    #[test]
+    fn test_replace_changes_order_type(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let order = Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10);
+        orderbook.add_order(order.clone());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 101, 5));
+
+        let outcome = orderbook.replace_order(OrderModify::new(1, Side::Buy, 101, 10), OrderType::FillAndKill);
+        assert!(matches!(outcome, ModifyOutcome::Replaced(ref trades) if trades.len() == 1));
+        assert_eq!(order.lock().unwrap().get_order_type(), OrderType::FillAndKill);
+        // the unfilled remainder of the F&K does not rest
+        assert_eq!(orderbook.size(), 0);
+
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 100, 10));
+        let outcome = orderbook.replace_order(OrderModify::new(3, Side::Buy, 100, 10), OrderType::Market);
+        assert!(matches!(outcome, ModifyOutcome::Rejected(ModifyReject::InvalidOrderType)));
     }
 
     #[test]
@@ -841,9 +1958,29 @@ mod test {
         // Now add enough sell quantity to fill the FOK order
         orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 100, 10));
 
-        // Add a FOK buy order that can be fully filled (should match and remove both)
-        orderbook.add_order(Order::new(OrderType::FillOrKill, 4, Side::Buy, 100, 10));
-        println!("{:#?}", orderbook);
+        // Add a FOK buy order that can be fully filled across both sells (should fill order 1 and half of order 3)
+        let trades = orderbook.add_order(Order::new(OrderType::FillOrKill, 4, Side::Buy, 100, 10));
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].get_ask_trade().order_id, 1);
+        assert_eq!(trades[0].get_quantity(), 5);
+        assert_eq!(trades[1].get_ask_trade().order_id, 3);
+        assert_eq!(trades[1].get_quantity(), 5);
+        assert_eq!(orderbook.size(), 1);
+        assert_eq!(orderbook.get_order_infos().get_asks()[0].quantity, 5);
+    }
+
+    #[test]
+    fn test_fill_and_kill_sweeps_levels(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 95, 5));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 97, 5));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 98, 5));
+
+        // checked against the best bid, not the lowest, and keeps going after the first fill
+        let trades = orderbook.add_order(Order::new(OrderType::FillAndKill, 4, Side::Sell, 96, 12));
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades.iter().map(|trade| trade.get_quantity()).sum::<Quantity>(), 10);
+        assert!(trades.iter().all(|trade| trade.get_maker_side() == Side::Buy));
         assert_eq!(orderbook.size(), 1);
     }
 
@@ -886,6 +2023,478 @@ mod test {
 
     }
 
+    #[test]
+    fn test_quote_replaces_both_sides(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let quote = Quote::new(
+            7,
+            QuoteLeg { order_id: 1, price: Price::from(99), quantity: 10 },
+            QuoteLeg { order_id: 2, price: Price::from(101), quantity: 10 },
+        );
+        assert!(matches!(orderbook.submit_quote(quote), QuoteOutcome::Accepted(_)));
+        assert_eq!(orderbook.size(), 2);
+
+        // the ask is dropped by quoting zero on that side; the bid moves up
+        let quote = Quote::new(
+            7,
+            QuoteLeg { order_id: 3, price: Price::from(100), quantity: 5 },
+            QuoteLeg { order_id: 4, price: Price::from(102), quantity: 0 },
+        );
+        assert!(matches!(orderbook.submit_quote(quote), QuoteOutcome::Accepted(_)));
+        let infos = orderbook.get_order_infos();
+        assert_eq!(infos.get_bids().len(), 1);
+        assert_eq!(infos.get_bids()[0].price, 100);
+        assert_eq!(infos.get_bids()[0].quantity, 5);
+        assert!(infos.get_asks().is_empty());
+
+        let crossed = Quote::new(
+            7,
+            QuoteLeg { order_id: 5, price: Price::from(101), quantity: 5 },
+            QuoteLeg { order_id: 6, price: Price::from(100), quantity: 5 },
+        );
+        assert!(matches!(orderbook.submit_quote(crossed), QuoteOutcome::Rejected(QuoteReject::CrossedQuote)));
+
+        orderbook.cancel_quote(7);
+        assert_eq!(orderbook.size(), 0);
+    }
+
+    #[test]
+    fn test_quote_leg_cannot_take_a_stop_id(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        let trail = TrailingStop::new(TrailAmount::Offset(Price::from(5)), TrailReference::BestPrice, 0);
+        orderbook.add_order(Order::new_trailing_stop(OrderType::Market, 2, Side::Sell, trail, 10));
+
+        let quote = Quote::new(
+            7,
+            QuoteLeg { order_id: 2, price: Price::from(99), quantity: 5 },
+            QuoteLeg { order_id: 3, price: Price::from(101), quantity: 5 },
+        );
+        assert!(matches!(orderbook.submit_quote(quote), QuoteOutcome::Rejected(QuoteReject::DuplicateOrderId)));
+
+        // cancelling the owner's quotes leaves the other owner's stop alone
+        orderbook.cancel_quote(7);
+        assert_eq!(orderbook.get_stop_trigger(2), Some(Price::from(95)));
+    }
+
+    #[test]
+    fn test_quote_protection_pulls_quotes(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.set_quote_protection(7, QuoteProtection { max_fills: 2, window: Duration::from_secs(60) });
+        orderbook.submit_quote(Quote::new(
+            7,
+            QuoteLeg { order_id: 1, price: Price::from(99), quantity: 10 },
+            QuoteLeg { order_id: 2, price: Price::from(101), quantity: 10 },
+        ));
+
+        orderbook.add_order(Order::new(OrderType::FillAndKill, 10, Side::Sell, 99, 1));
+        assert_eq!(orderbook.size(), 2);
+
+        // second fill trips the protection; the ask is pulled along with the bid
+        orderbook.add_order(Order::new(OrderType::FillAndKill, 11, Side::Buy, 101, 1));
+        assert_eq!(orderbook.size(), 0);
+
+        let quote = Quote::new(
+            7,
+            QuoteLeg { order_id: 1, price: Price::from(99), quantity: 10 },
+            QuoteLeg { order_id: 2, price: Price::from(101), quantity: 10 },
+        );
+        assert!(matches!(orderbook.submit_quote(quote), QuoteOutcome::Rejected(QuoteReject::ProtectionTripped)));
+        orderbook.reset_quote_protection(7);
+        assert!(matches!(orderbook.submit_quote(quote), QuoteOutcome::Accepted(_)));
+    }
+
+    #[test]
+    fn test_mass_quote(){
+        let mut books = HashMap::new();
+        books.insert("AAPL".to_string(), Orderbook::new(BTreeMap::new(), BTreeMap::new()));
+        books.insert("MSFT".to_string(), Orderbook::new(BTreeMap::new(), BTreeMap::new()));
+
+        let quote = |bid_id, ask_id| Quote::new(
+            7,
+            QuoteLeg { order_id: bid_id, price: Price::from(99), quantity: 10 },
+            QuoteLeg { order_id: ask_id, price: Price::from(101), quantity: 10 },
+        );
+        let outcomes = Orderbook::mass_quote(&books, vec![
+            ("AAPL".to_string(), quote(1, 2)),
+            ("MSFT".to_string(), quote(1, 2)),
+            ("TSLA".to_string(), quote(1, 2)),
+        ]);
+
+        assert!(matches!(outcomes[0].1, QuoteOutcome::Accepted(_)));
+        assert!(matches!(outcomes[1].1, QuoteOutcome::Accepted(_)));
+        assert!(matches!(outcomes[2].1, QuoteOutcome::Rejected(QuoteReject::UnknownSymbol)));
+        assert_eq!(books["AAPL"].size(), 2);
+        assert_eq!(books["MSFT"].size(), 2);
+    }
+
+    #[test]
+    fn test_quote_protection_counts_fills_across_books(){
+        let aapl = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let msft = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        aapl.share_quote_protection(&msft);
+        msft.set_quote_protection(7, QuoteProtection { max_fills: 2, window: Duration::from_secs(60) });
+
+        let quote = Quote::new(
+            7,
+            QuoteLeg { order_id: 1, price: Price::from(99), quantity: 10 },
+            QuoteLeg { order_id: 2, price: Price::from(101), quantity: 10 },
+        );
+        aapl.submit_quote(quote);
+        msft.submit_quote(quote);
+
+        aapl.add_order(Order::new(OrderType::FillAndKill, 10, Side::Sell, 99, 1));
+        assert_eq!(aapl.size(), 2);
+
+        // the second fill is on the other book, and pulls the owner from both
+        msft.add_order(Order::new(OrderType::FillAndKill, 11, Side::Buy, 101, 1));
+        assert_eq!(msft.size(), 0);
+        assert_eq!(aapl.size(), 0);
+        assert!(matches!(aapl.submit_quote(quote), QuoteOutcome::Rejected(QuoteReject::ProtectionTripped)));
+    }
+
+    #[test]
+    fn test_amended_quote_leg_stays_protected(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.set_quote_protection(7, QuoteProtection { max_fills: 2, window: Duration::from_secs(60) });
+        orderbook.submit_quote(Quote::new(
+            7,
+            QuoteLeg { order_id: 1, price: Price::from(99), quantity: 10 },
+            QuoteLeg { order_id: 2, price: Price::from(101), quantity: 10 },
+        ));
+
+        let outcome = orderbook.modify_order(OrderModify::new(1, Side::Buy, 98, 10));
+        assert!(matches!(outcome, ModifyOutcome::Rejected(ModifyReject::QuoteLeg)));
+        let outcome = orderbook.modify_order(OrderModify::new(1, Side::Buy, 99, 5));
+        assert!(matches!(outcome, ModifyOutcome::AmendedInPlace(trades) if trades.is_empty()));
+
+        // fills on the amended leg still count, and the trip pulls both legs
+        orderbook.add_order(Order::new(OrderType::FillAndKill, 10, Side::Sell, 99, 1));
+        orderbook.add_order(Order::new(OrderType::FillAndKill, 11, Side::Sell, 99, 1));
+        assert_eq!(orderbook.size(), 0);
+        assert!(orderbook.cancel_quote(7).is_empty());
+    }
+
+    #[test]
+    fn test_quote_protection_pulls_at_the_tripping_fill(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.set_quote_protection(7, QuoteProtection { max_fills: 2, window: Duration::from_secs(60) });
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Sell, 100, 1));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 100, 1));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 101, 1));
+
+        let outcome = orderbook.submit_quote(Quote::new(
+            7,
+            QuoteLeg { order_id: 4, price: Price::from(101), quantity: 10 },
+            QuoteLeg { order_id: 5, price: Price::from(0), quantity: 0 },
+        ));
+        let QuoteOutcome::Pulled(trades) = outcome else { panic!("expected the quote to be pulled, got {:?}", outcome) };
+        assert_eq!(trades.len(), 2);
+        assert!(orderbook.contains(3));
+        assert_eq!(orderbook.size(), 1);
+    }
+
+    #[test]
+    fn test_primary_peg_follows_best_bid(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 110, 10));
+
+        let peg = Order::new_pegged(OrderType::GoodTillCancel, 3, Side::Buy, Peg::new(PegType::Primary, 0, Some(Price::from(104))), 5);
+        orderbook.add_order(peg.clone());
+        assert_eq!(peg.lock().unwrap().get_price(), Some(Price::from(100)));
+
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Buy, 102, 10));
+        assert_eq!(peg.lock().unwrap().get_price(), Some(Price::from(102)));
+
+        // capped by its limit
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 5, Side::Buy, 106, 10));
+        assert_eq!(peg.lock().unwrap().get_price(), Some(Price::from(104)));
+
+        orderbook.cancel_order(5);
+        orderbook.cancel_order(4);
+        assert_eq!(peg.lock().unwrap().get_price(), Some(Price::from(100)));
+    }
+
+    #[test]
+    fn test_peg_reprices_after_match(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Sell, 101, 5));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 103, 5));
+
+        let peg = Order::new_pegged(OrderType::GoodTillCancel, 3, Side::Sell, Peg::new(PegType::Primary, 1, None), 5);
+        orderbook.add_order(peg.clone());
+        assert_eq!(peg.lock().unwrap().get_price(), Some(Price::from(102)));
+
+        // taking out the best ask moves the peg off the next one
+        orderbook.add_order(Order::new(OrderType::FillAndKill, 4, Side::Buy, 101, 5));
+        assert_eq!(peg.lock().unwrap().get_price(), Some(Price::from(104)));
+
+        // a market peg buy on the ask trades straight away
+        let trades = orderbook.add_order(Order::new_pegged(OrderType::GoodTillCancel, 5, Side::Buy, Peg::new(PegType::Market, 0, None), 5));
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].get_ask_trade().order_id, 2);
+        // with no unpegged ask left to follow, the sell peg stays where it was
+        assert_eq!(orderbook.size(), 1);
+        assert_eq!(peg.lock().unwrap().get_price(), Some(Price::from(104)));
+    }
+
+    #[test]
+    fn test_midpoint_peg(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let midpoint = Peg::new(PegType::Midpoint, 0, None);
+
+        // no midpoint without both sides
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new_pegged(OrderType::GoodTillCancel, 2, Side::Buy, midpoint, 5));
+        assert_eq!(orderbook.size(), 1);
+
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 104, 10));
+        let buy = Order::new_pegged(OrderType::GoodTillCancel, 4, Side::Buy, midpoint, 5);
+        orderbook.add_order(buy.clone());
+        assert_eq!(buy.lock().unwrap().get_price(), Some(Price::from(102)));
+
+        // an odd spread puts the peg on the half tick
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 5, Side::Sell, 103, 10));
+        assert_eq!(buy.lock().unwrap().get_price(), Some(Price::from_half_ticks(203)));
+        assert_eq!(orderbook.best_bid(), Some(Price::from_half_ticks(203)));
+
+        // and it trades there against a sell pegged to the same midpoint
+        let trades = orderbook.add_order(Order::new_pegged(OrderType::GoodTillCancel, 6, Side::Sell, midpoint, 2));
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].get_bid_trade().order_id, 4);
+        assert_eq!(trades[0].get_price().to_string(), "101.5");
+        assert_eq!(orderbook.depth_at(Side::Buy, Price::from_half_ticks(203)), 3);
+    }
+
+    #[test]
+    fn test_trailing_stop_follows_best_bid(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+
+        let trail = TrailingStop::new(TrailAmount::Offset(Price::from(5)), TrailReference::BestPrice, 0);
+        orderbook.add_order(Order::new_trailing_stop(OrderType::Market, 2, Side::Sell, trail, 10));
+        assert_eq!(orderbook.get_stop_trigger(2), Some(Price::from(95)));
+        assert_eq!(orderbook.size(), 1);
+
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 110, 10));
+        assert_eq!(orderbook.get_stop_trigger(2), Some(Price::from(105)));
+
+        // the bid falling back doesn't drag the trigger with it, so the stop fires
+        // as a market order into the 100 bid
+        let trades = orderbook.cancel_order(3);
+        assert_eq!(orderbook.get_stop_trigger(2), None);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].get_ask_trade().order_id, 2);
+        assert_eq!(orderbook.get_last_trade_price(), Some(Price::from(100)));
+        assert_eq!(orderbook.size(), 0);
+    }
+
+    #[test]
+    fn test_immediate_remainder_does_not_move_trailing_stops(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 101, 5));
+        let trail = TrailingStop::new(TrailAmount::Offset(Price::from(2)), TrailReference::BestPrice, 0);
+        orderbook.add_order(Order::new_trailing_stop(OrderType::Market, 3, Side::Sell, trail, 10));
+        assert_eq!(orderbook.get_stop_trigger(3), Some(Price::from(98)));
+
+        // the unfilled 5 never rests at 105, so the stop neither ratchets nor fires
+        let trades = orderbook.add_order(Order::new(OrderType::FillAndKill, 4, Side::Buy, 105, 10));
+        assert_eq!(trades.len(), 1);
+        assert_eq!(orderbook.get_stop_trigger(3), Some(Price::from(98)));
+        assert_eq!(orderbook.depth_at(Side::Buy, Price::from(100)), 10);
+    }
+
+    #[test]
+    fn test_immediate_remainder_does_not_move_pegs(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 101, 5));
+        let peg = Order::new_pegged(OrderType::GoodTillCancel, 3, Side::Buy, Peg::new(PegType::Primary, 0, None), 5);
+        orderbook.add_order(peg.clone());
+
+        orderbook.add_order(Order::new(OrderType::FillAndKill, 4, Side::Buy, 105, 10));
+        assert_eq!(peg.lock().unwrap().get_price(), Some(Price::from(100)));
+        assert_eq!(orderbook.size(), 2);
+    }
+
+    #[test]
+    fn test_trailing_stop_limit_on_last_trade(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Sell, 200, 1));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 200, 1));
+
+        let trail = TrailingStop::new(TrailAmount::Percent(5.0), TrailReference::LastTrade, 2);
+        let stop = Order::new_trailing_stop(OrderType::GoodTillCancel, 3, Side::Buy, trail, 5);
+        orderbook.add_order(stop.clone());
+        assert_eq!(orderbook.get_stop_trigger(3), Some(Price::from(210)));
+
+        // last trade falls to 180, the buy stop trails it down
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Buy, 180, 1));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 5, Side::Sell, 180, 1));
+        assert_eq!(orderbook.get_stop_trigger(3), Some(Price::from(189)));
+
+        // trading back up through 189 fires it as a limit 2 above the trigger
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 6, Side::Sell, 190, 1));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 7, Side::Buy, 190, 1));
+        assert_eq!(orderbook.get_stop_trigger(3), None);
+        assert_eq!(stop.lock().unwrap().get_price(), Some(Price::from(191)));
+        assert_eq!(orderbook.get_order_infos().get_bids()[0].price, 191);
+
+        orderbook.cancel_order(3);
+        assert_eq!(orderbook.size(), 0);
+    }
+
+    #[test]
+    fn test_hidden_orders_not_published(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new_hidden(OrderType::GoodTillCancel, 1, Side::Sell, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 101, 10));
+        orderbook.add_order(Order::new_hidden(OrderType::GoodTillCancel, 3, Side::Sell, 101, 7));
+
+        let infos = orderbook.get_order_infos();
+        assert_eq!(infos.get_asks().len(), 1);
+        assert_eq!(infos.get_asks()[0].price, 101);
+        assert_eq!(infos.get_asks()[0].quantity, 10);
+        assert_eq!(orderbook.size(), 3);
+
+        // FOK sees the hidden quantity even though it isn't published
+        let trades = orderbook.add_order(Order::new(OrderType::FillOrKill, 4, Side::Buy, 101, 25));
+        assert_eq!(trades.len(), 3);
+        assert_eq!(orderbook.size(), 1);
+    }
+
+    #[test]
+    fn test_hidden_orders_queue_behind_displayed(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new_hidden(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new_hidden(OrderType::GoodTillCancel, 3, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Buy, 100, 10));
+
+        let trades = orderbook.add_order(Order::new(OrderType::GoodTillCancel, 5, Side::Sell, 100, 40));
+        let filled: Vec<OrderId> = trades.iter().map(|trade| trade.get_bid_trade().order_id).collect();
+        assert_eq!(filled, vec![2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn test_queue_position(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 100, 5));
+        orderbook.add_order(Order::new_hidden(OrderType::GoodTillCancel, 3, Side::Buy, 100, 7));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Buy, 100, 8));
+
+        // 4 is displayed so it goes ahead of the hidden 3
+        let position = orderbook.queue_position(4).unwrap();
+        assert_eq!((position.side, position.price), (Side::Buy, Price::from(100)));
+        assert_eq!((position.orders_ahead, position.ahead_quantity, position.level_quantity), (2, 15, 30));
+        assert_eq!(orderbook.queue_position(3).map(|position| position.ahead_quantity), Some(23));
+
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 5, Side::Sell, 100, 12));
+        let position = orderbook.queue_position(4).unwrap();
+        assert_eq!((position.orders_ahead, position.ahead_quantity, position.level_quantity), (1, 3, 18));
+
+        orderbook.cancel_order(2);
+        assert_eq!(orderbook.queue_position(4).map(|position| position.ahead_quantity), Some(0));
+        assert_eq!(orderbook.queue_position(1), None);
+    }
+
+    #[test]
+    fn test_all_or_none_waits_for_full_fill(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Sell, 100, 5));
+
+        // crosses, but there isn't enough to fill it, so it rests untouched
+        let trades = orderbook.add_order(Order::new(OrderType::AllOrNone, 2, Side::Buy, 101, 10));
+        assert!(trades.is_empty());
+        assert_eq!(orderbook.size(), 2);
+
+        let trades = orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 101, 5));
+        assert_eq!(trades.len(), 2);
+        assert!(trades.iter().all(|trade| trade.get_bid_trade().order_id == 2));
+        assert_eq!(orderbook.size(), 0);
+    }
+
+    #[test]
+    fn test_all_or_none_does_not_block_queue(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::AllOrNone, 1, Side::Sell, 100, 20));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 100, 5));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 101, 5));
+
+        let trades = orderbook.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Buy, 101, 8));
+        let filled: Vec<OrderId> = trades.iter().map(|trade| trade.get_ask_trade().order_id).collect();
+        assert_eq!(filled, vec![2, 3]);
+        assert_eq!(orderbook.size(), 2);
+
+        // all-or-none quantity can't be relied on by immediate orders either
+        orderbook.add_order(Order::new(OrderType::FillOrKill, 5, Side::Buy, 100, 20));
+        let trades = orderbook.add_order(Order::new(OrderType::FillAndKill, 6, Side::Buy, 100, 5));
+        assert!(trades.is_empty());
+        assert_eq!(orderbook.size(), 2);
+    }
+
+    #[test]
+    fn test_fixed_point_book(){
+        type P = FixedPrice<8>;
+        type Q = FixedQuantity<8>;
+        let price = |text: &str| text.parse::<P>().unwrap();
+        let quantity = |text: &str| text.parse::<Q>().unwrap();
+
+        let orderbook: Orderbook<P, Q> = Orderbook::with_levels(BTreeMap::new(), BTreeMap::new());
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, price("67203.10000000"), quantity("0.5")));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, price("67203.10000001"), quantity("0.25")));
+
+        // a one satoshi spread has no exact midpoint
+        let midpoint = Peg::new(PegType::Midpoint, P::zero(), None);
+        let buy = Order::new_pegged(OrderType::GoodTillCancel, 3, Side::Buy, midpoint, quantity("0.1"));
+        orderbook.add_order(buy.clone());
+        assert_eq!(buy.lock().unwrap().get_price(), Some(price("67203.1")));
+
+        let trades = orderbook.add_order(Order::new_market(4, Side::Sell, quantity("0.00000001")));
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].get_bid_trade().order_id, 1);
+        assert_eq!(orderbook.get_order_infos().get_bids()[0].quantity, quantity("0.59999999"));
+    }
+
+    #[test]
+    fn test_top_of_book_analytics(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        assert_eq!(orderbook.spread(), None);
+        assert_eq!(orderbook.imbalance(5), None);
+        assert_eq!(orderbook.top_of_book(), (None, None));
+
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 99, 30));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 100, 10));
+        orderbook.add_order(Order::new_hidden(OrderType::GoodTillCancel, 3, Side::Buy, 101, 50));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Sell, 102, 30));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 5, Side::Sell, 104, 10));
+
+        // the hidden bid at 101 isn't part of the published top of book
+        assert_eq!(orderbook.best_bid(), Some(Price::from(100)));
+        assert_eq!(orderbook.best_ask(), Some(Price::from(102)));
+        assert_eq!(orderbook.top_of_book(), (Some((Price::from(100), 10)), Some((Price::from(102), 30))));
+        assert_eq!(orderbook.spread(), Some(Price::from(2)));
+        assert_eq!(orderbook.mid(), Some(101.0));
+        assert_eq!(orderbook.microprice(), Some((100.0 * 30.0 + 102.0 * 10.0) / 40.0));
+        assert_eq!(orderbook.imbalance(1), Some(-0.5));
+        assert_eq!(orderbook.imbalance(2), Some(0.0));
+
+        assert_eq!(orderbook.depth_at(Side::Buy, Price::from(99)), 40);
+        assert_eq!(orderbook.depth_at(Side::Sell, Price::from(103)), 30);
+        assert_eq!(orderbook.depth_at(Side::Sell, Price::from(101)), 0);
+
+        let sweep = orderbook.sweep_cost(Side::Buy, 40).unwrap();
+        assert_eq!(sweep.filled, 40);
+        assert_eq!(sweep.worst_price, 104);
+        assert_eq!(sweep.vwap(), (102.0 * 30.0 + 104.0 * 10.0) / 40.0);
+
+        // not enough on the book to fill
+        assert_eq!(orderbook.sweep_cost(Side::Sell, 100).unwrap().filled, 40);
+    }
+
     #[test]
     fn test_good_for_day_pruning() {
         use chrono::Local;
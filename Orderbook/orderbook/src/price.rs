@@ -0,0 +1,408 @@
+use std::{
+    fmt,
+    hash::Hash,
+    iter::Sum,
+    ops::{Add, AddAssign, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
+// What the book needs from a price. Prices are signed (spreads and some
+// instruments go negative) and offsets used by pegs and stops are prices too.
+pub trait PriceType:
+    Copy + Ord + Hash + fmt::Debug + Send + 'static + Add<Output = Self> + Sub<Output = Self>
+{
+    fn zero() -> Self;
+    // halfway between two prices at the type's resolution, rounded down or up when it falls between
+    fn midpoint_rounded(low: Self, high: Self, round_up: bool) -> Self;
+    fn to_f64(self) -> f64;
+    fn from_f64(value: f64) -> Self;
+}
+
+pub trait QuantityType:
+    Copy
+    + Ord
+    + fmt::Debug
+    + Default
+    + Send
+    + 'static
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + AddAssign
+    + SubAssign
+    + Sum
+{
+    fn zero() -> Self;
+    fn to_f64(self) -> f64;
+    fn from_f64(value: f64) -> Self;
+}
+
+impl PriceType for i64 {
+    fn zero() -> Self {
+        0
+    }
+    fn midpoint_rounded(low: Self, high: Self, round_up: bool) -> Self {
+        let sum = low + high;
+        if round_up {
+            (sum + 1).div_euclid(2)
+        } else {
+            sum.div_euclid(2)
+        }
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(value: f64) -> Self {
+        value.round() as i64
+    }
+}
+
+impl QuantityType for u64 {
+    fn zero() -> Self {
+        0
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(value: f64) -> Self {
+        value.round() as u64
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParseDecimalError {
+    Empty,
+    InvalidDigit,
+    TooManyDecimals,
+    Overflow,
+}
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            ParseDecimalError::Empty => "empty string",
+            ParseDecimalError::InvalidDigit => "invalid digit",
+            ParseDecimalError::TooManyDecimals => "more decimal places than the scale allows",
+            ParseDecimalError::Overflow => "value out of range",
+        };
+        write!(f, "cannot parse decimal: {}", reason)
+    }
+}
+
+impl std::error::Error for ParseDecimalError {}
+
+// Splits "-12.345" into (negative, units) at `scale` implied decimals.
+fn parse_scaled(text: &str, scale: u32) -> Result<(bool, u128), ParseDecimalError> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(ParseDecimalError::Empty);
+    }
+    if fraction.len() > scale as usize {
+        return Err(ParseDecimalError::TooManyDecimals);
+    }
+
+    let mut units: u128 = 0;
+    let padded = fraction.chars().chain(std::iter::repeat('0')).take(scale as usize);
+    for ch in whole.chars().chain(padded) {
+        let digit = ch.to_digit(10).ok_or(ParseDecimalError::InvalidDigit)?;
+        units = units
+            .checked_mul(10)
+            .and_then(|units| units.checked_add(digit as u128))
+            .ok_or(ParseDecimalError::Overflow)?;
+    }
+    Ok((negative, units))
+}
+
+fn format_scaled(f: &mut fmt::Formatter<'_>, negative: bool, units: u128, scale: u32) -> fmt::Result {
+    let sign = if negative { "-" } else { "" };
+    if scale == 0 {
+        return write!(f, "{}{}", sign, units);
+    }
+    let divisor = 10u128.pow(scale);
+    write!(f, "{}{}.{:0width$}", sign, units / divisor, units % divisor, width = scale as usize)
+}
+
+// The default book price: a whole number of ticks, or the half tick between two
+// that a midpoint peg rests on when the spread is an odd number of ticks.
+// Stored in half ticks; plain integers convert as whole ticks.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Ticks(i64);
+
+impl Ticks {
+    pub const fn from_half_ticks(half_ticks: i64) -> Self {
+        Self(half_ticks)
+    }
+    pub const fn half_ticks(&self) -> i64 {
+        self.0
+    }
+}
+
+impl PriceType for Ticks {
+    fn zero() -> Self {
+        Self(0)
+    }
+    // exact whenever both prices are whole ticks
+    fn midpoint_rounded(low: Self, high: Self, round_up: bool) -> Self {
+        Self(i64::midpoint_rounded(low.0, high.0, round_up))
+    }
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / 2.0
+    }
+    fn from_f64(value: f64) -> Self {
+        Self((value * 2.0).round() as i64)
+    }
+}
+
+impl From<i64> for Ticks {
+    fn from(ticks: i64) -> Self {
+        Self(ticks * 2)
+    }
+}
+
+impl PartialEq<i64> for Ticks {
+    fn eq(&self, ticks: &i64) -> bool {
+        *self == Self::from(*ticks)
+    }
+}
+
+impl Add for Ticks {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl Sub for Ticks {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl Neg for Ticks {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl FromStr for Ticks {
+    type Err = ParseDecimalError;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (negative, tenths) = parse_scaled(text, 1)?;
+        if !tenths.is_multiple_of(5) {
+            return Err(ParseDecimalError::TooManyDecimals);
+        }
+        let half_ticks = i64::try_from(tenths / 5).map_err(|_| ParseDecimalError::Overflow)?;
+        Ok(Self(if negative { -half_ticks } else { half_ticks }))
+    }
+}
+
+impl fmt::Display for Ticks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let half_ticks = self.0.unsigned_abs();
+        if half_ticks.is_multiple_of(2) {
+            write!(f, "{}{}", sign, half_ticks / 2)
+        } else {
+            write!(f, "{}{}.5", sign, half_ticks / 2)
+        }
+    }
+}
+
+impl fmt::Debug for Ticks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// Signed fixed-point price with SCALE implied decimal places, e.g.
+// FixedPrice<8> stores 0.00000001 as 1. Fine enough for crypto tick sizes, and
+// a midpoint between two ticks is exact as long as the tick is coarser than the scale.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FixedPrice<const SCALE: u32>(i64);
+
+impl<const SCALE: u32> FixedPrice<SCALE> {
+    pub const fn from_raw(units: i64) -> Self {
+        Self(units)
+    }
+    pub const fn raw(&self) -> i64 {
+        self.0
+    }
+}
+
+impl<const SCALE: u32> PriceType for FixedPrice<SCALE> {
+    fn zero() -> Self {
+        Self(0)
+    }
+    fn midpoint_rounded(low: Self, high: Self, round_up: bool) -> Self {
+        Self(i64::midpoint_rounded(low.0, high.0, round_up))
+    }
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / 10f64.powi(SCALE as i32)
+    }
+    fn from_f64(value: f64) -> Self {
+        Self((value * 10f64.powi(SCALE as i32)).round() as i64)
+    }
+}
+
+impl<const SCALE: u32> Add for FixedPrice<SCALE> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl<const SCALE: u32> Sub for FixedPrice<SCALE> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl<const SCALE: u32> FromStr for FixedPrice<SCALE> {
+    type Err = ParseDecimalError;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (negative, units) = parse_scaled(text, SCALE)?;
+        let units = i64::try_from(units).map_err(|_| ParseDecimalError::Overflow)?;
+        Ok(Self(if negative { -units } else { units }))
+    }
+}
+
+impl<const SCALE: u32> fmt::Display for FixedPrice<SCALE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_scaled(f, self.0 < 0, self.0.unsigned_abs() as u128, SCALE)
+    }
+}
+
+impl<const SCALE: u32> fmt::Debug for FixedPrice<SCALE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// Unsigned fixed-point quantity with SCALE implied decimal places.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FixedQuantity<const SCALE: u32>(u64);
+
+impl<const SCALE: u32> FixedQuantity<SCALE> {
+    pub const fn from_raw(units: u64) -> Self {
+        Self(units)
+    }
+    pub const fn raw(&self) -> u64 {
+        self.0
+    }
+}
+
+impl<const SCALE: u32> QuantityType for FixedQuantity<SCALE> {
+    fn zero() -> Self {
+        Self(0)
+    }
+    fn to_f64(self) -> f64 {
+        self.0 as f64 / 10f64.powi(SCALE as i32)
+    }
+    fn from_f64(value: f64) -> Self {
+        Self((value * 10f64.powi(SCALE as i32)).round() as u64)
+    }
+}
+
+impl<const SCALE: u32> Add for FixedQuantity<SCALE> {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self(self.0 + other.0)
+    }
+}
+
+impl<const SCALE: u32> Sub for FixedQuantity<SCALE> {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self(self.0 - other.0)
+    }
+}
+
+impl<const SCALE: u32> AddAssign for FixedQuantity<SCALE> {
+    fn add_assign(&mut self, other: Self) {
+        self.0 += other.0;
+    }
+}
+
+impl<const SCALE: u32> SubAssign for FixedQuantity<SCALE> {
+    fn sub_assign(&mut self, other: Self) {
+        self.0 -= other.0;
+    }
+}
+
+impl<const SCALE: u32> Sum for FixedQuantity<SCALE> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        Self(iter.map(|quantity| quantity.0).sum())
+    }
+}
+
+impl<const SCALE: u32> FromStr for FixedQuantity<SCALE> {
+    type Err = ParseDecimalError;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match parse_scaled(text, SCALE)? {
+            (true, units) if units > 0 => Err(ParseDecimalError::InvalidDigit),
+            (_, units) => u64::try_from(units).map(Self).map_err(|_| ParseDecimalError::Overflow),
+        }
+    }
+}
+
+impl<const SCALE: u32> fmt::Display for FixedQuantity<SCALE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_scaled(f, false, self.0 as u128, SCALE)
+    }
+}
+
+impl<const SCALE: u32> fmt::Debug for FixedQuantity<SCALE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fixed_price_parse_and_display(){
+        let price: FixedPrice<8> = "67203.12345678".parse().unwrap();
+        assert_eq!(price.raw(), 6_720_312_345_678);
+        assert_eq!(price.to_string(), "67203.12345678");
+
+        let negative: FixedPrice<2> = "-0.5".parse().unwrap();
+        assert_eq!(negative.raw(), -50);
+        assert_eq!(negative.to_string(), "-0.50");
+
+        assert_eq!("1.123".parse::<FixedPrice<2>>(), Err(ParseDecimalError::TooManyDecimals));
+        assert_eq!("1.x".parse::<FixedPrice<2>>(), Err(ParseDecimalError::InvalidDigit));
+        assert_eq!("-1".parse::<FixedQuantity<2>>(), Err(ParseDecimalError::InvalidDigit));
+    }
+
+    #[test]
+    fn test_midpoint_rounding(){
+        assert_eq!(i64::midpoint_rounded(100, 101, false), 100);
+        assert_eq!(i64::midpoint_rounded(100, 101, true), 101);
+        assert_eq!(i64::midpoint_rounded(-3, -2, false), -3);
+
+        // a half cent midpoint is exact at four decimals
+        let bid: FixedPrice<4> = "100.01".parse().unwrap();
+        let ask: FixedPrice<4> = "100.02".parse().unwrap();
+        assert_eq!(FixedPrice::midpoint_rounded(bid, ask, false).to_string(), "100.0150");
+    }
+
+    #[test]
+    fn test_ticks(){
+        let midpoint = Ticks::midpoint_rounded(Ticks::from(100), Ticks::from(103), false);
+        assert_eq!(midpoint.half_ticks(), 203);
+        assert_eq!(midpoint.to_string(), "101.5");
+        assert_eq!(midpoint.to_f64(), 101.5);
+        assert_eq!("101.5".parse::<Ticks>(), Ok(midpoint));
+        assert_eq!("-0.5".parse::<Ticks>().unwrap().to_string(), "-0.5");
+        assert_eq!("101.2".parse::<Ticks>(), Err(ParseDecimalError::TooManyDecimals));
+        assert_eq!(Ticks::from(100), 100);
+    }
+}
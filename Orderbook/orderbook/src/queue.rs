@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use crate::orderbook::{Price, Quantity, Side};
+use crate::price::{PriceType, QuantityType};
+
+type OrderId = u32;
+
+// A level update only says the size went down, not whose orders left. This is
+// how the estimator splits a fall that no trade explains between the quantity
+// ahead of an order and the quantity behind it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CancelAssumption {
+    // cancels come from behind us first; the pessimistic estimate
+    Back,
+    // cancels are spread over the queue in proportion to its size
+    Proportional,
+    // cancels come from ahead of us first; the optimistic estimate
+    Front,
+}
+
+#[derive(Debug)]
+struct TrackedOrder<P, Q> {
+    side: Side,
+    price: P,
+    // kept fractional so proportional cuts don't round away
+    ahead: f64,
+    remaining: Q,
+    // the level's size as last seen, this order included
+    level: Q,
+}
+
+// Client-side queue position for our own resting orders, tracked from L2 deltas
+// and trade prints where the feed doesn't show individual orders. A local
+// `Orderbook` knows the answer exactly through `queue_position`.
+#[derive(Debug)]
+pub struct QueueEstimator<P = Price, Q = Quantity> {
+    assumption: CancelAssumption,
+    orders: HashMap<OrderId, TrackedOrder<P, Q>>,
+}
+
+impl<P: PriceType, Q: QuantityType> QueueEstimator<P, Q> {
+    pub fn new(assumption: CancelAssumption) -> Self {
+        Self { assumption, orders: HashMap::new() }
+    }
+
+    // An order of ours started resting; `level_quantity` is the level's size
+    // just before it joined, all of which is now ahead of it.
+    pub fn join(&mut self, order_id: OrderId, side: Side, price: P, quantity: Q, level_quantity: Q) {
+        self.orders.insert(order_id, TrackedOrder {
+            side,
+            price,
+            ahead: level_quantity.to_f64(),
+            remaining: quantity,
+            level: level_quantity + quantity,
+        });
+    }
+
+    // cancelled, replaced or otherwise gone
+    pub fn remove(&mut self, order_id: OrderId) {
+        self.orders.remove(&order_id);
+    }
+
+    pub fn ahead(&self, order_id: OrderId) -> Option<Q> {
+        self.orders.get(&order_id).map(|order| Q::from_f64(order.ahead))
+    }
+
+    // Our order traded, so nothing was left ahead of it. The trade print
+    // accounts for the level getting smaller.
+    pub fn on_fill(&mut self, order_id: OrderId, quantity: Q) {
+        let Some(order) = self.orders.get_mut(&order_id) else { return };
+        order.ahead = 0.0;
+        order.remaining -= quantity.min(order.remaining);
+        if order.remaining == Q::zero() {
+            self.orders.remove(&order_id);
+        }
+    }
+
+    // A print against resting orders on `side`. Trades take from the front of
+    // the queue; one through a worse price means our level was cleared first.
+    pub fn on_trade(&mut self, side: Side, price: P, quantity: Q) {
+        for order in self.orders.values_mut().filter(|order| order.side == side) {
+            let through = match side {
+                Side::Buy => price < order.price,
+                Side::Sell => price > order.price,
+            };
+            if through {
+                order.ahead = 0.0;
+            } else if price == order.price {
+                order.ahead = (order.ahead - quantity.to_f64()).max(0.0);
+                order.level -= quantity.min(order.level);
+            }
+        }
+    }
+
+    // the level's new total size from an L2 update
+    pub fn on_level(&mut self, side: Side, price: P, quantity: Q) {
+        let assumption = self.assumption;
+        for order in self.orders.values_mut().filter(|order| order.side == side && order.price == price) {
+            if quantity < order.level {
+                let fall = (order.level - quantity).to_f64();
+                let behind = (order.level.to_f64() - order.ahead - order.remaining.to_f64()).max(0.0);
+                let from_ahead = match assumption {
+                    CancelAssumption::Back => (fall - behind).max(0.0),
+                    CancelAssumption::Front => fall.min(order.ahead),
+                    CancelAssumption::Proportional if order.ahead + behind > 0.0 => fall * order.ahead / (order.ahead + behind),
+                    CancelAssumption::Proportional => 0.0,
+                };
+                order.ahead = (order.ahead - from_ahead).max(0.0);
+            }
+            // whatever else is on the level, ours can't be ahead of itself
+            order.ahead = order.ahead.min((quantity.to_f64() - order.remaining.to_f64()).max(0.0));
+            order.level = quantity;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::orderbook::{Order, OrderType, Orderbook};
+
+    fn level(book: &Orderbook, price: Price) -> Quantity {
+        book.get_order_infos().get_bids().iter().find(|level| level.price == price).map_or(0, |level| level.quantity)
+    }
+
+    #[test]
+    fn test_estimate_tracks_the_book(){
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut estimators = [CancelAssumption::Back, CancelAssumption::Proportional, CancelAssumption::Front].map(QueueEstimator::new);
+        book.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10));
+        book.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 100, 5));
+
+        // ours joins behind 15, then 6 more join behind us
+        book.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 100, 4));
+        book.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Buy, 100, 6));
+        for estimator in &mut estimators {
+            estimator.join(3, Side::Buy, Price::from(100), 4, 15);
+            estimator.on_level(Side::Buy, Price::from(100), level(&book, Price::from(100)));
+        }
+        assert_eq!(estimators.each_ref().map(|estimator| estimator.ahead(3)), [Some(15); 3]);
+
+        // 5 cancelled from ahead of us; only the optimistic view gets it right
+        book.cancel_order(2);
+        for estimator in &mut estimators {
+            estimator.on_level(Side::Buy, Price::from(100), level(&book, Price::from(100)));
+        }
+        assert_eq!(book.queue_position(3).unwrap().ahead_quantity, 10);
+        assert_eq!(estimators.each_ref().map(|estimator| estimator.ahead(3)), [Some(15), Some(11), Some(10)]);
+
+        // a sell for 12 clears what's ahead and fills 2 of ours
+        let trades = book.add_order(Order::new(OrderType::GoodTillCancel, 5, Side::Sell, 100, 12));
+        for estimator in &mut estimators {
+            for trade in &trades {
+                estimator.on_trade(Side::Buy, trade.get_price(), trade.get_quantity());
+                if trade.get_bid_trade().order_id == 3 {
+                    estimator.on_fill(3, trade.get_quantity());
+                }
+            }
+            estimator.on_level(Side::Buy, Price::from(100), level(&book, Price::from(100)));
+        }
+        assert_eq!(book.queue_position(3).unwrap().ahead_quantity, 0);
+        assert_eq!(estimators.each_ref().map(|estimator| estimator.ahead(3)), [Some(0); 3]);
+
+        // the rest of ours fills and it's no longer tracked
+        for estimator in &mut estimators {
+            estimator.on_fill(3, 2);
+        }
+        assert_eq!(estimators[0].ahead(3), None);
+    }
+}
@@ -0,0 +1,554 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+use crate::orderbook::{ModifyOutcome, Order, OrderModify, OrderType, Orderbook, Price, Quantity, Side, Trade};
+use crate::price::{PriceType, QuantityType};
+
+pub type AccountId = u32;
+type OrderId = u32;
+type OrderPointer<P, Q> = Arc<Mutex<Order<P, Q>>>;
+type Trades<P, Q> = Vec<Trade<P, Q>>;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RiskReject {
+    GlobalKillSwitch,
+    AccountKillSwitch,
+    AccountThrottle,
+    GlobalThrottle,
+    OrderQuantityLimit,
+    NotionalLimit,
+    PositionLimit,
+    OpenOrderLimit,
+    // priced too far from the last trade
+    PriceCollar,
+    // not an open order of the account asking
+    UnknownOrder,
+    // the id belongs to an order the gate or the book already has
+    DuplicateOrderId,
+}
+
+// At most `max_messages` orders and amends inside any `window`.
+#[derive(Debug, Clone, Copy)]
+pub struct Throttle {
+    pub max_messages: u32,
+    pub window: Duration,
+}
+
+// Per-account limits; None means unlimited.
+#[derive(Debug, Clone, Copy)]
+pub struct RiskLimits<Q = Quantity> {
+    pub max_order_quantity: Option<Q>,
+    // price times quantity of a single order
+    pub max_notional: Option<f64>,
+    // net position either way if every open order on that side filled
+    pub max_position: Option<Q>,
+    pub max_open_orders: Option<usize>,
+    pub throttle: Option<Throttle>,
+}
+
+impl<Q> Default for RiskLimits<Q> {
+    fn default() -> Self {
+        Self {
+            max_order_quantity: None,
+            max_notional: None,
+            max_position: None,
+            max_open_orders: None,
+            throttle: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MessageWindow {
+    sent: VecDeque<Instant>,
+}
+
+impl MessageWindow {
+    fn allows(&mut self, throttle: Option<Throttle>, now: Instant) -> bool {
+        let Some(throttle) = throttle else { return true };
+        while self.sent.front().is_some_and(|sent| now.duration_since(*sent) >= throttle.window) {
+            self.sent.pop_front();
+        }
+        self.sent.len() < throttle.max_messages as usize
+    }
+
+    fn record(&mut self, now: Instant) {
+        self.sent.push_back(now);
+    }
+}
+
+#[derive(Debug)]
+struct Account<P, Q> {
+    limits: RiskLimits<Q>,
+    killed: bool,
+    bought: Q,
+    sold: Q,
+    // everything the account has resting or waiting as a stop
+    orders: HashMap<OrderId, OrderPointer<P, Q>>,
+    // how much of each open order's fills settle has counted so far
+    credited: HashMap<OrderId, Q>,
+    messages: MessageWindow,
+}
+
+impl<P: PriceType, Q: QuantityType> Account<P, Q> {
+    fn new(limits: RiskLimits<Q>) -> Self {
+        Self {
+            limits,
+            killed: false,
+            bought: Q::zero(),
+            sold: Q::zero(),
+            orders: HashMap::new(),
+            credited: HashMap::new(),
+            messages: MessageWindow::default(),
+        }
+    }
+
+    // quantity still open on `side`, leaving out `except` (an order being amended)
+    fn open_quantity(&self, side: Side, except: Option<OrderId>) -> Q {
+        self.orders
+            .iter()
+            .filter(|(order_id, _)| Some(**order_id) != except)
+            .map(|(_, order)| order.lock().unwrap())
+            .filter(|ord| ord.get_side() == side)
+            .fold(Q::zero(), |total, ord| total + ord.get_remaining_quantity())
+    }
+}
+
+// Pre-trade checks in front of a book. Orders, amends and cancels go through the
+// gate, which tracks each account's fills and open orders from what the book returns.
+// Cancels are never blocked, so an account can always take risk off.
+#[derive(Debug)]
+pub struct RiskGate<P = Price, Q = Quantity> {
+    book: Orderbook<P, Q>,
+    default_limits: RiskLimits<Q>,
+    accounts: HashMap<AccountId, Account<P, Q>>,
+    owners: HashMap<OrderId, AccountId>,
+    // how far an order's price may be from the last trade, as a fraction of it
+    collar: Option<f64>,
+    throttle: Option<Throttle>,
+    messages: MessageWindow,
+    killed: bool,
+}
+
+impl<P: PriceType, Q: QuantityType> RiskGate<P, Q> {
+    pub fn new(book: Orderbook<P, Q>, default_limits: RiskLimits<Q>) -> Self {
+        Self {
+            book,
+            default_limits,
+            accounts: HashMap::new(),
+            owners: HashMap::new(),
+            collar: None,
+            throttle: None,
+            messages: MessageWindow::default(),
+            killed: false,
+        }
+    }
+
+    pub const fn get_book(&self) -> &Orderbook<P, Q> {
+        &self.book
+    }
+
+    pub fn set_account_limits(&mut self, account: AccountId, limits: RiskLimits<Q>) {
+        self.account(account).limits = limits;
+    }
+
+    pub fn set_price_collar(&mut self, collar: Option<f64>) {
+        self.collar = collar;
+    }
+
+    pub fn set_global_throttle(&mut self, throttle: Option<Throttle>) {
+        self.throttle = throttle;
+    }
+
+    pub fn submit(&mut self, account: AccountId, order: OrderPointer<P, Q>) -> Result<Trades<P, Q>, RiskReject> {
+        let (order_id, side, price, quantity, order_type) = {
+            let ord = order.lock().unwrap();
+            (ord.get_order_id(), ord.get_side(), ord.get_price(), ord.get_remaining_quantity(), ord.get_order_type())
+        };
+        // checked before anything is counted, and before the id could be handed to this account
+        if self.owners.contains_key(&order_id) || self.book.contains(order_id) {
+            debug!(account, order_id, "risk reject: duplicate order id");
+            return Err(RiskReject::DuplicateOrderId);
+        }
+        let result = self.check(account, side, price, quantity, order_type, None);
+        if let Err(reject) = result {
+            debug!(account, order_id, ?reject, "risk reject");
+            return Err(reject);
+        }
+
+        self.owners.insert(order_id, account);
+        self.account(account).orders.insert(order_id, order.clone());
+        let trades = self.book.add_order(order);
+        self.settle(&trades);
+        self.forget_if_gone(order_id);
+        Ok(trades)
+    }
+
+    pub fn modify(&mut self, account: AccountId, modify: OrderModify<P, Q>) -> Result<ModifyOutcome<P, Q>, RiskReject> {
+        let order_id = modify.get_order_id();
+        let order = match self.owners.get(&order_id) {
+            Some(owner) if *owner == account => self.accounts[&account].orders[&order_id].clone(),
+            _ => return Err(RiskReject::UnknownOrder),
+        };
+        let (order_type, filled) = {
+            let ord = order.lock().unwrap();
+            (ord.get_order_type(), ord.get_filled_quantity())
+        };
+        // the new quantity is the order's total size, fills so far included
+        let open = if modify.get_quantity() > filled { modify.get_quantity() - filled } else { Q::zero() };
+        let result = self.check(account, modify.get_side(), Some(modify.get_price()), open, order_type, Some(order_id));
+        if let Err(reject) = result {
+            debug!(account, order_id, ?reject, "risk reject on amend");
+            return Err(reject);
+        }
+
+        let outcome = self.book.modify_order(modify);
+        if let ModifyOutcome::AmendedInPlace(trades) | ModifyOutcome::Replaced(trades) | ModifyOutcome::Cancelled(trades) = &outcome {
+            self.settle(trades);
+        }
+        self.forget_if_gone(order_id);
+        Ok(outcome)
+    }
+
+    pub fn cancel(&mut self, account: AccountId, order_id: OrderId) -> Result<Trades<P, Q>, RiskReject> {
+        if self.owners.get(&order_id) != Some(&account) {
+            return Err(RiskReject::UnknownOrder);
+        }
+        let trades = self.book.cancel_order(order_id);
+        self.settle(&trades);
+        self.forget(order_id);
+        Ok(trades)
+    }
+
+    // Blocks new orders from the account and pulls everything it has open.
+    pub fn kill_account(&mut self, account: AccountId) -> Trades<P, Q> {
+        warn!(account, "account kill switch");
+        self.account(account).killed = true;
+        self.cancel_all(|owner| owner == account)
+    }
+
+    pub fn revive_account(&mut self, account: AccountId) {
+        info!(account, "account kill switch reset");
+        self.account(account).killed = false;
+    }
+
+    // Blocks new orders from everyone and pulls every order that went through the gate.
+    pub fn kill_all(&mut self) -> Trades<P, Q> {
+        warn!("global kill switch");
+        self.killed = true;
+        self.cancel_all(|_| true)
+    }
+
+    pub fn revive_all(&mut self) {
+        info!("global kill switch reset");
+        self.killed = false;
+    }
+
+    // bought minus sold
+    pub fn net_position(&self, account: AccountId) -> f64 {
+        self.accounts.get(&account).map_or(0.0, |state| state.bought.to_f64() - state.sold.to_f64())
+    }
+
+    pub fn open_orders(&mut self, account: AccountId) -> usize {
+        self.reconcile(account);
+        self.accounts.get(&account).map_or(0, |state| state.orders.len())
+    }
+
+    fn account(&mut self, account: AccountId) -> &mut Account<P, Q> {
+        let limits = self.default_limits;
+        self.accounts.entry(account).or_insert_with(|| Account::new(limits))
+    }
+
+    fn check(
+        &mut self,
+        account: AccountId,
+        side: Side,
+        price: Option<P>,
+        quantity: Q,
+        order_type: OrderType,
+        amending: Option<OrderId>,
+    ) -> Result<(), RiskReject> {
+        if self.killed {
+            return Err(RiskReject::GlobalKillSwitch);
+        }
+        if self.account(account).killed {
+            return Err(RiskReject::AccountKillSwitch);
+        }
+
+        let now = Instant::now();
+        let limits = self.account(account).limits;
+        if !self.account(account).messages.allows(limits.throttle, now) {
+            return Err(RiskReject::AccountThrottle);
+        }
+        if !self.messages.allows(self.throttle, now) {
+            return Err(RiskReject::GlobalThrottle);
+        }
+        self.account(account).messages.record(now);
+        self.messages.record(now);
+
+        if limits.max_order_quantity.is_some_and(|max| quantity > max) {
+            return Err(RiskReject::OrderQuantityLimit);
+        }
+
+        // a market order is checked at the worst price it would sweep to; pegs and stops
+        // have no price yet, so only the notional check applies, at the last trade
+        let last_trade = self.book.get_last_trade_price();
+        let (check_price, notional) = match (order_type, price) {
+            (OrderType::Market, _) => match self.book.sweep_cost(side, quantity) {
+                Some(sweep) => {
+                    let unfilled = quantity.to_f64() - sweep.filled.to_f64();
+                    (Some(sweep.worst_price), Some(sweep.notional + unfilled * sweep.worst_price.to_f64()))
+                }
+                None => (None, None),
+            },
+            (_, Some(price)) => (Some(price), Some(price.to_f64() * quantity.to_f64())),
+            (_, None) => (None, last_trade.map(|last| last.to_f64() * quantity.to_f64())),
+        };
+
+        if let (Some(max), Some(notional)) = (limits.max_notional, notional) {
+            if notional > max {
+                return Err(RiskReject::NotionalLimit);
+            }
+        }
+
+        if let (Some(collar), Some(price), Some(last)) = (self.collar, check_price, last_trade) {
+            let last = last.to_f64();
+            if (price.to_f64() - last).abs() > last.abs() * collar {
+                return Err(RiskReject::PriceCollar);
+            }
+        }
+
+        self.reconcile(account);
+        let state = &self.accounts[&account];
+        if let Some(max) = limits.max_position {
+            // compared without going negative: bought + open buys + this order <= sold + max
+            let open = state.open_quantity(side, amending) + quantity;
+            let breached = match side {
+                Side::Buy => state.bought + open > state.sold + max,
+                Side::Sell => state.sold + open > state.bought + max,
+            };
+            if breached {
+                return Err(RiskReject::PositionLimit);
+            }
+        }
+
+        if amending.is_none() && limits.max_open_orders.is_some_and(|max| state.orders.len() >= max) {
+            return Err(RiskReject::OpenOrderLimit);
+        }
+        Ok(())
+    }
+
+    // credits fills to the accounts whose orders traded
+    fn settle(&mut self, trades: &[Trade<P, Q>]) {
+        for trade in trades {
+            let (bid, ask) = (trade.get_bid_trade(), trade.get_ask_trade());
+            if let Some(state) = self.owners.get(&bid.order_id).and_then(|owner| self.accounts.get_mut(owner)) {
+                state.bought += bid.quantity;
+                *state.credited.entry(bid.order_id).or_insert_with(Q::zero) += bid.quantity;
+            }
+            if let Some(state) = self.owners.get(&ask.order_id).and_then(|owner| self.accounts.get_mut(owner)) {
+                state.sold += ask.quantity;
+                *state.credited.entry(ask.order_id).or_insert_with(Q::zero) += ask.quantity;
+            }
+        }
+        for trade in trades {
+            self.forget_if_gone(trade.get_bid_trade().order_id);
+            self.forget_if_gone(trade.get_ask_trade().order_id);
+        }
+    }
+
+    fn forget_if_gone(&mut self, order_id: OrderId) {
+        if !self.book.contains(order_id) {
+            self.forget(order_id);
+        }
+    }
+
+    // the order may have traded through another handle on the book, so whatever it
+    // filled beyond what settle saw is credited before it's dropped
+    fn forget(&mut self, order_id: OrderId) {
+        if let Some(owner) = self.owners.remove(&order_id) {
+            if let Some(state) = self.accounts.get_mut(&owner) {
+                let credited = state.credited.remove(&order_id).unwrap_or_else(Q::zero);
+                if let Some(order) = state.orders.remove(&order_id) {
+                    let (side, filled) = {
+                        let ord = order.lock().unwrap();
+                        (ord.get_side(), ord.get_filled_quantity())
+                    };
+                    if filled > credited {
+                        match side {
+                            Side::Buy => state.bought += filled - credited,
+                            Side::Sell => state.sold += filled - credited,
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // drops orders the book let go of on its own, like expired good for day orders
+    fn reconcile(&mut self, account: AccountId) {
+        let gone: Vec<OrderId> = match self.accounts.get(&account) {
+            Some(state) => state.orders.keys().copied().filter(|order_id| !self.book.contains(*order_id)).collect(),
+            None => return,
+        };
+        for order_id in gone {
+            self.forget(order_id);
+        }
+    }
+
+    fn cancel_all(&mut self, pick: impl Fn(AccountId) -> bool) -> Trades<P, Q> {
+        let order_ids: Vec<OrderId> = self.owners.iter().filter(|(_, owner)| pick(**owner)).map(|(order_id, _)| *order_id).collect();
+        let mut trades = vec![];
+        for order_id in order_ids {
+            let cancelled = self.book.cancel_order(order_id);
+            self.settle(&cancelled);
+            self.forget(order_id);
+            trades.extend(cancelled);
+        }
+        trades
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn gate(limits: RiskLimits) -> RiskGate {
+        RiskGate::new(Orderbook::new(BTreeMap::new(), BTreeMap::new()), limits)
+    }
+
+    #[test]
+    fn test_order_size_and_notional_limits(){
+        let mut gate = gate(RiskLimits { max_order_quantity: Some(100), max_notional: Some(5_000.0), ..Default::default() });
+        let rejected = gate.submit(1, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 10, 101));
+        assert_eq!(rejected.unwrap_err(), RiskReject::OrderQuantityLimit);
+        let rejected = gate.submit(1, Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 60, 100));
+        assert_eq!(rejected.unwrap_err(), RiskReject::NotionalLimit);
+        assert!(gate.submit(1, Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 50, 100)).is_ok());
+        assert_eq!(gate.get_book().size(), 1);
+
+        // a market order is costed at what it would sweep
+        gate.submit(2, Order::new(OrderType::GoodTillCancel, 4, Side::Sell, 55, 50)).unwrap();
+        gate.submit(2, Order::new(OrderType::GoodTillCancel, 5, Side::Sell, 60, 50)).unwrap();
+        let rejected = gate.submit(1, Order::new_market(6, Side::Buy, 90));
+        assert_eq!(rejected.unwrap_err(), RiskReject::NotionalLimit);
+        let trades = gate.submit(1, Order::new_market(7, Side::Buy, 80)).unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(gate.net_position(1), 80.0);
+    }
+
+    #[test]
+    fn test_position_and_open_order_limits(){
+        let mut gate = gate(RiskLimits { max_position: Some(50), max_open_orders: Some(2), ..Default::default() });
+        gate.submit(1, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 30)).unwrap();
+        // 30 open plus 30 more could leave the account long 60
+        let rejected = gate.submit(1, Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 99, 30));
+        assert_eq!(rejected.unwrap_err(), RiskReject::PositionLimit);
+        gate.submit(1, Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 99, 20)).unwrap();
+        let rejected = gate.submit(1, Order::new(OrderType::GoodTillCancel, 4, Side::Sell, 200, 1));
+        assert_eq!(rejected.unwrap_err(), RiskReject::OpenOrderLimit);
+
+        // another account fills the first order, which frees an open order slot
+        let trades = gate.submit(2, Order::new(OrderType::GoodTillCancel, 5, Side::Sell, 100, 30)).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(gate.net_position(1), 30.0);
+        assert_eq!(gate.net_position(2), -30.0);
+        assert_eq!(gate.open_orders(1), 1);
+
+        // selling brings the position back in, so a sale of 80 is fine
+        gate.submit(1, Order::new(OrderType::GoodTillCancel, 6, Side::Sell, 200, 80)).unwrap();
+        let rejected = gate.modify(1, OrderModify::new(3, Side::Buy, 99, 40));
+        assert_eq!(rejected.unwrap_err(), RiskReject::PositionLimit);
+        assert!(gate.modify(1, OrderModify::new(3, Side::Buy, 99, 10)).is_ok());
+        assert_eq!(gate.cancel(2, 3).unwrap_err(), RiskReject::UnknownOrder);
+    }
+
+    #[test]
+    fn test_price_collar_and_throttles(){
+        let mut gate = gate(RiskLimits { throttle: Some(Throttle { max_messages: 3, window: Duration::from_secs(60) }), ..Default::default() });
+        gate.set_price_collar(Some(0.1));
+        // no last trade yet, nothing to collar against
+        gate.submit(1, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10)).unwrap();
+        gate.submit(2, Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 100, 5)).unwrap();
+        assert_eq!(gate.get_book().get_last_trade_price(), Some(Price::from(100)));
+
+        let rejected = gate.submit(2, Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 111, 5));
+        assert_eq!(rejected.unwrap_err(), RiskReject::PriceCollar);
+        gate.submit(2, Order::new(OrderType::GoodTillCancel, 4, Side::Sell, 110, 5)).unwrap();
+        // account 2 has used its three messages; cancels still go through
+        let rejected = gate.submit(2, Order::new(OrderType::GoodTillCancel, 5, Side::Sell, 105, 5));
+        assert_eq!(rejected.unwrap_err(), RiskReject::AccountThrottle);
+        assert!(gate.cancel(2, 4).is_ok());
+
+        gate.set_global_throttle(Some(Throttle { max_messages: 5, window: Duration::from_secs(60) }));
+        gate.submit(3, Order::new(OrderType::GoodTillCancel, 6, Side::Buy, 95, 5)).unwrap();
+        let rejected = gate.submit(3, Order::new(OrderType::GoodTillCancel, 7, Side::Buy, 95, 5));
+        assert_eq!(rejected.unwrap_err(), RiskReject::GlobalThrottle);
+    }
+
+    #[test]
+    fn test_kill_switches_pull_orders(){
+        let mut gate = gate(RiskLimits::default());
+        gate.submit(1, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10)).unwrap();
+        gate.submit(1, Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 105, 10)).unwrap();
+        gate.submit(2, Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 99, 10)).unwrap();
+
+        gate.kill_account(1);
+        assert_eq!(gate.get_book().size(), 1);
+        assert_eq!(gate.open_orders(1), 0);
+        let rejected = gate.submit(1, Order::new(OrderType::GoodTillCancel, 4, Side::Buy, 100, 10));
+        assert_eq!(rejected.unwrap_err(), RiskReject::AccountKillSwitch);
+        gate.submit(2, Order::new(OrderType::GoodTillCancel, 5, Side::Buy, 98, 10)).unwrap();
+
+        gate.kill_all();
+        assert_eq!(gate.get_book().size(), 0);
+        let rejected = gate.submit(2, Order::new(OrderType::GoodTillCancel, 6, Side::Buy, 98, 10));
+        assert_eq!(rejected.unwrap_err(), RiskReject::GlobalKillSwitch);
+
+        gate.revive_all();
+        gate.revive_account(1);
+        assert!(gate.submit(1, Order::new(OrderType::GoodTillCancel, 7, Side::Buy, 100, 10)).is_ok());
+    }
+
+    #[test]
+    fn test_fills_outside_the_gate_count_toward_position(){
+        let mut gate = gate(RiskLimits { max_position: Some(10), ..Default::default() });
+        gate.submit(1, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10)).unwrap();
+        // part filled straight on the book, the rest through the gate
+        gate.get_book().add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 100, 4));
+        gate.submit(2, Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 100, 6)).unwrap();
+        assert_eq!(gate.net_position(1), 10.0);
+        assert_eq!(gate.net_position(2), -6.0);
+        assert_eq!(gate.open_orders(1), 0);
+
+        // filled entirely around the gate, found when the account next trades
+        gate.submit(2, Order::new(OrderType::GoodTillCancel, 4, Side::Sell, 101, 4)).unwrap();
+        gate.get_book().add_order(Order::new(OrderType::GoodTillCancel, 5, Side::Buy, 101, 4));
+        let rejected = gate.submit(2, Order::new(OrderType::GoodTillCancel, 6, Side::Sell, 102, 1));
+        assert_eq!(rejected.unwrap_err(), RiskReject::PositionLimit);
+        assert_eq!(gate.net_position(2), -10.0);
+        let rejected = gate.submit(1, Order::new(OrderType::GoodTillCancel, 7, Side::Buy, 99, 1));
+        assert_eq!(rejected.unwrap_err(), RiskReject::PositionLimit);
+    }
+
+    #[test]
+    fn test_duplicate_order_id_keeps_the_owner(){
+        let mut gate = gate(RiskLimits { max_open_orders: Some(1), ..Default::default() });
+        gate.submit(1, Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 100, 10)).unwrap();
+        // straight into the book, around the gate
+        gate.get_book().add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Buy, 99, 10));
+
+        let rejected = gate.submit(2, Order::new(OrderType::GoodTillCancel, 1, Side::Sell, 105, 10));
+        assert_eq!(rejected.unwrap_err(), RiskReject::DuplicateOrderId);
+        let rejected = gate.submit(2, Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 105, 10));
+        assert_eq!(rejected.unwrap_err(), RiskReject::DuplicateOrderId);
+        assert_eq!(gate.cancel(2, 1).unwrap_err(), RiskReject::UnknownOrder);
+        assert_eq!(gate.open_orders(1), 1);
+        assert_eq!(gate.open_orders(2), 0);
+        assert!(gate.cancel(1, 1).is_ok());
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::mpsc::{self, Receiver, Sender},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use crate::orderbook::{Price, Quantity, Symbol, Trade};
+use crate::price::{PriceType, QuantityType};
+
+// milliseconds since the Unix epoch; callers pass it in so a replay can use its own clock
+pub type Timestamp = u64;
+
+pub fn now() -> Timestamp {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as Timestamp)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BarInterval {
+    OneSecond,
+    OneMinute,
+    FiveMinutes,
+}
+
+impl BarInterval {
+    pub const ALL: [BarInterval; 3] = [BarInterval::OneSecond, BarInterval::OneMinute, BarInterval::FiveMinutes];
+
+    pub const fn millis(&self) -> u64 {
+        match self {
+            BarInterval::OneSecond => 1_000,
+            BarInterval::OneMinute => 60_000,
+            BarInterval::FiveMinutes => 300_000,
+        }
+    }
+
+    // start of the bar a trade at `timestamp` falls in; bars line up with the epoch
+    const fn bar_start(&self, timestamp: Timestamp) -> Timestamp {
+        timestamp - timestamp % self.millis()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Bar<P = Price, Q = Quantity> {
+    pub interval: BarInterval,
+    pub start: Timestamp,
+    pub open: P,
+    pub high: P,
+    pub low: P,
+    pub close: P,
+    pub volume: Q,
+    pub trade_count: u64,
+    notional: f64,
+}
+
+impl<P: PriceType, Q: QuantityType> Bar<P, Q> {
+    fn new(interval: BarInterval, start: Timestamp, price: P, quantity: Q) -> Self {
+        Self {
+            interval,
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: quantity,
+            trade_count: 1,
+            notional: price.to_f64() * quantity.to_f64(),
+        }
+    }
+
+    fn update(&mut self, price: P, quantity: Q) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += quantity;
+        self.trade_count += 1;
+        self.notional += price.to_f64() * quantity.to_f64();
+    }
+
+    pub const fn end(&self) -> Timestamp {
+        self.start + self.interval.millis()
+    }
+
+    pub fn vwap(&self) -> f64 {
+        self.notional / self.volume.to_f64()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SessionStats<P = Price, Q = Quantity> {
+    pub open: P,
+    pub high: P,
+    pub low: P,
+    pub last: P,
+    pub volume: Q,
+    pub trade_count: u64,
+    notional: f64,
+}
+
+impl<P: PriceType, Q: QuantityType> SessionStats<P, Q> {
+    fn new(price: P, quantity: Q) -> Self {
+        Self {
+            open: price,
+            high: price,
+            low: price,
+            last: price,
+            volume: quantity,
+            trade_count: 1,
+            notional: price.to_f64() * quantity.to_f64(),
+        }
+    }
+
+    fn update(&mut self, price: P, quantity: Q) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.last = price;
+        self.volume += quantity;
+        self.trade_count += 1;
+        self.notional += price.to_f64() * quantity.to_f64();
+    }
+
+    pub fn vwap(&self) -> f64 {
+        self.notional / self.volume.to_f64()
+    }
+}
+
+// sent to subscribers each time a bar closes
+#[derive(Clone, Debug)]
+pub struct BarUpdate<P = Price, Q = Quantity> {
+    pub symbol: Symbol,
+    pub bar: Bar<P, Q>,
+}
+
+#[derive(Debug)]
+struct SymbolStats<P, Q> {
+    session: SessionStats<P, Q>,
+    open_bars: HashMap<BarInterval, Bar<P, Q>>,
+    closed_bars: HashMap<BarInterval, VecDeque<Bar<P, Q>>>,
+    // end of the last bar closed per interval; nothing before it is built again
+    closed_until: HashMap<BarInterval, Timestamp>,
+}
+
+// Per-symbol session stats and 1s/1m/5m OHLCV bars built from the trades the
+// books return. Intervals without trades produce no bar.
+#[derive(Debug)]
+pub struct TradeStats<P = Price, Q = Quantity> {
+    symbols: HashMap<Symbol, SymbolStats<P, Q>>,
+    // closed bars kept per symbol and interval
+    history: usize,
+    subscribers: Vec<Sender<BarUpdate<P, Q>>>,
+}
+
+impl<P: PriceType, Q: QuantityType> TradeStats<P, Q> {
+    pub fn new(history: usize) -> Self {
+        Self {
+            symbols: HashMap::new(),
+            history,
+            subscribers: vec![],
+        }
+    }
+
+    pub fn record_trades(&mut self, symbol: &str, trades: &[Trade<P, Q>], timestamp: Timestamp) {
+        for trade in trades {
+            self.record_trade(symbol, trade.get_price(), trade.get_quantity(), timestamp);
+        }
+    }
+
+    pub fn record_trade(&mut self, symbol: &str, price: P, quantity: Q, timestamp: Timestamp) {
+        let mut closed = vec![];
+        match self.symbols.get_mut(symbol) {
+            Some(stats) => {
+                stats.session.update(price, quantity);
+                for interval in BarInterval::ALL {
+                    let start = interval.bar_start(timestamp);
+                    // a late trade belongs to a bar already sent or moved past, so it only
+                    // counts toward the session
+                    if stats.closed_until.get(&interval).is_some_and(|until| start < *until) {
+                        continue;
+                    }
+                    match stats.open_bars.get_mut(&interval) {
+                        Some(bar) if bar.start > start => {}
+                        Some(bar) if bar.start == start => bar.update(price, quantity),
+                        _ => {
+                            let bar = Bar::new(interval, start, price, quantity);
+                            closed.extend(stats.open_bars.insert(interval, bar));
+                        }
+                    }
+                }
+            }
+            None => {
+                let open_bars = BarInterval::ALL.iter()
+                    .map(|interval| (*interval, Bar::new(*interval, interval.bar_start(timestamp), price, quantity)))
+                    .collect();
+                self.symbols.insert(symbol.to_string(), SymbolStats {
+                    session: SessionStats::new(price, quantity),
+                    open_bars,
+                    closed_bars: HashMap::new(),
+                    closed_until: HashMap::new(),
+                });
+            }
+        }
+        for bar in closed {
+            self.close_bar(symbol, bar);
+        }
+    }
+
+    // Closes every bar whose interval ended at or before `timestamp`. Bars
+    // otherwise only close when the next trade arrives, so call this on a timer
+    // to stream bars through quiet periods.
+    pub fn close_bars(&mut self, timestamp: Timestamp) {
+        let mut closed = vec![];
+        for (symbol, stats) in self.symbols.iter_mut() {
+            stats.open_bars.retain(|_, bar| {
+                if bar.end() <= timestamp {
+                    closed.push((symbol.clone(), *bar));
+                    return false;
+                }
+                true
+            });
+        }
+        for (symbol, bar) in closed {
+            self.close_bar(&symbol, bar);
+        }
+    }
+
+    fn close_bar(&mut self, symbol: &str, bar: Bar<P, Q>) {
+        if let Some(stats) = self.symbols.get_mut(symbol) {
+            stats.closed_until.insert(bar.interval, bar.end());
+            let bars = stats.closed_bars.entry(bar.interval).or_default();
+            bars.push_back(bar);
+            while bars.len() > self.history {
+                bars.pop_front();
+            }
+        }
+        let update = BarUpdate { symbol: symbol.to_string(), bar };
+        self.subscribers.retain(|subscriber| subscriber.send(update.clone()).is_ok());
+    }
+
+    // every bar closed from now on, for every symbol and interval
+    pub fn subscribe(&mut self) -> Receiver<BarUpdate<P, Q>> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    pub fn get_session(&self, symbol: &str) -> Option<SessionStats<P, Q>> {
+        self.symbols.get(symbol).map(|stats| stats.session)
+    }
+
+    // the bar still being built
+    pub fn get_current_bar(&self, symbol: &str, interval: BarInterval) -> Option<Bar<P, Q>> {
+        self.symbols.get(symbol).and_then(|stats| stats.open_bars.get(&interval).copied())
+    }
+
+    // closed bars, oldest first
+    pub fn get_bars(&self, symbol: &str, interval: BarInterval) -> Vec<Bar<P, Q>> {
+        self.symbols.get(symbol)
+            .and_then(|stats| stats.closed_bars.get(&interval))
+            .map_or(vec![], |bars| bars.iter().copied().collect())
+    }
+
+    // drops all stats, e.g. at the start of a new trading day
+    pub fn reset_session(&mut self) {
+        self.symbols.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::orderbook::{Order, OrderType, Orderbook, Side};
+
+    #[test]
+    fn test_session_stats_from_book_trades(){
+        let orderbook = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        let mut stats = TradeStats::new(10);
+
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Sell, 100, 10));
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 102, 10));
+        // prints at the resting asks, not the bid's limit
+        let trades = orderbook.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 105, 15));
+        stats.record_trades("BTC/USD", &trades, 1_000);
+        orderbook.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Buy, 99, 5));
+        let trades = orderbook.add_order(Order::new(OrderType::FillAndKill, 5, Side::Sell, 90, 5));
+        stats.record_trades("BTC/USD", &trades, 2_000);
+
+        let session = stats.get_session("BTC/USD").unwrap();
+        assert_eq!((session.open, session.high, session.low, session.last), (Price::from(100), Price::from(102), Price::from(99), Price::from(99)));
+        assert_eq!(session.volume, 20);
+        assert_eq!(session.trade_count, 3);
+        assert_eq!(session.vwap(), (100.0 * 10.0 + 102.0 * 5.0 + 99.0 * 5.0) / 20.0);
+        assert!(stats.get_session("ETH/USD").is_none());
+    }
+
+    #[test]
+    fn test_bars_close_and_stream(){
+        let mut stats: TradeStats = TradeStats::new(2);
+        let updates = stats.subscribe();
+
+        stats.record_trade("SPY", Price::from(528), 10, 59_500);
+        stats.record_trade("SPY", Price::from(530), 5, 59_900);
+        assert!(updates.try_recv().is_err());
+
+        // next second closes the 1s bar, next minute the 1m bar
+        stats.record_trade("SPY", Price::from(527), 1, 60_100);
+        let second = updates.try_recv().unwrap();
+        assert_eq!(second.bar.interval, BarInterval::OneSecond);
+        assert_eq!((second.bar.open, second.bar.high, second.bar.low, second.bar.close), (Price::from(528), Price::from(530), Price::from(528), Price::from(530)));
+        assert_eq!(second.bar.volume, 15);
+        let minute = updates.try_recv().unwrap();
+        assert_eq!(minute.bar.interval, BarInterval::OneMinute);
+        assert_eq!(minute.bar.start, 0);
+        assert!(updates.try_recv().is_err());
+
+        assert_eq!(stats.get_current_bar("SPY", BarInterval::FiveMinutes).unwrap().trade_count, 3);
+        assert_eq!(stats.get_bars("SPY", BarInterval::OneSecond).len(), 1);
+
+        // quiet market: bars close on the clock
+        stats.close_bars(61_000);
+        assert_eq!(updates.try_recv().unwrap().bar.start, 60_000);
+        assert!(stats.get_current_bar("SPY", BarInterval::OneSecond).is_none());
+        assert_eq!(stats.get_bars("SPY", BarInterval::OneSecond).len(), 2);
+
+        // only `history` closed bars are kept
+        stats.record_trade("SPY", Price::from(531), 1, 70_000);
+        stats.close_bars(71_000);
+        let closed = stats.get_bars("SPY", BarInterval::OneSecond);
+        assert_eq!(closed.len(), 2);
+        assert_eq!(closed[1].close, 531);
+    }
+
+    #[test]
+    fn test_late_trades_only_reach_the_session(){
+        let mut stats: TradeStats = TradeStats::new(10);
+        let updates = stats.subscribe();
+
+        stats.record_trade("SPY", Price::from(528), 10, 1_500);
+        stats.record_trade("SPY", Price::from(530), 5, 2_200);
+        assert_eq!(updates.try_recv().unwrap().bar.start, 1_000);
+
+        // stamped in the bar that just closed
+        stats.record_trade("SPY", Price::from(520), 1, 1_900);
+        assert!(updates.try_recv().is_err());
+        let current = stats.get_current_bar("SPY", BarInterval::OneSecond).unwrap();
+        assert_eq!((current.start, current.low, current.trade_count), (2_000, Price::from(530), 1));
+        assert_eq!(stats.get_bars("SPY", BarInterval::OneSecond)[0].trade_count, 1);
+
+        // a trade after the clock closed its bar doesn't open it again
+        stats.close_bars(3_000);
+        assert_eq!(updates.try_recv().unwrap().bar.start, 2_000);
+        stats.record_trade("SPY", Price::from(531), 2, 2_800);
+        stats.close_bars(4_000);
+        assert!(updates.try_recv().is_err());
+        assert!(stats.get_current_bar("SPY", BarInterval::OneSecond).is_none());
+        assert_eq!(stats.get_bars("SPY", BarInterval::OneSecond).len(), 2);
+
+        // longer bars still open take them
+        assert_eq!(stats.get_current_bar("SPY", BarInterval::OneMinute).unwrap().trade_count, 4);
+        let session = stats.get_session("SPY").unwrap();
+        assert_eq!((session.low, session.volume, session.trade_count), (Price::from(520), 18, 4));
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::{HashMap, VecDeque};
+use oms::positions::{CostMethod, Position};
+use orderbook::fees::{FeeEngine, FeeSchedule};
+use orderbook::orderbook::{Orderbook, Price, Quantity, Side, Symbol, Trade};
+use orderbook::price::{PriceType, QuantityType};
+use orderbook::stats::Timestamp;
+use crate::replay::MarketEvent;
+use crate::router::{BookRouter, OrderRouter, Reports};
+use crate::runner::StrategyRunner;
+use crate::{ExecutionReport, Fill, OrderId, OrderRequest, Strategy, TopOfBook};
+
+#[derive(Clone, Debug)]
+pub struct BacktestConfig {
+    // how long an order, cancel or replace takes to reach the book, in the
+    // same unit as the event timestamps
+    pub latency: Timestamp,
+    // where the strategy's order ids start; keep it clear of the recorded ones
+    pub first_order_id: OrderId,
+    pub cost_method: CostMethod,
+    // the strategy trades `symbol` as account 0 under this schedule; fills are free without one
+    pub fees: Option<FeeSchedule>,
+    pub symbol: Symbol,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self { latency: 0, first_order_id: 3_000_000_000, cost_method: CostMethod::Fifo, fees: None, symbol: Symbol::new() }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SimulatedFill<P = Price, Q = Quantity> {
+    pub timestamp: Timestamp,
+    pub fill: Fill<P, Q>,
+    // mid when the strategy sent the order, None on a one-sided book
+    pub decision_mid: Option<f64>,
+}
+
+// Book router that holds each request back for the configured latency, and logs
+// every fill with the mid the strategy saw when it decided to send the order.
+#[derive(Debug)]
+pub struct SimulatedRouter<P = Price, Q = Quantity> {
+    inner: BookRouter<P, Q>,
+    latency: Timestamp,
+    now: Timestamp,
+    in_flight: VecDeque<(Timestamp, OrderRequest<P, Q>)>,
+    decision_mids: HashMap<OrderId, Option<f64>>,
+    orders_sent: usize,
+    fills: Vec<SimulatedFill<P, Q>>,
+}
+
+impl<P: PriceType, Q: QuantityType> SimulatedRouter<P, Q> {
+    pub fn new(book: Orderbook<P, Q>, latency: Timestamp) -> Self {
+        Self {
+            inner: BookRouter::new(book),
+            latency,
+            now: 0,
+            in_flight: VecDeque::new(),
+            decision_mids: HashMap::new(),
+            orders_sent: 0,
+            fills: vec![],
+        }
+    }
+
+    pub const fn get_book(&self) -> &Orderbook<P, Q> {
+        self.inner.get_book()
+    }
+
+    pub fn set_fees(&mut self, engine: FeeEngine, symbol: Symbol) {
+        self.inner.set_fees(engine, 0, symbol);
+    }
+
+    pub fn get_fills(&self) -> &[SimulatedFill<P, Q>] {
+        &self.fills
+    }
+
+    pub fn set_time(&mut self, now: Timestamp) {
+        self.now = now;
+    }
+
+    fn deliver(&mut self, request: OrderRequest<P, Q>, reports: &mut Reports<P, Q>) {
+        let start = reports.len();
+        self.inner.route(request, reports);
+        self.log_fills(start, reports);
+    }
+
+    fn log_fills(&mut self, start: usize, reports: &Reports<P, Q>) {
+        for report in reports.range(start..) {
+            if let ExecutionReport::Filled(fill) = report {
+                let decision_mid = self.decision_mids.get(&fill.order_id).copied().flatten();
+                self.fills.push(SimulatedFill { timestamp: self.now, fill: *fill, decision_mid });
+            }
+        }
+    }
+}
+
+impl<P: PriceType, Q: QuantityType> OrderRouter<P, Q> for SimulatedRouter<P, Q> {
+    fn route(&mut self, request: OrderRequest<P, Q>, reports: &mut Reports<P, Q>) {
+        if let OrderRequest::New { order_id, .. } = request {
+            self.orders_sent += 1;
+            self.decision_mids.insert(order_id, self.get_book().mid());
+        }
+        if self.latency == 0 {
+            self.deliver(request, reports);
+        } else {
+            self.in_flight.push_back((self.now + self.latency, request));
+        }
+    }
+
+    // hands the book whatever has arrived by now
+    fn poll(&mut self, reports: &mut Reports<P, Q>) {
+        while let Some((arrival, _)) = self.in_flight.front() {
+            if *arrival > self.now {
+                break;
+            }
+            let (_, request) = self.in_flight.pop_front().unwrap();
+            self.deliver(request, reports);
+        }
+    }
+
+    fn on_market_trades(&mut self, trades: &[Trade<P, Q>], reports: &mut Reports<P, Q>) {
+        let start = reports.len();
+        self.inner.on_market_trades(trades, reports);
+        self.log_fills(start, reports);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BacktestReport<P = Price, Q = Quantity> {
+    pub fills: Vec<SimulatedFill<P, Q>>,
+    pub orders_sent: usize,
+    pub volume: f64,
+    // traded notional, buys and sells
+    pub turnover: f64,
+    // net of rebates, and already taken out of realized_pnl
+    pub fees: f64,
+    pub realized_pnl: f64,
+    // the open position marked to the final mid, or the last trade without one
+    pub unrealized_pnl: f64,
+    pub total_pnl: f64,
+    // largest fall in total P&L from an earlier high
+    pub max_drawdown: f64,
+    pub final_position: f64,
+    // Average cost per unit filled against the mid when the order was sent.
+    // Latency and queueing show up here: positive is worse than the decision mid.
+    pub slippage_per_unit: f64,
+    // total P&L after each event
+    pub equity_curve: Vec<(Timestamp, f64)>,
+}
+
+// Replays recorded events into a book under a simulated clock with one strategy
+// trading against them. Recorded orders are order-by-order, so the strategy's
+// orders queue behind whatever was resting before them and only fill once the
+// orders ahead have traded or cancelled.
+pub struct Backtester<S, P = Price, Q = Quantity> {
+    runner: StrategyRunner<S, SimulatedRouter<P, Q>, P, Q>,
+    position: Position<Q>,
+    // fills already applied to `position`
+    booked: usize,
+    peak: f64,
+    max_drawdown: f64,
+    equity_curve: Vec<(Timestamp, f64)>,
+}
+
+impl<P: PriceType, Q: QuantityType, S: Strategy<P, Q>> Backtester<S, P, Q> {
+    pub fn new(strategy: S, book: Orderbook<P, Q>, config: BacktestConfig) -> Self {
+        let mut router = SimulatedRouter::new(book, config.latency);
+        if let Some(schedule) = config.fees {
+            router.set_fees(FeeEngine::new(schedule), config.symbol);
+        }
+        Self {
+            runner: StrategyRunner::new(strategy, router, config.first_order_id),
+            position: Position::new(config.cost_method),
+            booked: 0,
+            peak: 0.0,
+            max_drawdown: 0.0,
+            equity_curve: vec![],
+        }
+    }
+
+    pub fn get_strategy(&self) -> &S {
+        self.runner.get_strategy()
+    }
+
+    pub fn get_book(&self) -> &Orderbook<P, Q> {
+        self.runner.get_router().get_book()
+    }
+
+    pub fn run(mut self, events: impl IntoIterator<Item = MarketEvent<P, Q>>) -> BacktestReport<P, Q> {
+        let mut started = false;
+        for event in events {
+            if !started {
+                self.runner.start(event.timestamp);
+                started = true;
+            }
+            self.step(event);
+        }
+        self.report()
+    }
+
+    pub fn step(&mut self, event: MarketEvent<P, Q>) {
+        self.advance(event.timestamp);
+        let trades = event.action.apply(self.get_book());
+        if !trades.is_empty() {
+            self.runner.on_trades(&trades);
+        }
+        let top = TopOfBook::of(self.get_book());
+        self.runner.on_book(&top);
+        self.book_fills();
+        self.record_equity(event.timestamp);
+    }
+
+    // Moves the clock to `now`: due timers fire and orders in flight reach the book.
+    fn advance(&mut self, now: Timestamp) {
+        self.runner.get_router_mut().set_time(now);
+        self.runner.set_time(now);
+        self.runner.poll();
+    }
+
+    fn book_fills(&mut self) {
+        let fills = self.runner.get_router().get_fills();
+        for fill in &fills[self.booked..] {
+            self.position.on_fill(fill.fill.side, fill.fill.price.to_f64(), fill.fill.quantity);
+            self.position.charge_fee(fill.fill.fee);
+        }
+        self.booked = fills.len();
+    }
+
+    fn mark(&self) -> Option<f64> {
+        let book = self.get_book();
+        book.mid().or_else(|| book.get_last_trade_price().map(|price| price.to_f64()))
+    }
+
+    fn record_equity(&mut self, now: Timestamp) {
+        let unrealized = self.mark().map_or(0.0, |mark| self.position.unrealized_pnl(mark));
+        let equity = self.position.realized_pnl() + unrealized;
+        self.peak = self.peak.max(equity);
+        self.max_drawdown = self.max_drawdown.max(self.peak - equity);
+        self.equity_curve.push((now, equity));
+    }
+
+    fn report(self) -> BacktestReport<P, Q> {
+        let router = self.runner.get_router();
+        let fills = router.get_fills().to_vec();
+        let volume: f64 = fills.iter().map(|fill| fill.fill.quantity.to_f64()).sum();
+
+        let (mut slippage, mut slipped_volume) = (0.0, 0.0);
+        for fill in &fills {
+            let Some(mid) = fill.decision_mid else { continue };
+            let (price, quantity) = (fill.fill.price.to_f64(), fill.fill.quantity.to_f64());
+            slippage += match fill.fill.side {
+                Side::Buy => (price - mid) * quantity,
+                Side::Sell => (mid - price) * quantity,
+            };
+            slipped_volume += quantity;
+        }
+
+        let unrealized = self.mark().map_or(0.0, |mark| self.position.unrealized_pnl(mark));
+        BacktestReport {
+            orders_sent: router.orders_sent,
+            volume,
+            turnover: self.position.get_traded_notional(),
+            fees: self.position.get_fees(),
+            realized_pnl: self.position.realized_pnl(),
+            unrealized_pnl: unrealized,
+            total_pnl: self.position.realized_pnl() + unrealized,
+            max_drawdown: self.max_drawdown,
+            final_position: self.position.net_quantity(),
+            slippage_per_unit: if slipped_volume > 0.0 { slippage / slipped_volume } else { 0.0 },
+            equity_curve: self.equity_curve,
+            fills,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use orderbook::fees::Liquidity;
+    use orderbook::orderbook::OrderType;
+    use crate::Context;
+
+    // joins the best bid once with `quantity`, then sells it all back at `exit`
+    struct JoinBid {
+        quantity: Quantity,
+        exit: Price,
+        sent: bool,
+    }
+
+    impl Strategy for JoinBid {
+        fn on_book(&mut self, ctx: &mut dyn Context, book: &TopOfBook) {
+            if let (false, Some((price, _))) = (self.sent, book.best_bid) {
+                ctx.send_order(Side::Buy, OrderType::GoodTillCancel, Some(price), self.quantity);
+                self.sent = true;
+            }
+        }
+
+        fn on_fill(&mut self, ctx: &mut dyn Context, fill: &Fill) {
+            if fill.side == Side::Buy && fill.leaves == 0 {
+                ctx.send_order(Side::Sell, OrderType::GoodTillCancel, Some(self.exit), self.quantity);
+            }
+        }
+    }
+
+    fn events(csv: &str) -> Vec<MarketEvent> {
+        crate::replay::read_csv(format!("timestamp,action,order_id,side,price,quantity\n{}", csv).as_bytes()).unwrap()
+    }
+
+    fn run_with(config: BacktestConfig, csv: &str) -> BacktestReport {
+        let strategy = JoinBid { quantity: 5, exit: Price::from(103), sent: false };
+        Backtester::new(strategy, Orderbook::new(BTreeMap::new(), BTreeMap::new()), config).run(events(csv))
+    }
+
+    fn run(latency: Timestamp, csv: &str) -> BacktestReport {
+        run_with(BacktestConfig { latency, ..Default::default() }, csv)
+    }
+
+    #[test]
+    fn test_fills_wait_for_the_queue(){
+        let csv = "1000,add,2,sell,104,10\n\
+                   1000,add,1,buy,100,10\n\
+                   1010,market,3,sell,,8\n\
+                   1020,market,4,sell,,6\n\
+                   1030,cancel,2,,,\n\
+                   1040,add,5,sell,102,1\n";
+        let report = run(0, csv);
+        // the first 8 sold only reach the 10 ahead of us, the next 6 take the last 2 and 4 of ours
+        assert_eq!(report.fills.len(), 1);
+        assert_eq!(report.fills[0].timestamp, 1020);
+        assert_eq!(report.fills[0].fill.quantity, 4);
+        assert_eq!(report.fills[0].fill.leaves, 1);
+        assert_eq!(report.orders_sent, 1);
+        assert_eq!(report.final_position, 4.0);
+        // joined the bid 2 under the 102 mid we decided on
+        assert_eq!(report.slippage_per_unit, -2.0);
+        assert_eq!(report.turnover, 400.0);
+        // marked at 102, then at the 100 last trade with no offer, then at 101
+        let equity: Vec<f64> = report.equity_curve.iter().map(|(_, equity)| *equity).collect();
+        assert_eq!(equity, vec![0.0, 0.0, 0.0, 8.0, 0.0, 4.0]);
+        assert_eq!(report.max_drawdown, 8.0);
+        assert_eq!(report.total_pnl, 4.0);
+
+        // resting earns the maker rebate, which comes back through realized P&L
+        let rebated = run_with(BacktestConfig { fees: Some(FeeSchedule::flat(-0.00390625, 0.01)), ..Default::default() }, csv);
+        assert_eq!(rebated.fills[0].fill.liquidity, Liquidity::Maker);
+        assert_eq!(rebated.fees, -1.5625);
+        assert_eq!(rebated.total_pnl, 4.0 + 1.5625);
+    }
+
+    #[test]
+    fn test_latency_misses_the_fill(){
+        let csv = "1000,add,1,buy,100,5\n\
+                   1000,add,2,buy,99,10\n\
+                   1000,add,3,sell,104,10\n\
+                   1003,market,4,sell,,8\n\
+                   1010,add,5,sell,103,1\n";
+        // instant: we're next behind order 1 when the sell comes in
+        let instant = run(0, csv);
+        assert_eq!(instant.volume, 3.0);
+        assert_eq!(instant.fills[0].fill.price, 100);
+
+        // 5 late: the sell clears 100 and finishes at 99 before we arrive,
+        // and we end up alone on the bid with nothing traded
+        let late = run(5, csv);
+        assert_eq!(late.orders_sent, 1);
+        assert!(late.fills.is_empty());
+        assert_eq!(late.final_position, 0.0);
+    }
+}
@@ -0,0 +1,44 @@
+use std::{collections::BTreeMap, env, fs::File, io::BufReader, process};
+use orderbook::orderbook::Orderbook;
+use strategy::{market_making::MarketMaker, replay, BacktestConfig, Backtester};
+
+// backtest <capture.csv|capture.bin> [latency]
+// Runs the reference market maker over a capture and prints the report.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let Some(path) = args.get(1) else {
+        eprintln!("usage: backtest <capture.csv|capture.bin> [latency]");
+        process::exit(2);
+    };
+    let latency = args.get(2).map_or(0, |latency| latency.parse().expect("latency must be a number"));
+
+    let file = File::open(path).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+    let events = if path.ends_with(".csv") {
+        replay::read_csv(BufReader::new(file))
+    } else {
+        replay::read_binary(&mut BufReader::new(file))
+    };
+    let events = events.unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+
+    let strategy = MarketMaker::new(1.0, 10u64, 100, 0.02);
+    let config = BacktestConfig { latency, ..Default::default() };
+    let report = Backtester::new(strategy, Orderbook::new(BTreeMap::new(), BTreeMap::new()), config).run(events);
+
+    println!("orders sent       {}", report.orders_sent);
+    println!("fills             {}", report.fills.len());
+    println!("volume            {}", report.volume);
+    println!("turnover          {:.2}", report.turnover);
+    println!("fees              {:.2}", report.fees);
+    println!("realized p&l      {:.2}", report.realized_pnl);
+    println!("unrealized p&l    {:.2}", report.unrealized_pnl);
+    println!("total p&l         {:.2}", report.total_pnl);
+    println!("max drawdown      {:.2}", report.max_drawdown);
+    println!("final position    {}", report.final_position);
+    println!("slippage per unit {:.4}", report.slippage_per_unit);
+}
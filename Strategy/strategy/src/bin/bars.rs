@@ -0,0 +1,71 @@
+use std::{collections::BTreeMap, env, fs::File, io::BufReader, process, thread, time::Duration};
+use orderbook::orderbook::Orderbook;
+use orderbook::stats::{BarInterval, BarUpdate, Timestamp, TradeStats};
+use strategy::replay;
+
+// bars <capture.csv|capture.bin> <symbol> [1s|1m|5m] [speed]
+// Replays a capture through a book and prints each bar TradeStats closes as one JSON
+// line, the price_update feed the Monitoring UI reads. A speed paces the replay at
+// that multiple of the capture's clock; without one it runs flat out.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (Some(path), Some(symbol)) = (args.get(1), args.get(2)) else {
+        eprintln!("usage: bars <capture.csv|capture.bin> <symbol> [1s|1m|5m] [speed]");
+        process::exit(2);
+    };
+    let interval = match args.get(3).map(String::as_str) {
+        None | Some("1s") => BarInterval::OneSecond,
+        Some("1m") => BarInterval::OneMinute,
+        Some("5m") => BarInterval::FiveMinutes,
+        Some(other) => {
+            eprintln!("unknown interval {:?}", other);
+            process::exit(2);
+        }
+    };
+    let speed: f64 = args.get(4).map_or(0.0, |speed| speed.parse().expect("speed must be a number"));
+
+    let file = File::open(path).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+    let events = if path.ends_with(".csv") {
+        replay::read_csv(BufReader::new(file))
+    } else {
+        replay::read_binary(&mut BufReader::new(file))
+    };
+    let events = events.unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    });
+
+    let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+    // bars only go out on the feed, so none are kept
+    let mut stats: TradeStats = TradeStats::new(0);
+    let updates = stats.subscribe();
+    let mut clock: Option<Timestamp> = None;
+    for event in events {
+        if let Some(last) = clock.filter(|last| speed > 0.0 && event.timestamp > *last) {
+            thread::sleep(Duration::from_secs_f64((event.timestamp - last) as f64 / 1_000.0 / speed));
+        }
+        clock = Some(event.timestamp);
+        stats.close_bars(event.timestamp);
+        let trades = event.action.apply(&book);
+        stats.record_trades(symbol, &trades, event.timestamp);
+        for update in updates.try_iter().filter(|update| update.bar.interval == interval) {
+            print_bar(&update);
+        }
+    }
+    stats.close_bars(Timestamp::MAX);
+    for update in updates.try_iter().filter(|update| update.bar.interval == interval) {
+        print_bar(&update);
+    }
+}
+
+// old_price/new_price are the bar's open and close, as the panel shows them
+fn print_bar(update: &BarUpdate) {
+    let bar = &update.bar;
+    println!(
+        "{{\"type\":\"price_update\",\"symbol\":{:?},\"start\":{},\"open\":{},\"high\":{},\"low\":{},\"close\":{},\"volume\":{},\"vwap\":{},\"old_price\":{},\"new_price\":{}}}",
+        update.symbol, bar.start, bar.open, bar.high, bar.low, bar.close, bar.volume, bar.vwap(), bar.open, bar.close,
+    );
+}
@@ -0,0 +1,451 @@
+use orderbook::orderbook::{OrderType, Price, Quantity, Side};
+use orderbook::price::{PriceType, QuantityType};
+use orderbook::stats::Timestamp;
+use crate::{Context, Fill, MarketTrade, OrderId, RejectReason, Strategy, TopOfBook};
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Schedule {
+    // equal slices, one at the start of each of `slices` intervals across the window
+    Twap { slices: u32 },
+    // the window split into equal buckets, each taking its share of the curve
+    Vwap { curve: Vec<f64> },
+    // a `rate` share of the volume printed since the start, topped up every `interval`
+    Pov { rate: f64, interval: Timestamp },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChildStyle {
+    // immediate orders that take from the far touch
+    Aggressive,
+    // joins the near touch; what hasn't filled is pulled and re-sliced next interval
+    Passive,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ParentOrder<P = Price, Q = Quantity> {
+    pub side: Side,
+    pub quantity: Q,
+    // no child is priced through this
+    pub limit: Option<P>,
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParentState {
+    Working,
+    Paused,
+    Completed,
+    Cancelled,
+    // the window ended before the parent filled
+    Expired,
+}
+
+impl ParentState {
+    pub const fn is_terminal(&self) -> bool {
+        matches!(self, ParentState::Completed | ParentState::Cancelled | ParentState::Expired)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChildOrder<P = Price, Q = Quantity> {
+    pub order_id: OrderId,
+    pub price: P,
+    pub quantity: Q,
+    pub filled: Q,
+    pub sent_at: Timestamp,
+    // still working at the destination
+    pub open: bool,
+}
+
+// Works a parent order by slicing it into child orders on a timer. Each interval
+// it works out how much the schedule says should be done by now and sends a
+// child for whatever isn't already filled or working. Child fills roll up into
+// the parent; pausing or cancelling the parent cancels its working children.
+//
+// It's a `Strategy`, so it runs under a `StrategyRunner` with the router of your
+// choice and on whichever clock drives the runner: a backtest's or the wall
+// clock's. Use `StrategyRunner::control` to pause, resume or cancel it.
+#[derive(Debug)]
+pub struct ExecutionAlgo<P = Price, Q = Quantity> {
+    parent: ParentOrder<P, Q>,
+    schedule: Schedule,
+    style: ChildStyle,
+    state: ParentState,
+    filled: Q,
+    notional: f64,
+    // net of rebates
+    fees: f64,
+    children: Vec<ChildOrder<P, Q>>,
+    touch: TopOfBook<P, Q>,
+    // printed since the start, as seen through on_trade
+    market_volume: Q,
+}
+
+impl<P: PriceType, Q: QuantityType> ExecutionAlgo<P, Q> {
+    pub fn new(parent: ParentOrder<P, Q>, schedule: Schedule, style: ChildStyle) -> Self {
+        Self {
+            parent,
+            schedule,
+            style,
+            state: ParentState::Working,
+            filled: Q::zero(),
+            notional: 0.0,
+            fees: 0.0,
+            children: vec![],
+            touch: TopOfBook { best_bid: None, best_ask: None },
+            market_volume: Q::zero(),
+        }
+    }
+
+    pub const fn get_parent(&self) -> &ParentOrder<P, Q> {
+        &self.parent
+    }
+    pub const fn get_state(&self) -> ParentState {
+        self.state
+    }
+    pub const fn get_filled(&self) -> Q {
+        self.filled
+    }
+    pub fn get_leaves(&self) -> Q {
+        self.parent.quantity - self.filled
+    }
+    pub const fn get_fees(&self) -> f64 {
+        self.fees
+    }
+    pub fn get_children(&self) -> &[ChildOrder<P, Q>] {
+        &self.children
+    }
+
+    pub fn average_price(&self) -> Option<f64> {
+        if self.filled == Q::zero() {
+            return None;
+        }
+        Some(self.notional / self.filled.to_f64())
+    }
+
+    pub fn pause(&mut self, ctx: &mut dyn Context<P, Q>) {
+        if self.state == ParentState::Working {
+            self.state = ParentState::Paused;
+            self.cancel_children(ctx);
+        }
+    }
+
+    // picks up where the schedule is now, so time spent paused is caught up straight away
+    pub fn resume(&mut self, ctx: &mut dyn Context<P, Q>) {
+        if self.state == ParentState::Paused {
+            self.state = ParentState::Working;
+            let now = ctx.now();
+            self.slice(ctx, now);
+        }
+    }
+
+    pub fn cancel(&mut self, ctx: &mut dyn Context<P, Q>) {
+        if !self.state.is_terminal() {
+            self.state = ParentState::Cancelled;
+            self.cancel_children(ctx);
+        }
+    }
+
+    fn cancel_children(&mut self, ctx: &mut dyn Context<P, Q>) {
+        for child in self.children.iter().filter(|child| child.open) {
+            ctx.cancel_order(child.order_id);
+        }
+    }
+
+    fn working_quantity(&self) -> Q {
+        self.children.iter().filter(|child| child.open).map(|child| child.quantity - child.filled).sum()
+    }
+
+    fn interval(&self) -> Timestamp {
+        let window = self.parent.end.saturating_sub(self.parent.start);
+        let interval = match &self.schedule {
+            Schedule::Twap { slices } => window / (*slices).max(1) as Timestamp,
+            Schedule::Vwap { curve } => window / curve.len().max(1) as Timestamp,
+            Schedule::Pov { interval, .. } => *interval,
+        };
+        interval.max(1)
+    }
+
+    // how much of the parent should be done by `now`
+    fn target(&self, now: Timestamp) -> Q {
+        let quantity = self.parent.quantity.to_f64();
+        let window = self.parent.end.saturating_sub(self.parent.start);
+        let elapsed = now.saturating_sub(self.parent.start);
+        let done = now >= self.parent.end || window == 0;
+        let target = match &self.schedule {
+            Schedule::Twap { .. } | Schedule::Vwap { .. } if done => quantity,
+            Schedule::Twap { slices } => {
+                let slices = (*slices).max(1) as u64;
+                let slice = (elapsed * slices / window + 1).min(slices);
+                quantity * slice as f64 / slices as f64
+            }
+            Schedule::Vwap { curve } => {
+                let buckets = curve.len().max(1);
+                let bucket = ((elapsed as usize * buckets) / window as usize).min(buckets - 1);
+                let total: f64 = curve.iter().sum();
+                if total > 0.0 {
+                    quantity * curve.iter().take(bucket + 1).sum::<f64>() / total
+                } else {
+                    quantity
+                }
+            }
+            Schedule::Pov { rate, .. } => self.market_volume.to_f64() * rate,
+        };
+        Q::from_f64(target.min(quantity).floor())
+    }
+
+    fn child_price(&self) -> Option<P> {
+        let (near, far) = match self.parent.side {
+            Side::Buy => (self.touch.best_bid, self.touch.best_ask),
+            Side::Sell => (self.touch.best_ask, self.touch.best_bid),
+        };
+        let touch = match self.style {
+            ChildStyle::Aggressive => far,
+            ChildStyle::Passive => near,
+        };
+        match (touch.map(|(price, _)| price), self.parent.limit) {
+            (Some(price), Some(limit)) => Some(match self.parent.side {
+                Side::Buy => price.min(limit),
+                Side::Sell => price.max(limit),
+            }),
+            (price, limit) => price.or(limit),
+        }
+    }
+
+    fn slice(&mut self, ctx: &mut dyn Context<P, Q>, now: Timestamp) {
+        if self.state != ParentState::Working {
+            return;
+        }
+        // nothing is left resting past the end of the window
+        if self.style == ChildStyle::Passive && now >= self.parent.end {
+            return;
+        }
+        let target = self.target(now);
+        let committed = self.filled + self.working_quantity();
+        if target <= committed {
+            return;
+        }
+        let Some(price) = self.child_price() else { return };
+        let quantity = target - committed;
+        let order_type = match self.style {
+            ChildStyle::Aggressive => OrderType::FillAndKill,
+            ChildStyle::Passive => OrderType::GoodTillCancel,
+        };
+        // reports on the child are only dispatched once this callback returns
+        let order_id = ctx.send_order(self.parent.side, order_type, Some(price), quantity);
+        self.children.push(ChildOrder { order_id, price, quantity, filled: Q::zero(), sent_at: now, open: true });
+    }
+
+    fn settle(&mut self, now: Timestamp) {
+        if self.state.is_terminal() {
+            return;
+        }
+        if self.filled >= self.parent.quantity {
+            self.state = ParentState::Completed;
+        } else if now >= self.parent.end && self.working_quantity() == Q::zero() {
+            self.state = ParentState::Expired;
+        }
+    }
+
+    fn child(&mut self, order_id: OrderId) -> Option<&mut ChildOrder<P, Q>> {
+        self.children.iter_mut().find(|child| child.order_id == order_id)
+    }
+
+    fn close_child(&mut self, ctx: &mut dyn Context<P, Q>, order_id: OrderId) {
+        if let Some(child) = self.child(order_id) {
+            child.open = false;
+            self.settle(ctx.now());
+        }
+    }
+}
+
+impl<P: PriceType, Q: QuantityType> Strategy<P, Q> for ExecutionAlgo<P, Q> {
+    fn on_start(&mut self, ctx: &mut dyn Context<P, Q>) {
+        ctx.set_timer(ctx.now().max(self.parent.start));
+    }
+
+    fn on_book(&mut self, _ctx: &mut dyn Context<P, Q>, book: &TopOfBook<P, Q>) {
+        self.touch = *book;
+    }
+
+    fn on_trade(&mut self, ctx: &mut dyn Context<P, Q>, trade: &MarketTrade<P, Q>) {
+        // our own fills aren't market volume, or a POV target would chase itself
+        let own = self.children.iter().any(|child| child.order_id == trade.bid_order_id || child.order_id == trade.ask_order_id);
+        if !own && ctx.now() >= self.parent.start {
+            self.market_volume += trade.quantity;
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut dyn Context<P, Q>, now: Timestamp) {
+        if self.state.is_terminal() {
+            return;
+        }
+        // passive children had their interval; what's left of them is re-sliced once the cancels land
+        if self.style == ChildStyle::Passive && self.state == ParentState::Working {
+            self.cancel_children(ctx);
+        }
+        self.slice(ctx, now);
+        self.settle(now);
+        if now < self.parent.end && !self.state.is_terminal() {
+            ctx.set_timer((now + self.interval()).min(self.parent.end));
+        }
+    }
+
+    fn on_fill(&mut self, ctx: &mut dyn Context<P, Q>, fill: &Fill<P, Q>) {
+        let Some(child) = self.child(fill.order_id) else { return };
+        child.filled += fill.quantity;
+        child.open = fill.leaves > Q::zero();
+        self.filled += fill.quantity;
+        self.notional += fill.price.to_f64() * fill.quantity.to_f64();
+        self.fees += fill.fee;
+        self.settle(ctx.now());
+    }
+
+    fn on_cancel(&mut self, ctx: &mut dyn Context<P, Q>, order_id: OrderId) {
+        self.close_child(ctx, order_id);
+        // a passive child pulled at the interval is replaced straight away
+        if self.style == ChildStyle::Passive && self.working_quantity() == Q::zero() {
+            let now = ctx.now();
+            self.slice(ctx, now);
+        }
+    }
+
+    fn on_reject(&mut self, ctx: &mut dyn Context<P, Q>, order_id: OrderId, _reason: RejectReason) {
+        self.close_child(ctx, order_id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use orderbook::orderbook::{Order, Orderbook};
+    use crate::router::BookRouter;
+    use crate::runner::StrategyRunner;
+
+    type Runner = StrategyRunner<ExecutionAlgo, BookRouter>;
+
+    fn runner(parent: ParentOrder, schedule: Schedule, style: ChildStyle, orders: &[(u32, Side, i64, Quantity)]) -> Runner {
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        for &(order_id, side, price, quantity) in orders {
+            book.add_order(Order::new(OrderType::GoodTillCancel, order_id, side, price, quantity));
+        }
+        let mut runner = StrategyRunner::new(ExecutionAlgo::new(parent, schedule, style), BookRouter::new(book), 1_000);
+        runner.start(0);
+        refresh(&mut runner);
+        runner
+    }
+
+    fn refresh(runner: &mut Runner) {
+        let top = TopOfBook::of(runner.get_router().get_book());
+        runner.on_book(&top);
+    }
+
+    #[test]
+    fn test_twap_slices_and_pauses(){
+        let parent = ParentOrder { side: Side::Buy, quantity: 30, limit: Some(Price::from(102)), start: 0, end: 3_000 };
+        let mut runner = runner(parent, Schedule::Twap { slices: 3 }, ChildStyle::Aggressive, &[(1, Side::Sell, 101, 100)]);
+
+        runner.set_time(0);
+        assert_eq!(runner.get_strategy().get_filled(), 10);
+
+        // paused through the second slice, which is caught up on resume
+        runner.set_time(500);
+        runner.control(|algo, ctx| algo.pause(ctx));
+        runner.set_time(1_200);
+        assert_eq!(runner.get_strategy().get_filled(), 10);
+        runner.control(|algo, ctx| algo.resume(ctx));
+        assert_eq!(runner.get_strategy().get_filled(), 20);
+
+        runner.control(|algo, ctx| algo.cancel(ctx));
+        runner.set_time(3_000);
+        let algo = runner.get_strategy();
+        assert_eq!(algo.get_state(), ParentState::Cancelled);
+        assert_eq!((algo.get_filled(), algo.get_leaves()), (20, 10));
+        assert_eq!(algo.get_children().len(), 2);
+        assert_eq!(algo.average_price(), Some(101.0));
+        assert_eq!(runner.get_router().get_book().depth_at(Side::Sell, Price::from(101)), 80);
+    }
+
+    #[test]
+    fn test_vwap_passive_children(){
+        let parent = ParentOrder { side: Side::Buy, quantity: 10, limit: None, start: 0, end: 2_000 };
+        let schedule = Schedule::Vwap { curve: vec![1.0, 3.0] };
+        let mut runner = runner(parent, schedule, ChildStyle::Passive, &[(1, Side::Buy, 99, 5), (2, Side::Sell, 101, 10)]);
+
+        // a quarter of 10, rounded down, joins the bid
+        runner.set_time(0);
+        assert_eq!(runner.get_router().get_book().depth_at(Side::Buy, Price::from(99)), 7);
+        // pausing pulls it
+        runner.control(|algo, ctx| algo.pause(ctx));
+        assert_eq!(runner.get_router().get_book().depth_at(Side::Buy, Price::from(99)), 5);
+        assert!(!runner.get_strategy().get_children()[0].open);
+
+        // second bucket: everything is due
+        runner.set_time(1_000);
+        runner.control(|algo, ctx| algo.resume(ctx));
+        assert_eq!(runner.get_router().get_book().depth_at(Side::Buy, Price::from(99)), 15);
+
+        // 12 sold at 99 fills the 5 ahead of us and 7 of ours
+        let trades = runner.get_router().get_book().add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 99, 12));
+        runner.on_trades(&trades);
+        assert_eq!(runner.get_strategy().get_filled(), 7);
+
+        // the rest is pulled at the end of the window
+        runner.set_time(2_000);
+        let algo = runner.get_strategy();
+        assert_eq!(algo.get_state(), ParentState::Expired);
+        assert_eq!(algo.average_price(), Some(99.0));
+        assert_eq!(runner.get_router().get_book().depth_at(Side::Buy, Price::from(99)), 0);
+    }
+
+    #[test]
+    fn test_pov_follows_volume(){
+        let parent = ParentOrder { side: Side::Sell, quantity: 50, limit: Some(Price::from(95)), start: 0, end: 10_000 };
+        let schedule = Schedule::Pov { rate: 0.25, interval: 100 };
+        let mut runner = runner(parent, schedule, ChildStyle::Aggressive, &[(1, Side::Buy, 98, 100)]);
+        runner.set_time(50);
+
+        // 40 trades in the market: we owe 10 at the next check
+        let book = runner.get_router().get_book();
+        book.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 99, 40));
+        let trades = book.add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Buy, 99, 40));
+        runner.on_trades(&trades);
+        refresh(&mut runner);
+        runner.set_time(99);
+        assert_eq!(runner.get_strategy().get_filled(), 0);
+        runner.set_time(100);
+        assert_eq!(runner.get_strategy().get_filled(), 10);
+        assert_eq!(runner.get_strategy().get_children()[0].price, 98);
+        assert_eq!(runner.get_strategy().get_state(), ParentState::Working);
+    }
+
+    #[test]
+    fn test_pov_leaves_out_its_own_fills(){
+        let parent = ParentOrder { side: Side::Sell, quantity: 50, limit: None, start: 0, end: 10_000 };
+        let schedule = Schedule::Pov { rate: 0.25, interval: 100 };
+        let mut runner = runner(parent, schedule, ChildStyle::Passive, &[(1, Side::Buy, 98, 100), (2, Side::Sell, 101, 5), (3, Side::Sell, 102, 50)]);
+        runner.set_time(50);
+
+        let book = runner.get_router().get_book();
+        book.add_order(Order::new(OrderType::GoodTillCancel, 4, Side::Sell, 99, 40));
+        let trades = book.add_order(Order::new(OrderType::GoodTillCancel, 5, Side::Buy, 99, 40));
+        runner.on_trades(&trades);
+        refresh(&mut runner);
+        runner.set_time(100);
+        assert_eq!(runner.get_strategy().get_children()[0].quantity, 10);
+
+        // 5 of the 15 bought are someone else's, 10 are our child's
+        let trades = runner.get_router().get_book().add_order(Order::new(OrderType::GoodTillCancel, 6, Side::Buy, 101, 15));
+        runner.on_trades(&trades);
+        refresh(&mut runner);
+        assert_eq!(runner.get_strategy().get_filled(), 10);
+
+        // a quarter of the 45 others traded is 11, so one more is due
+        runner.set_time(200);
+        let children = runner.get_strategy().get_children();
+        assert_eq!(children.len(), 2);
+        assert_eq!((children[1].price, children[1].quantity), (Price::from(102), 1));
+    }
+}
@@ -0,0 +1,118 @@
+// Event driven strategy framework. A `Strategy` reacts to market data, to reports
+// on its own orders and to timers, and acts only through a `Context`. The runner
+// hands it a context backed by an `OrderRouter`: a local `Orderbook` in simulation
+// or a gateway when live, so the same strategy code runs in both.
+pub mod backtest;
+pub mod execution;
+pub mod market_making;
+pub mod momentum;
+pub mod replay;
+pub mod router;
+pub mod runner;
+pub mod sor;
+
+use orderbook::fees::Liquidity;
+use orderbook::orderbook::{ModifyReject, OrderType, Orderbook, Price, Quantity, Side};
+use orderbook::price::{PriceType, QuantityType};
+use orderbook::stats::Timestamp;
+pub use backtest::{BacktestConfig, BacktestReport, Backtester};
+pub use execution::{ExecutionAlgo, ParentOrder, Schedule};
+pub use router::{BookRouter, GatewayRouter, OrderRouter};
+pub use runner::StrategyRunner;
+pub use sor::{RouteMode, SmartOrderRouter};
+
+pub type OrderId = u32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TopOfBook<P = Price, Q = Quantity> {
+    pub best_bid: Option<(P, Q)>,
+    pub best_ask: Option<(P, Q)>,
+}
+
+impl<P: PriceType, Q: QuantityType> TopOfBook<P, Q> {
+    pub fn of(book: &Orderbook<P, Q>) -> Self {
+        let (best_bid, best_ask) = book.top_of_book();
+        Self { best_bid, best_ask }
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        let (bid, _) = self.best_bid?;
+        let (ask, _) = self.best_ask?;
+        Some((bid.to_f64() + ask.to_f64()) / 2.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MarketTrade<P = Price, Q = Quantity> {
+    pub price: P,
+    pub quantity: Q,
+    // the orders on each side, so a strategy can tell its own fills apart
+    pub bid_order_id: OrderId,
+    pub ask_order_id: OrderId,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OrderRequest<P = Price, Q = Quantity> {
+    // price is None for market orders
+    New { order_id: OrderId, side: Side, order_type: OrderType, price: Option<P>, quantity: Q },
+    Cancel { order_id: OrderId },
+    // quantity is the new total size, fills so far included
+    Replace { order_id: OrderId, side: Side, price: P, quantity: Q },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RejectReason {
+    // the book or venue wouldn't take the order
+    Refused,
+    Modify(ModifyReject),
+    UnknownOrder,
+    // the gateway has gone away
+    Disconnected,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Fill<P = Price, Q = Quantity> {
+    pub order_id: OrderId,
+    pub side: Side,
+    pub price: P,
+    pub quantity: Q,
+    // what's left of the order after this fill
+    pub leaves: Q,
+    pub liquidity: Liquidity,
+    // what the venue charged for it, negative for a rebate
+    pub fee: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExecutionReport<P = Price, Q = Quantity> {
+    Acked(OrderId),
+    Replaced(OrderId),
+    Filled(Fill<P, Q>),
+    // cancelled on request, or the unfilled rest of an immediate order
+    Cancelled(OrderId),
+    Rejected(OrderId, RejectReason),
+}
+
+// Everything a strategy can do. Orders get their id straight away; whether the
+// book took them comes back later as a report.
+pub trait Context<P = Price, Q = Quantity> {
+    fn now(&self) -> Timestamp;
+    fn send_order(&mut self, side: Side, order_type: OrderType, price: Option<P>, quantity: Q) -> OrderId;
+    fn cancel_order(&mut self, order_id: OrderId);
+    fn replace_order(&mut self, order_id: OrderId, side: Side, price: P, quantity: Q);
+    // calls on_timer once the clock reaches `at`
+    fn set_timer(&mut self, at: Timestamp);
+}
+
+// Callbacks default to doing nothing, so a strategy only implements what it uses.
+pub trait Strategy<P = Price, Q = Quantity> {
+    fn on_start(&mut self, _ctx: &mut dyn Context<P, Q>) {}
+    fn on_book(&mut self, _ctx: &mut dyn Context<P, Q>, _book: &TopOfBook<P, Q>) {}
+    fn on_trade(&mut self, _ctx: &mut dyn Context<P, Q>, _trade: &MarketTrade<P, Q>) {}
+    // a new order or a replace was accepted
+    fn on_ack(&mut self, _ctx: &mut dyn Context<P, Q>, _order_id: OrderId) {}
+    fn on_fill(&mut self, _ctx: &mut dyn Context<P, Q>, _fill: &Fill<P, Q>) {}
+    fn on_cancel(&mut self, _ctx: &mut dyn Context<P, Q>, _order_id: OrderId) {}
+    fn on_reject(&mut self, _ctx: &mut dyn Context<P, Q>, _order_id: OrderId, _reason: RejectReason) {}
+    fn on_timer(&mut self, _ctx: &mut dyn Context<P, Q>, _now: Timestamp) {}
+}
@@ -0,0 +1,117 @@
+use orderbook::orderbook::{OrderType, Price, Quantity, Side};
+use orderbook::price::{PriceType, QuantityType};
+use crate::{Context, Fill, OrderId, RejectReason, Strategy, TopOfBook};
+
+#[derive(Clone, Copy, Debug)]
+struct Working<P> {
+    order_id: OrderId,
+    price: P,
+}
+
+// Reference market maker: quotes `size` on both sides `half_spread` away from
+// the mid, leaning both quotes against its inventory by `skew` per unit held, and
+// stops quoting the side that would take it past `max_position`.
+#[derive(Debug)]
+pub struct MarketMaker<P = Price, Q = Quantity> {
+    half_spread: f64,
+    size: Q,
+    max_position: Q,
+    skew: f64,
+    // long positive
+    position: f64,
+    bid: Option<Working<P>>,
+    ask: Option<Working<P>>,
+}
+
+impl<P: PriceType, Q: QuantityType> MarketMaker<P, Q> {
+    pub fn new(half_spread: f64, size: Q, max_position: Q, skew: f64) -> Self {
+        Self {
+            half_spread,
+            size,
+            max_position,
+            skew,
+            position: 0.0,
+            bid: None,
+            ask: None,
+        }
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    // prices of the working bid and ask
+    pub fn quotes(&self) -> (Option<P>, Option<P>) {
+        (self.bid.map(|bid| bid.price), self.ask.map(|ask| ask.price))
+    }
+
+    fn working(&mut self, side: Side) -> &mut Option<Working<P>> {
+        match side {
+            Side::Buy => &mut self.bid,
+            Side::Sell => &mut self.ask,
+        }
+    }
+
+    // moves the quote on `side` to `price`, or pulls it when `price` is None
+    fn quote(&mut self, ctx: &mut dyn Context<P, Q>, side: Side, price: Option<P>) {
+        let size = self.size;
+        let working = self.working(side);
+        match (*working, price) {
+            (Some(current), Some(price)) if current.price == price => {}
+            (Some(current), Some(price)) => {
+                *working = Some(Working { order_id: current.order_id, price });
+                ctx.replace_order(current.order_id, side, price, size);
+            }
+            (Some(current), None) => {
+                *working = None;
+                ctx.cancel_order(current.order_id);
+            }
+            (None, Some(price)) => {
+                // reports on the new order are handled once this callback returns
+                let order_id = ctx.send_order(side, OrderType::GoodTillCancel, Some(price), size);
+                *working = Some(Working { order_id, price });
+            }
+            (None, None) => {}
+        }
+    }
+
+    fn forget(&mut self, order_id: OrderId) {
+        for side in [Side::Buy, Side::Sell] {
+            if self.working(side).is_some_and(|working| working.order_id == order_id) {
+                *self.working(side) = None;
+            }
+        }
+    }
+}
+
+impl<P: PriceType, Q: QuantityType> Strategy<P, Q> for MarketMaker<P, Q> {
+    fn on_book(&mut self, ctx: &mut dyn Context<P, Q>, book: &TopOfBook<P, Q>) {
+        let Some(mid) = book.mid() else { return };
+        let fair = mid - self.skew * self.position;
+        let limit = self.max_position.to_f64();
+        let size = self.size.to_f64();
+
+        let bid = (self.position + size <= limit).then(|| P::from_f64(fair - self.half_spread));
+        let ask = (-self.position + size <= limit).then(|| P::from_f64(fair + self.half_spread));
+        self.quote(ctx, Side::Buy, bid);
+        self.quote(ctx, Side::Sell, ask);
+    }
+
+    fn on_fill(&mut self, _ctx: &mut dyn Context<P, Q>, fill: &Fill<P, Q>) {
+        match fill.side {
+            Side::Buy => self.position += fill.quantity.to_f64(),
+            Side::Sell => self.position -= fill.quantity.to_f64(),
+        }
+        if fill.leaves == Q::zero() {
+            self.forget(fill.order_id);
+        }
+    }
+
+    fn on_cancel(&mut self, _ctx: &mut dyn Context<P, Q>, order_id: OrderId) {
+        self.forget(order_id);
+    }
+
+    fn on_reject(&mut self, _ctx: &mut dyn Context<P, Q>, order_id: OrderId, _reason: RejectReason) {
+        self.forget(order_id);
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+use orderbook::orderbook::{OrderType, Quantity, Side};
+use orderbook::price::{PriceType, QuantityType};
+use orderbook::stats::Timestamp;
+use crate::{Context, Fill, MarketTrade, OrderId, RejectReason, Strategy};
+
+// Reference momentum strategy: every `interval` ms it compares a fast and a slow
+// moving average of trade prices and, when they're more than `threshold` apart
+// (as a fraction of the slow one), takes a position of `size` in that direction
+// with a market order. Back inside the threshold it goes flat.
+#[derive(Debug)]
+pub struct Momentum<Q = Quantity> {
+    fast: usize,
+    slow: usize,
+    threshold: f64,
+    size: Q,
+    interval: Timestamp,
+    // most recent last, at most `slow` of them
+    prices: VecDeque<f64>,
+    // long positive
+    position: f64,
+    working: Option<OrderId>,
+}
+
+impl<Q: QuantityType> Momentum<Q> {
+    pub fn new(fast: usize, slow: usize, threshold: f64, size: Q, interval: Timestamp) -> Self {
+        Self {
+            fast,
+            slow: slow.max(fast),
+            threshold,
+            size,
+            interval,
+            prices: VecDeque::new(),
+            position: 0.0,
+            working: None,
+        }
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    fn average(&self, count: usize) -> f64 {
+        self.prices.iter().rev().take(count).sum::<f64>() / count as f64
+    }
+
+    // -1, 0 or 1 times `size`, once there's enough history
+    fn target(&self) -> Option<f64> {
+        if self.prices.len() < self.slow {
+            return None;
+        }
+        let (fast, slow) = (self.average(self.fast), self.average(self.slow));
+        let signal = (fast - slow) / slow;
+        let size = self.size.to_f64();
+        Some(if signal > self.threshold {
+            size
+        } else if signal < -self.threshold {
+            -size
+        } else {
+            0.0
+        })
+    }
+}
+
+impl<P: PriceType, Q: QuantityType> Strategy<P, Q> for Momentum<Q> {
+    fn on_start(&mut self, ctx: &mut dyn Context<P, Q>) {
+        ctx.set_timer(ctx.now() + self.interval);
+    }
+
+    fn on_trade(&mut self, _ctx: &mut dyn Context<P, Q>, trade: &MarketTrade<P, Q>) {
+        self.prices.push_back(trade.price.to_f64());
+        if self.prices.len() > self.slow {
+            self.prices.pop_front();
+        }
+    }
+
+    fn on_timer(&mut self, ctx: &mut dyn Context<P, Q>, now: Timestamp) {
+        ctx.set_timer(now + self.interval);
+        // one order at a time
+        if self.working.is_some() {
+            return;
+        }
+        let Some(target) = self.target() else { return };
+        let difference = target - self.position;
+        if difference == 0.0 {
+            return;
+        }
+        let side = if difference > 0.0 { Side::Buy } else { Side::Sell };
+        self.working = Some(ctx.send_order(side, OrderType::Market, None, Q::from_f64(difference.abs())));
+    }
+
+    fn on_fill(&mut self, _ctx: &mut dyn Context<P, Q>, fill: &Fill<P, Q>) {
+        match fill.side {
+            Side::Buy => self.position += fill.quantity.to_f64(),
+            Side::Sell => self.position -= fill.quantity.to_f64(),
+        }
+        if fill.leaves == Q::zero() && self.working == Some(fill.order_id) {
+            self.working = None;
+        }
+    }
+
+    fn on_cancel(&mut self, _ctx: &mut dyn Context<P, Q>, order_id: OrderId) {
+        if self.working == Some(order_id) {
+            self.working = None;
+        }
+    }
+
+    fn on_reject(&mut self, _ctx: &mut dyn Context<P, Q>, order_id: OrderId, _reason: RejectReason) {
+        if self.working == Some(order_id) {
+            self.working = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use orderbook::orderbook::{Order, Orderbook, Price};
+    use crate::router::BookRouter;
+    use crate::runner::StrategyRunner;
+
+    #[test]
+    fn test_momentum_follows_the_trend(){
+        let router = BookRouter::new(Orderbook::new(BTreeMap::new(), BTreeMap::new()));
+        let mut runner = StrategyRunner::new(Momentum::new(2, 4, 0.01, 10u64, 1_000), router, 1_000);
+        runner.start(0);
+
+        // rising prints: 100, 101, 103, 106
+        let mut order_id = 1;
+        for price in [100, 101, 103, 106] {
+            let book = runner.get_router().get_book();
+            book.add_order(Order::new(OrderType::GoodTillCancel, order_id, Side::Buy, price, 1));
+            let trades = book.add_order(Order::new(OrderType::GoodTillCancel, order_id + 1, Side::Sell, price, 1));
+            runner.on_trades(&trades);
+            order_id += 2;
+        }
+        // liquidity for the strategy to buy from
+        runner.get_router().get_book().add_order(Order::new(OrderType::GoodTillCancel, 50, Side::Sell, 107, 20));
+
+        // nothing happens before the timer
+        runner.set_time(999);
+        assert_eq!(runner.get_strategy().position(), 0.0);
+        // fast average 104.5 is 2.2% over the slow 102.5
+        runner.set_time(1_000);
+        assert_eq!(runner.get_strategy().position(), 10.0);
+        // still trending, already long: nothing more to do
+        runner.set_time(2_000);
+        assert_eq!(runner.get_strategy().position(), 10.0);
+        assert_eq!(runner.get_router().get_book().best_ask(), Some(Price::from(107)));
+        assert_eq!(runner.get_router().get_book().depth_at(Side::Sell, Price::from(107)), 10);
+    }
+}
@@ -0,0 +1,211 @@
+// Recorded order-by-order market data. CSV has a header and one event per line:
+//
+//   timestamp,action,order_id,side,price,quantity
+//   1000,add,1,buy,100,10
+//   1005,modify,1,buy,101,10
+//   1010,market,2,sell,,5
+//   1020,cancel,1,,,
+//
+// The binary capture holds the same events as fixed 32 byte little-endian records
+// for the default price and quantity types.
+use std::{
+    fmt::Debug,
+    io::{self, BufRead, Read, Write},
+    str::FromStr,
+};
+use orderbook::orderbook::{ModifyOutcome, Order, OrderModify, OrderType, Orderbook, Price, Quantity, Side, Trade};
+use orderbook::price::{PriceType, QuantityType};
+use orderbook::stats::Timestamp;
+use crate::OrderId;
+
+pub const BINARY_RECORD_SIZE: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MarketAction<P = Price, Q = Quantity> {
+    Add { order_id: OrderId, side: Side, price: P, quantity: Q },
+    Modify { order_id: OrderId, side: Side, price: P, quantity: Q },
+    Market { order_id: OrderId, side: Side, quantity: Q },
+    Cancel { order_id: OrderId },
+}
+
+impl<P: PriceType, Q: QuantityType> MarketAction<P, Q> {
+    // plays the recorded action into `book` and returns what traded
+    pub fn apply(self, book: &Orderbook<P, Q>) -> Vec<Trade<P, Q>> {
+        match self {
+            MarketAction::Add { order_id, side, price, quantity } => {
+                book.add_order(Order::new(OrderType::GoodTillCancel, order_id, side, price, quantity))
+            }
+            MarketAction::Modify { order_id, side, price, quantity } => {
+                match book.modify_order(OrderModify::new(order_id, side, price, quantity)) {
+                    ModifyOutcome::AmendedInPlace(trades)
+                    | ModifyOutcome::Replaced(trades)
+                    | ModifyOutcome::Cancelled(trades) => trades,
+                    // the recorded order may already have traded away, e.g. with a backtest's orders
+                    ModifyOutcome::Rejected(_) => vec![],
+                }
+            }
+            MarketAction::Market { order_id, side, quantity } => book.add_order(Order::new_market(order_id, side, quantity)),
+            MarketAction::Cancel { order_id } => book.cancel_order(order_id),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MarketEvent<P = Price, Q = Quantity> {
+    pub timestamp: Timestamp,
+    pub action: MarketAction<P, Q>,
+}
+
+fn invalid(line: usize, message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line, message))
+}
+
+fn parse<T: FromStr>(line: usize, name: &str, value: &str) -> io::Result<T> {
+    value.trim().parse().map_err(|_| invalid(line, format!("bad {} {:?}", name, value)))
+}
+
+fn parse_side(line: usize, value: &str) -> io::Result<Side> {
+    match value.trim() {
+        "buy" | "b" | "B" => Ok(Side::Buy),
+        "sell" | "s" | "S" => Ok(Side::Sell),
+        other => Err(invalid(line, format!("bad side {:?}", other))),
+    }
+}
+
+pub fn read_csv<P, Q>(reader: impl BufRead) -> io::Result<Vec<MarketEvent<P, Q>>>
+where
+    P: PriceType + FromStr,
+    Q: QuantityType + FromStr,
+{
+    let mut events = vec![];
+    for (index, line) in reader.lines().enumerate().skip(1) {
+        let line_number = index + 1;
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != 6 {
+            return Err(invalid(line_number, format!("expected 6 fields, got {}", fields.len())));
+        }
+        let timestamp = parse(line_number, "timestamp", fields[0])?;
+        let order_id = parse(line_number, "order id", fields[2])?;
+        let action = match fields[1].trim() {
+            "add" => MarketAction::Add {
+                order_id,
+                side: parse_side(line_number, fields[3])?,
+                price: parse(line_number, "price", fields[4])?,
+                quantity: parse(line_number, "quantity", fields[5])?,
+            },
+            "modify" => MarketAction::Modify {
+                order_id,
+                side: parse_side(line_number, fields[3])?,
+                price: parse(line_number, "price", fields[4])?,
+                quantity: parse(line_number, "quantity", fields[5])?,
+            },
+            "market" => MarketAction::Market {
+                order_id,
+                side: parse_side(line_number, fields[3])?,
+                quantity: parse(line_number, "quantity", fields[5])?,
+            },
+            "cancel" => MarketAction::Cancel { order_id },
+            other => return Err(invalid(line_number, format!("unknown action {:?}", other))),
+        };
+        events.push(MarketEvent { timestamp, action });
+    }
+    Ok(events)
+}
+
+// Layout: timestamp u64, action u8 (0 add, 1 modify, 2 market, 3 cancel),
+// side u8 (0 buy, 1 sell), 2 bytes padding, order id u32, price i64 in half ticks, quantity u64.
+pub fn write_binary(writer: &mut impl Write, events: &[MarketEvent]) -> io::Result<()> {
+    for event in events {
+        let (action, order_id, side, price, quantity) = match event.action {
+            MarketAction::Add { order_id, side, price, quantity } => (0u8, order_id, side, price, quantity),
+            MarketAction::Modify { order_id, side, price, quantity } => (1, order_id, side, price, quantity),
+            MarketAction::Market { order_id, side, quantity } => (2, order_id, side, Price::zero(), quantity),
+            MarketAction::Cancel { order_id } => (3, order_id, Side::Buy, Price::zero(), 0),
+        };
+        let mut record = [0u8; BINARY_RECORD_SIZE];
+        record[0..8].copy_from_slice(&event.timestamp.to_le_bytes());
+        record[8] = action;
+        record[9] = if side == Side::Buy { 0 } else { 1 };
+        record[12..16].copy_from_slice(&order_id.to_le_bytes());
+        record[16..24].copy_from_slice(&price.half_ticks().to_le_bytes());
+        record[24..32].copy_from_slice(&quantity.to_le_bytes());
+        writer.write_all(&record)?;
+    }
+    Ok(())
+}
+
+pub fn read_binary(reader: &mut impl Read) -> io::Result<Vec<MarketEvent>> {
+    let mut events = vec![];
+    let mut record = [0u8; BINARY_RECORD_SIZE];
+    let mut index = 0;
+    loop {
+        // the file may only end between records
+        let mut filled = 0;
+        while filled < BINARY_RECORD_SIZE {
+            match reader.read(&mut record[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+        index += 1;
+        match filled {
+            0 => break,
+            BINARY_RECORD_SIZE => {}
+            _ => return Err(invalid(index, format!("record cut short at {} bytes", filled))),
+        }
+        let word = |start: usize| u64::from_le_bytes(record[start..start + 8].try_into().unwrap());
+        let order_id = u32::from_le_bytes(record[12..16].try_into().unwrap());
+        let side = match record[9] {
+            0 => Side::Buy,
+            1 => Side::Sell,
+            other => return Err(invalid(index, format!("bad side {}", other))),
+        };
+        let (price, quantity) = (Price::from_half_ticks(word(16) as i64), word(24) as Quantity);
+        let action = match record[8] {
+            0 => MarketAction::Add { order_id, side, price, quantity },
+            1 => MarketAction::Modify { order_id, side, price, quantity },
+            2 => MarketAction::Market { order_id, side, quantity },
+            3 => MarketAction::Cancel { order_id },
+            other => return Err(invalid(index, format!("unknown action {}", other))),
+        };
+        events.push(MarketEvent { timestamp: word(0), action });
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_csv_and_binary_agree(){
+        let csv = "timestamp,action,order_id,side,price,quantity\n\
+                   1000,add,1,buy,100,10\n\
+                   1005,modify,1,buy,101,10\n\
+                   1010,market,2,sell,,5\n\
+                   \n\
+                   1020,cancel,1,,,\n";
+        let events: Vec<MarketEvent> = read_csv(csv.as_bytes()).unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[2], MarketEvent { timestamp: 1010, action: MarketAction::Market { order_id: 2, side: Side::Sell, quantity: 5 } });
+
+        let mut capture = vec![];
+        write_binary(&mut capture, &events).unwrap();
+        assert_eq!(capture.len(), 4 * BINARY_RECORD_SIZE);
+        assert_eq!(read_binary(&mut &capture[..]).unwrap(), events);
+
+        // a capture cut off partway through a record is an error, not a short replay
+        let truncated = &capture[..capture.len() - 5];
+        assert_eq!(read_binary(&mut &truncated[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(read_binary(&mut &capture[..0]).unwrap().is_empty());
+
+        let error = read_csv::<Price, Quantity>("header\n1000,add,1,up,100,10\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: bad side \"up\"");
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::mpsc::{Receiver, Sender},
+};
+use orderbook::fees::{FeeEngine, Liquidity};
+use orderbook::orderbook::{ModifyOutcome, Order, OrderModify, OrderType, Orderbook, Price, Quantity, Side, Symbol, Trade};
+use orderbook::price::{PriceType, QuantityType};
+use orderbook::risk::AccountId;
+use crate::{ExecutionReport, Fill, OrderId, OrderRequest, RejectReason};
+
+pub type Reports<P = Price, Q = Quantity> = VecDeque<ExecutionReport<P, Q>>;
+
+// Where a strategy's orders go. Reports the destination answers with straight
+// away are pushed onto `reports`; ones that come later are picked up by `poll`.
+pub trait OrderRouter<P = Price, Q = Quantity> {
+    fn route(&mut self, request: OrderRequest<P, Q>, reports: &mut Reports<P, Q>);
+
+    fn poll(&mut self, _reports: &mut Reports<P, Q>) {}
+
+    // fills for our resting orders in trades someone else's order caused
+    fn on_market_trades(&mut self, _trades: &[Trade<P, Q>], _reports: &mut Reports<P, Q>) {}
+}
+
+#[derive(Debug)]
+struct OwnOrder<Q> {
+    quantity: Q,
+    filled: Q,
+}
+
+#[derive(Debug)]
+struct Fees {
+    engine: FeeEngine,
+    account: AccountId,
+    symbol: Symbol,
+}
+
+// Simulation: orders go straight into a local book, which answers synchronously.
+// Fills are free unless a fee engine is set.
+#[derive(Debug)]
+pub struct BookRouter<P = Price, Q = Quantity> {
+    book: Orderbook<P, Q>,
+    orders: HashMap<OrderId, OwnOrder<Q>>,
+    fees: Option<Fees>,
+}
+
+impl<P: PriceType, Q: QuantityType> BookRouter<P, Q> {
+    pub fn new(book: Orderbook<P, Q>) -> Self {
+        Self { book, orders: HashMap::new(), fees: None }
+    }
+
+    // charges our fills as `account` trading `symbol`
+    pub fn set_fees(&mut self, engine: FeeEngine, account: AccountId, symbol: Symbol) {
+        self.fees = Some(Fees { engine, account, symbol });
+    }
+
+    pub fn get_fee_engine(&self) -> Option<&FeeEngine> {
+        self.fees.as_ref().map(|fees| &fees.engine)
+    }
+
+    // the book, for feeding it everyone else's orders
+    pub const fn get_book(&self) -> &Orderbook<P, Q> {
+        &self.book
+    }
+
+    fn fills(&mut self, trades: &[Trade<P, Q>], reports: &mut Reports<P, Q>) {
+        for trade in trades {
+            for (side, info) in [(Side::Buy, trade.get_bid_trade()), (Side::Sell, trade.get_ask_trade())] {
+                if let Some(order) = self.orders.get_mut(&info.order_id) {
+                    order.filled += info.quantity;
+                    let leaves = order.quantity - order.filled;
+                    let (liquidity, fee) = match &mut self.fees {
+                        Some(fees) => {
+                            let charge = fees.engine.charge_trade(fees.account, &fees.symbol, trade, side);
+                            (charge.liquidity, charge.amount)
+                        }
+                        None => (Liquidity::of(trade, side), 0.0),
+                    };
+                    reports.push_back(ExecutionReport::Filled(Fill {
+                        order_id: info.order_id,
+                        side,
+                        price: trade.get_price(),
+                        quantity: info.quantity,
+                        leaves,
+                        liquidity,
+                        fee,
+                    }));
+                }
+            }
+        }
+    }
+
+    // forgets orders the book no longer holds
+    fn forget_gone(&mut self) {
+        let book = &self.book;
+        self.orders.retain(|order_id, _| book.contains(*order_id));
+    }
+}
+
+impl<P: PriceType, Q: QuantityType> OrderRouter<P, Q> for BookRouter<P, Q> {
+    fn route(&mut self, request: OrderRequest<P, Q>, reports: &mut Reports<P, Q>) {
+        match request {
+            OrderRequest::New { order_id, side, order_type, price, quantity } => {
+                let order = match (order_type, price) {
+                    (OrderType::Market, _) => Order::new_market(order_id, side, quantity),
+                    (_, Some(price)) => Order::new(order_type, order_id, side, price, quantity),
+                    (_, None) => {
+                        reports.push_back(ExecutionReport::Rejected(order_id, RejectReason::Refused));
+                        return;
+                    }
+                };
+                // an id someone else's order already has on the book would be refused
+                // there, and a later cancel would pull their order
+                if self.orders.contains_key(&order_id) || self.book.contains(order_id) {
+                    reports.push_back(ExecutionReport::Rejected(order_id, RejectReason::Refused));
+                    return;
+                }
+                self.orders.insert(order_id, OwnOrder { quantity, filled: Q::zero() });
+                let trades = self.book.add_order(order);
+                let traded = trades.iter().any(|trade| trade.get_bid_trade().order_id == order_id || trade.get_ask_trade().order_id == order_id);
+                if !traded && !self.book.contains(order_id) {
+                    self.orders.remove(&order_id);
+                    reports.push_back(ExecutionReport::Rejected(order_id, RejectReason::Refused));
+                    return;
+                }
+                reports.push_back(ExecutionReport::Acked(order_id));
+                self.fills(&trades, reports);
+                let unfilled = self.orders.get(&order_id).is_some_and(|order| order.filled < order.quantity);
+                if unfilled && !self.book.contains(order_id) {
+                    reports.push_back(ExecutionReport::Cancelled(order_id));
+                }
+            }
+            OrderRequest::Cancel { order_id } => {
+                if !self.orders.contains_key(&order_id) || !self.book.contains(order_id) {
+                    reports.push_back(ExecutionReport::Rejected(order_id, RejectReason::UnknownOrder));
+                    return;
+                }
+                let trades = self.book.cancel_order(order_id);
+                reports.push_back(ExecutionReport::Cancelled(order_id));
+                self.fills(&trades, reports);
+            }
+            OrderRequest::Replace { order_id, side, price, quantity } => {
+                if !self.orders.contains_key(&order_id) {
+                    reports.push_back(ExecutionReport::Rejected(order_id, RejectReason::UnknownOrder));
+                    return;
+                }
+                match self.book.modify_order(OrderModify::new(order_id, side, price, quantity)) {
+                    ModifyOutcome::AmendedInPlace(trades) | ModifyOutcome::Replaced(trades) => {
+                        if let Some(order) = self.orders.get_mut(&order_id) {
+                            order.quantity = quantity;
+                        }
+                        reports.push_back(ExecutionReport::Replaced(order_id));
+                        self.fills(&trades, reports);
+                    }
+                    ModifyOutcome::Cancelled(trades) => {
+                        reports.push_back(ExecutionReport::Cancelled(order_id));
+                        self.fills(&trades, reports);
+                    }
+                    ModifyOutcome::Rejected(reject) => {
+                        reports.push_back(ExecutionReport::Rejected(order_id, RejectReason::Modify(reject)));
+                    }
+                }
+            }
+        }
+        self.forget_gone();
+    }
+
+    fn on_market_trades(&mut self, trades: &[Trade<P, Q>], reports: &mut Reports<P, Q>) {
+        self.fills(trades, reports);
+        self.forget_gone();
+    }
+}
+
+// Live: requests go down a channel to the gateway's connection thread, and
+// reports come back up another as the venue sends them.
+#[derive(Debug)]
+pub struct GatewayRouter<P = Price, Q = Quantity> {
+    requests: Sender<OrderRequest<P, Q>>,
+    reports: Receiver<ExecutionReport<P, Q>>,
+}
+
+impl<P: PriceType, Q: QuantityType> GatewayRouter<P, Q> {
+    pub fn new(requests: Sender<OrderRequest<P, Q>>, reports: Receiver<ExecutionReport<P, Q>>) -> Self {
+        Self { requests, reports }
+    }
+}
+
+impl<P: PriceType, Q: QuantityType> OrderRouter<P, Q> for GatewayRouter<P, Q> {
+    fn route(&mut self, request: OrderRequest<P, Q>, reports: &mut Reports<P, Q>) {
+        if self.requests.send(request).is_err() {
+            let order_id = match request {
+                OrderRequest::New { order_id, .. } | OrderRequest::Cancel { order_id } | OrderRequest::Replace { order_id, .. } => order_id,
+            };
+            reports.push_back(ExecutionReport::Rejected(order_id, RejectReason::Disconnected));
+        }
+    }
+
+    fn poll(&mut self, reports: &mut Reports<P, Q>) {
+        // a dropped sender just means nothing more is coming
+        while let Ok(report) = self.reports.try_recv() {
+            reports.push_back(report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_book_router_reports(){
+        let mut router = BookRouter::new(Orderbook::new(BTreeMap::new(), BTreeMap::new()));
+        let mut reports = Reports::new();
+        router.get_book().add_order(Order::new(OrderType::GoodTillCancel, 100, Side::Sell, 101, 3));
+
+        // crosses for 3, the fill and kill rest is cancelled
+        router.route(OrderRequest::New { order_id: 1, side: Side::Buy, order_type: OrderType::FillAndKill, price: Some(Price::from(101)), quantity: 5 }, &mut reports);
+        assert_eq!(reports.drain(..).collect::<Vec<_>>(), vec![
+            ExecutionReport::Acked(1),
+            ExecutionReport::Filled(Fill { order_id: 1, side: Side::Buy, price: Price::from(101), quantity: 3, leaves: 2, liquidity: Liquidity::Taker, fee: 0.0 }),
+            ExecutionReport::Cancelled(1),
+        ]);
+
+        router.route(OrderRequest::New { order_id: 2, side: Side::Buy, order_type: OrderType::GoodTillCancel, price: Some(Price::from(100)), quantity: 5 }, &mut reports);
+        router.route(OrderRequest::Replace { order_id: 2, side: Side::Buy, price: Price::from(99), quantity: 6 }, &mut reports);
+        let trades = router.get_book().add_order(Order::new(OrderType::GoodTillCancel, 101, Side::Sell, 99, 2));
+        router.on_market_trades(&trades, &mut reports);
+        router.route(OrderRequest::Cancel { order_id: 2 }, &mut reports);
+        router.route(OrderRequest::Cancel { order_id: 2 }, &mut reports);
+        assert_eq!(reports.drain(..).collect::<Vec<_>>(), vec![
+            ExecutionReport::Acked(2),
+            ExecutionReport::Replaced(2),
+            ExecutionReport::Filled(Fill { order_id: 2, side: Side::Buy, price: Price::from(99), quantity: 2, leaves: 4, liquidity: Liquidity::Maker, fee: 0.0 }),
+            ExecutionReport::Cancelled(2),
+            ExecutionReport::Rejected(2, RejectReason::UnknownOrder),
+        ]);
+    }
+
+    #[test]
+    fn test_book_router_refuses_an_id_on_the_book(){
+        let mut router = BookRouter::new(Orderbook::new(BTreeMap::new(), BTreeMap::new()));
+        let mut reports = Reports::new();
+        router.get_book().add_order(Order::new(OrderType::GoodTillCancel, 7, Side::Sell, 101, 3));
+
+        router.route(OrderRequest::New { order_id: 7, side: Side::Buy, order_type: OrderType::GoodTillCancel, price: Some(Price::from(100)), quantity: 5 }, &mut reports);
+        router.route(OrderRequest::Cancel { order_id: 7 }, &mut reports);
+        assert_eq!(reports.drain(..).collect::<Vec<_>>(), vec![
+            ExecutionReport::Rejected(7, RejectReason::Refused),
+            ExecutionReport::Rejected(7, RejectReason::UnknownOrder),
+        ]);
+        assert!(router.get_book().contains(7));
+    }
+
+    #[test]
+    fn test_gateway_router_uses_channels(){
+        let (request_sender, request_receiver) = mpsc::channel();
+        let (report_sender, report_receiver) = mpsc::channel();
+        let mut router: GatewayRouter = GatewayRouter::new(request_sender, report_receiver);
+        let mut reports = Reports::new();
+
+        router.route(OrderRequest::Cancel { order_id: 4 }, &mut reports);
+        assert!(reports.is_empty());
+        assert_eq!(request_receiver.recv().unwrap(), OrderRequest::Cancel { order_id: 4 });
+
+        report_sender.send(ExecutionReport::Cancelled(4)).unwrap();
+        router.poll(&mut reports);
+        assert_eq!(reports.pop_front(), Some(ExecutionReport::Cancelled(4)));
+
+        drop(request_receiver);
+        router.route(OrderRequest::Cancel { order_id: 5 }, &mut reports);
+        assert_eq!(reports.pop_front(), Some(ExecutionReport::Rejected(5, RejectReason::Disconnected)));
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+};
+use orderbook::orderbook::{OrderType, Price, Quantity, Side, Trade};
+use orderbook::price::{PriceType, QuantityType};
+use orderbook::stats::Timestamp;
+use crate::router::{OrderRouter, Reports};
+use crate::{Context, ExecutionReport, MarketTrade, OrderId, OrderRequest, Strategy, TopOfBook};
+
+// The context a strategy sees while one of its callbacks runs.
+struct RunnerContext<'a, P, Q, R> {
+    router: &'a mut R,
+    reports: &'a mut Reports<P, Q>,
+    timers: &'a mut BinaryHeap<Reverse<Timestamp>>,
+    next_order_id: &'a mut OrderId,
+    now: Timestamp,
+}
+
+impl<P: PriceType, Q: QuantityType, R: OrderRouter<P, Q>> Context<P, Q> for RunnerContext<'_, P, Q, R> {
+    fn now(&self) -> Timestamp {
+        self.now
+    }
+
+    fn send_order(&mut self, side: Side, order_type: OrderType, price: Option<P>, quantity: Q) -> OrderId {
+        let order_id = *self.next_order_id;
+        *self.next_order_id += 1;
+        self.router.route(OrderRequest::New { order_id, side, order_type, price, quantity }, self.reports);
+        order_id
+    }
+
+    fn cancel_order(&mut self, order_id: OrderId) {
+        self.router.route(OrderRequest::Cancel { order_id }, self.reports);
+    }
+
+    fn replace_order(&mut self, order_id: OrderId, side: Side, price: P, quantity: Q) {
+        self.router.route(OrderRequest::Replace { order_id, side, price, quantity }, self.reports);
+    }
+
+    fn set_timer(&mut self, at: Timestamp) {
+        self.timers.push(Reverse(at));
+    }
+}
+
+// Drives one strategy: feeds it market data and timers, and hands each report
+// on its orders to the matching callback. The clock only moves when told to, so
+// a backtest can run it on simulated time and a live loop on the wall clock.
+#[derive(Debug)]
+pub struct StrategyRunner<S, R, P = Price, Q = Quantity> {
+    strategy: S,
+    router: R,
+    reports: Reports<P, Q>,
+    timers: BinaryHeap<Reverse<Timestamp>>,
+    next_order_id: OrderId,
+    now: Timestamp,
+}
+
+impl<P: PriceType, Q: QuantityType, S: Strategy<P, Q>, R: OrderRouter<P, Q>> StrategyRunner<S, R, P, Q> {
+    // order ids are handed out from `first_order_id` up, so give each runner on a book its own range
+    pub fn new(strategy: S, router: R, first_order_id: OrderId) -> Self {
+        Self {
+            strategy,
+            router,
+            reports: Reports::new(),
+            timers: BinaryHeap::new(),
+            next_order_id: first_order_id,
+            now: 0,
+        }
+    }
+
+    pub const fn get_strategy(&self) -> &S {
+        &self.strategy
+    }
+    pub const fn get_router(&self) -> &R {
+        &self.router
+    }
+    pub fn get_router_mut(&mut self) -> &mut R {
+        &mut self.router
+    }
+    pub const fn now(&self) -> Timestamp {
+        self.now
+    }
+
+    pub fn start(&mut self, now: Timestamp) {
+        self.now = now;
+        self.dispatch(|strategy, ctx| strategy.on_start(ctx));
+    }
+
+    // Moves the clock forward, firing timers that come due on the way in order.
+    pub fn set_time(&mut self, now: Timestamp) {
+        while let Some(Reverse(at)) = self.timers.peek().copied() {
+            if at > now {
+                break;
+            }
+            self.timers.pop();
+            self.now = self.now.max(at);
+            self.dispatch(|strategy, ctx| strategy.on_timer(ctx, at));
+        }
+        self.now = self.now.max(now);
+    }
+
+    pub fn on_book(&mut self, book: &TopOfBook<P, Q>) {
+        self.dispatch(|strategy, ctx| strategy.on_book(ctx, book));
+    }
+
+    // Trades from the market. Any that filled our resting orders are reported first.
+    pub fn on_trades(&mut self, trades: &[Trade<P, Q>]) {
+        self.router.on_market_trades(trades, &mut self.reports);
+        self.dispatch(|_, _| {});
+        for trade in trades {
+            let trade = MarketTrade {
+                price: trade.get_price(),
+                quantity: trade.get_quantity(),
+                bid_order_id: trade.get_bid_trade().order_id,
+                ask_order_id: trade.get_ask_trade().order_id,
+            };
+            self.dispatch(|strategy, ctx| strategy.on_trade(ctx, &trade));
+        }
+    }
+
+    // Calls into the strategy from outside its callbacks, with a context so it
+    // can act on its orders: pausing an execution algo, say.
+    pub fn control(&mut self, command: impl FnOnce(&mut S, &mut dyn Context<P, Q>)) {
+        self.dispatch(command);
+    }
+
+    // picks up reports a live router received since the last call
+    pub fn poll(&mut self) {
+        self.router.poll(&mut self.reports);
+        self.dispatch(|_, _| {});
+    }
+
+    // Runs `callback`, then the callbacks for every report it (or they) caused.
+    fn dispatch(&mut self, callback: impl FnOnce(&mut S, &mut dyn Context<P, Q>)) {
+        let mut ctx = RunnerContext {
+            router: &mut self.router,
+            reports: &mut self.reports,
+            timers: &mut self.timers,
+            next_order_id: &mut self.next_order_id,
+            now: self.now,
+        };
+        callback(&mut self.strategy, &mut ctx);
+        while let Some(report) = ctx.reports.pop_front() {
+            match report {
+                ExecutionReport::Acked(order_id) | ExecutionReport::Replaced(order_id) => self.strategy.on_ack(&mut ctx, order_id),
+                ExecutionReport::Filled(fill) => self.strategy.on_fill(&mut ctx, &fill),
+                ExecutionReport::Cancelled(order_id) => self.strategy.on_cancel(&mut ctx, order_id),
+                ExecutionReport::Rejected(order_id, reason) => self.strategy.on_reject(&mut ctx, order_id, reason),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+    use orderbook::orderbook::{Order, Orderbook};
+    use crate::market_making::MarketMaker;
+    use crate::router::BookRouter;
+
+    #[test]
+    fn test_market_maker_quotes_and_skews(){
+        let router = BookRouter::new(Orderbook::new(BTreeMap::new(), BTreeMap::new()));
+        let mut runner = StrategyRunner::new(MarketMaker::new(2.0, 5, 10, 0.5), router, 1_000);
+        runner.start(0);
+        let book = runner.get_router().get_book();
+        book.add_order(Order::new(OrderType::GoodTillCancel, 1, Side::Buy, 95, 10));
+        book.add_order(Order::new(OrderType::GoodTillCancel, 2, Side::Sell, 105, 10));
+        let top = TopOfBook::of(book);
+
+        runner.on_book(&top);
+        let quotes = runner.get_strategy().quotes();
+        assert_eq!(quotes, (Some(Price::from(98)), Some(Price::from(102))));
+
+        // someone sells into our bid. The mid is now 98.5 (the 95 bid against our own
+        // 102 ask) and being long 5 leans both quotes a further 2.5 lower
+        let trades = runner.get_router().get_book().add_order(Order::new(OrderType::GoodTillCancel, 3, Side::Sell, 98, 5));
+        runner.on_trades(&trades);
+        assert_eq!(runner.get_strategy().position(), 5.0);
+        let top = TopOfBook::of(runner.get_router().get_book());
+        runner.on_book(&top);
+        assert_eq!(runner.get_strategy().quotes(), (Some(Price::from(94)), Some(Price::from(98))));
+        assert_eq!(runner.get_router().get_book().best_ask(), Some(Price::from(98)));
+    }
+}
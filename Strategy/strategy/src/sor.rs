@@ -0,0 +1,314 @@
+use std::cmp::Ordering;
+use orderbook::fees::{FeeEngine, FeeSchedule, Liquidity};
+use orderbook::orderbook::{Order, OrderType, Orderbook, Price, Quantity, Side, Symbol};
+use orderbook::price::{PriceType, QuantityType};
+use orderbook::risk::AccountId;
+use tracing::debug;
+use crate::OrderId;
+
+pub const DEFAULT_MAX_ROUNDS: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RouteMode {
+    // one child to every venue in the plan at once
+    Spray,
+    // one venue at a time, best first, planning again after each
+    Sequential,
+}
+
+#[derive(Debug)]
+pub struct Venue<P = Price, Q = Quantity> {
+    name: String,
+    book: Orderbook<P, Q>,
+    // our fees there; the router's children always take
+    fees: FeeEngine,
+}
+
+impl<P: PriceType, Q: QuantityType> Venue<P, Q> {
+    pub const fn get_name(&self) -> &String {
+        &self.name
+    }
+    pub const fn get_book(&self) -> &Orderbook<P, Q> {
+        &self.book
+    }
+    pub const fn get_fees(&self) -> &FeeEngine {
+        &self.fees
+    }
+}
+
+// One child order and how it went, kept for audit.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RoutingDecision<P = Price, Q = Quantity> {
+    pub round: usize,
+    pub venue: String,
+    pub order_id: OrderId,
+    // limit of the child: the worst level it was planned to reach
+    pub price: P,
+    pub quantity: Q,
+    // fee inclusive price per unit the plan expected
+    pub expected_price: f64,
+    pub filled: Q,
+    pub notional: f64,
+    pub fees: f64,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RouteReport<P = Price, Q = Quantity> {
+    pub decisions: Vec<RoutingDecision<P, Q>>,
+    pub filled: Q,
+    pub notional: f64,
+    pub fees: f64,
+    // no venue had liquidity left for it inside the limit
+    pub unfilled: Q,
+}
+
+impl<P: PriceType, Q: QuantityType> RouteReport<P, Q> {
+    // average price paid or received per unit with fees counted against us
+    pub fn all_in_price(&self, side: Side) -> Option<f64> {
+        if self.filled == Q::zero() {
+            return None;
+        }
+        let fees = match side {
+            Side::Buy => self.fees,
+            Side::Sell => -self.fees,
+        };
+        Some((self.notional + fees) / self.filled.to_f64())
+    }
+}
+
+#[derive(Debug)]
+struct Allocation<P, Q> {
+    venue: usize,
+    quantity: Q,
+    worst_price: P,
+    // fee inclusive
+    cost: f64,
+}
+
+// Smart order router over books for the same instrument on different venues.
+// A parent order is split across the venues' displayed depth best fee inclusive
+// price first, and sent as immediate children. Whatever a venue doesn't fill is
+// planned again over what's left, leaving out venues that came up short since
+// their displayed depth can't be trusted for the rest of this order.
+#[derive(Debug)]
+pub struct SmartOrderRouter<P = Price, Q = Quantity> {
+    symbol: Symbol,
+    // who we trade as, for the venues' fee tiers
+    account: AccountId,
+    venues: Vec<Venue<P, Q>>,
+    mode: RouteMode,
+    max_rounds: usize,
+    next_order_id: OrderId,
+}
+
+impl<P: PriceType, Q: QuantityType> SmartOrderRouter<P, Q> {
+    pub fn new(symbol: Symbol, account: AccountId, mode: RouteMode, first_order_id: OrderId) -> Self {
+        Self { symbol, account, venues: vec![], mode, max_rounds: DEFAULT_MAX_ROUNDS, next_order_id: first_order_id }
+    }
+
+    pub fn add_venue(&mut self, name: impl Into<String>, book: Orderbook<P, Q>, fees: FeeSchedule) {
+        self.venues.push(Venue { name: name.into(), book, fees: FeeEngine::new(fees) });
+    }
+
+    pub fn get_venue(&self, name: &str) -> Option<&Venue<P, Q>> {
+        self.venues.iter().find(|venue| venue.name == name)
+    }
+
+    pub fn set_mode(&mut self, mode: RouteMode) {
+        self.mode = mode;
+    }
+
+    // every plan-and-send is a round; sequential routing uses one per venue visited
+    pub fn set_max_rounds(&mut self, max_rounds: usize) {
+        self.max_rounds = max_rounds;
+    }
+
+    // Splits `quantity` over the venues' contra levels no worse than `limit`,
+    // cheapest first once each venue's fee is added.
+    fn plan(&self, side: Side, quantity: Q, limit: Option<P>, excluded: &[bool]) -> Vec<Allocation<P, Q>> {
+        let mut levels = vec![];
+        for (index, venue) in self.venues.iter().enumerate().filter(|(index, _)| !excluded[*index]) {
+            let infos = venue.book.get_order_infos();
+            let taker_fee = venue.fees.rate(self.account, &self.symbol, Liquidity::Taker);
+            let contra = match side {
+                Side::Buy => infos.get_asks(),
+                Side::Sell => infos.get_bids(),
+            };
+            for level in contra {
+                let inside = match (side, limit) {
+                    (_, None) => true,
+                    (Side::Buy, Some(limit)) => level.price <= limit,
+                    (Side::Sell, Some(limit)) => level.price >= limit,
+                };
+                if inside {
+                    let effective = match side {
+                        Side::Buy => level.price.to_f64() * (1.0 + taker_fee),
+                        Side::Sell => level.price.to_f64() * (1.0 - taker_fee),
+                    };
+                    levels.push((effective, index, level.price, level.quantity));
+                }
+            }
+        }
+        // best first: cheapest to buy, dearest to sell
+        levels.sort_by(|a, b| {
+            let order = a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal);
+            if side == Side::Buy { order } else { order.reverse() }
+        });
+
+        let mut allocations: Vec<Allocation<P, Q>> = vec![];
+        let mut left = quantity;
+        for (effective, venue, price, level_quantity) in levels {
+            if left == Q::zero() {
+                break;
+            }
+            let take = left.min(level_quantity);
+            left -= take;
+            let cost = effective * take.to_f64();
+            match allocations.iter_mut().find(|allocation| allocation.venue == venue) {
+                Some(allocation) => {
+                    allocation.quantity += take;
+                    allocation.worst_price = price;
+                    allocation.cost += cost;
+                }
+                None => allocations.push(Allocation { venue, quantity: take, worst_price: price, cost }),
+            }
+        }
+        allocations
+    }
+
+    pub fn route(&mut self, side: Side, quantity: Q, limit: Option<P>) -> RouteReport<P, Q> {
+        let mut report = RouteReport { decisions: vec![], filled: Q::zero(), notional: 0.0, fees: 0.0, unfilled: quantity };
+        let mut excluded = vec![false; self.venues.len()];
+
+        for round in 0..self.max_rounds {
+            if report.unfilled == Q::zero() {
+                break;
+            }
+            let mut plan = self.plan(side, report.unfilled, limit, &excluded);
+            if plan.is_empty() {
+                break;
+            }
+            if self.mode == RouteMode::Sequential {
+                plan.truncate(1);
+            }
+            for allocation in plan {
+                let decision = self.send(round, side, &allocation);
+                if decision.filled < allocation.quantity {
+                    excluded[allocation.venue] = true;
+                }
+                report.filled += decision.filled;
+                report.unfilled -= decision.filled;
+                report.notional += decision.notional;
+                report.fees += decision.fees;
+                report.decisions.push(decision);
+            }
+        }
+        report
+    }
+
+    fn send(&mut self, round: usize, side: Side, allocation: &Allocation<P, Q>) -> RoutingDecision<P, Q> {
+        let venue = &mut self.venues[allocation.venue];
+        // the venue's own orders share the id space, so skip any it already holds
+        while venue.book.contains(self.next_order_id) {
+            self.next_order_id += 1;
+        }
+        let order_id = self.next_order_id;
+        self.next_order_id += 1;
+        let trades = venue.book.add_order(Order::new(OrderType::FillAndKill, order_id, side, allocation.worst_price, allocation.quantity));
+
+        let (mut filled, mut notional, mut fees) = (Q::zero(), 0.0, 0.0);
+        for trade in &trades {
+            let ours = match side {
+                Side::Buy => trade.get_bid_trade(),
+                Side::Sell => trade.get_ask_trade(),
+            };
+            if ours.order_id == order_id {
+                filled += ours.quantity;
+                notional += trade.get_price().to_f64() * ours.quantity.to_f64();
+                fees += venue.fees.charge_trade(self.account, &self.symbol, trade, side).amount;
+            }
+        }
+        let decision = RoutingDecision {
+            round,
+            venue: venue.name.clone(),
+            order_id,
+            price: allocation.worst_price,
+            quantity: allocation.quantity,
+            expected_price: allocation.cost / allocation.quantity.to_f64(),
+            filled,
+            notional,
+            fees,
+        };
+        debug!(round, venue = %decision.venue, order_id, price = ?decision.price, quantity = ?decision.quantity, filled = ?decision.filled, "routed");
+        decision
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn venue(orders: &[(u32, OrderType, i64, Quantity)]) -> Orderbook {
+        let book = Orderbook::new(BTreeMap::new(), BTreeMap::new());
+        for &(order_id, order_type, price, quantity) in orders {
+            book.add_order(Order::new(order_type, order_id, Side::Sell, price, quantity));
+        }
+        book
+    }
+
+    fn router(mode: RouteMode) -> SmartOrderRouter {
+        let mut router = SmartOrderRouter::new("ABC".to_string(), 7, mode, 1_000);
+        router.add_venue("cheap", venue(&[(1, OrderType::GoodTillCancel, 1000, 10), (2, OrderType::GoodTillCancel, 1001, 10)]), FeeSchedule::flat(0.0, 0.002));
+        router.add_venue("free", venue(&[(1, OrderType::GoodTillCancel, 1000, 10), (2, OrderType::GoodTillCancel, 1003, 10)]), FeeSchedule::flat(0.0, 0.0));
+        router
+    }
+
+    #[test]
+    fn test_spray_splits_after_fees(){
+        let mut router = router(RouteMode::Spray);
+        // 1000 at free, then 1002 all in at cheap, then 1003 at free beats 1003.002 at cheap
+        let report = router.route(Side::Buy, 25, None);
+        let sent: Vec<(&str, Price, Quantity)> = report.decisions.iter().map(|decision| (decision.venue.as_str(), decision.price, decision.filled)).collect();
+        assert_eq!(sent, vec![("free", Price::from(1003), 15), ("cheap", Price::from(1000), 10)]);
+        assert!(report.decisions.iter().all(|decision| decision.round == 0));
+        assert_eq!((report.filled, report.unfilled), (25, 0));
+        assert_eq!(report.notional, 25_015.0);
+        assert_eq!(report.fees, 20.0);
+        assert_eq!(report.all_in_price(Side::Buy), Some(1001.4));
+        assert_eq!(router.get_venue("cheap").unwrap().get_book().best_ask(), Some(Price::from(1001)));
+        assert_eq!(router.get_venue("cheap").unwrap().get_fees().get_fees(7), 20.0);
+    }
+
+    #[test]
+    fn test_sequential_reroutes_remainder(){
+        let mut router = router(RouteMode::Sequential);
+        // displayed at the best price, but only trades for all 20 at once
+        router.add_venue("aon", venue(&[(1, OrderType::AllOrNone, 990, 20)]), FeeSchedule::flat(0.0, 0.0));
+        let report = router.route(Side::Buy, 15, Some(Price::from(1000)));
+        let sent: Vec<(usize, &str, Quantity, Quantity)> = report.decisions.iter()
+            .map(|decision| (decision.round, decision.venue.as_str(), decision.quantity, decision.filled))
+            .collect();
+        assert_eq!(sent, vec![(0, "aon", 15, 0), (1, "free", 10, 10), (2, "cheap", 5, 5)]);
+        assert_eq!((report.filled, report.unfilled), (15, 0));
+        assert_eq!(router.get_venue("aon").unwrap().get_book().best_ask(), Some(Price::from(990)));
+
+        // nothing left inside the limit
+        let report = router.route(Side::Buy, 10, Some(Price::from(1000)));
+        assert_eq!((report.filled, report.unfilled), (5, 5));
+    }
+
+    #[test]
+    fn test_child_ids_skip_ids_on_the_venue(){
+        let mut router = router(RouteMode::Sequential);
+        router.add_venue("busy", venue(&[(1_000, OrderType::GoodTillCancel, 990, 5), (1_001, OrderType::GoodTillCancel, 995, 5)]), FeeSchedule::flat(0.0, 0.0));
+        let report = router.route(Side::Buy, 10, Some(Price::from(995)));
+        let sent: Vec<(OrderId, Quantity)> = report.decisions.iter().map(|decision| (decision.order_id, decision.filled)).collect();
+        assert_eq!(sent, vec![(1_002, 10)]);
+        assert!(router.get_venue("busy").unwrap().get_book().best_ask().is_none());
+
+        // ids the venue no longer holds are fine to use again
+        let report = router.route(Side::Buy, 5, None);
+        assert_eq!(report.decisions[0].order_id, 1_003);
+    }
+}